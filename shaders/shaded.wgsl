@@ -1,14 +1,17 @@
 // Uniform buffer containing the transform matrix
-struct Uniforms {
-    transform: mat4x4<f32>,
-    max_depth: f32,
-}
-
 struct Light {
     position: vec3<f32>,
     intensity: f32,
 }
 
+// Lights are defined in `render.rs`, and shared with CPU rendering
+struct Uniforms {
+    transform: mat4x4<f32>,
+    max_depth: f32,
+    ambient: f32,
+    lights: array<Light, 3>,
+}
+
 // Common data (shared with all images)
 @group(0) @binding(0) var t_ssao: texture_2d<f32>;
 @group(0) @binding(1) var s_ssao: sampler;
@@ -89,14 +92,9 @@ fn fs_main(
 
         let normal = vec3<f32>(pixel.yzw);
         let n = normalize(normal);
-        const LIGHTS = array<Light, 3>(
-            Light(vec3<f32>(5.0, -5.0, 10.0), 0.5),
-            Light(vec3<f32>(-5.0, 0.0, 10.0), 0.15),
-            Light(vec3<f32>(0.0, -5.0, 10.0), 0.15)
-        );
-        var accum: f32 = 0.2;
+        var accum: f32 = uniforms.ambient;
         for (var i = 0u; i < 3u; i = i + 1u) {
-            let light = LIGHTS[i];
+            let light = uniforms.lights[i];
             let light_dir = normalize(light.position - p);
             accum = accum + max(dot(light_dir, n), 0.0) * light.intensity;
         }
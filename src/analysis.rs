@@ -0,0 +1,325 @@
+//! Analysis window, which estimates mass properties of a tree output
+//!
+//! Mass properties are computed on a worker thread, because meshing a detailed
+//! shape can take a while.
+use crate::{
+    Message, MessageGenSender,
+    gui::WARN,
+    platform::Notify,
+    world::{self, BlockIndex, MassProperties, World},
+};
+use fidget::{context::Tree, shapes::types::Vec3};
+
+/// Persistent state for the analysis window
+pub struct AnalysisState {
+    pub open: bool,
+    /// Selected tree, as a block and output name
+    selected: Option<(BlockIndex, String)>,
+    /// Compute bounds from the shape, instead of using `lower` and `upper`
+    auto_bounds: bool,
+    lower: [f64; 3],
+    upper: [f64; 3],
+    feature_size: f64,
+    pending: Option<Pending>,
+    result: Option<Result<MassProperties, String>>,
+    next_id: u64,
+}
+
+impl Default for AnalysisState {
+    fn default() -> Self {
+        Self {
+            open: false,
+            selected: None,
+            auto_bounds: true,
+            lower: [-1.0; 3],
+            upper: [1.0; 3],
+            feature_size: 0.05,
+            pending: None,
+            result: None,
+            next_id: 0,
+        }
+    }
+}
+
+/// In-progress computation
+struct Pending {
+    id: u64,
+    cancel: fidget::render::CancelToken,
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.cancel.cancel()
+    }
+}
+
+impl AnalysisState {
+    /// Clears the selection and any results, e.g. when loading a new file
+    pub fn reset(&mut self) {
+        self.selected = None;
+        self.pending = None;
+        self.result = None;
+    }
+
+    /// Callback when a computation is complete
+    pub fn receive(&mut self, id: u64, result: Result<MassProperties, String>) {
+        if self.pending.as_ref().is_some_and(|p| p.id == id) {
+            self.pending = None;
+            self.result = Some(result);
+        }
+    }
+
+    /// Starts computing mass properties on a worker thread
+    fn start<N: Notify>(&mut self, tree: Tree, tx: &MessageGenSender<N>) {
+        let id = self.next_id;
+        self.next_id += 1;
+        let cancel = fidget::render::CancelToken::new();
+        let cancel_ = cancel.clone();
+        let v = |p: [f64; 3]| Vec3 {
+            x: p[0],
+            y: p[1],
+            z: p[2],
+        };
+        let bounds = if self.auto_bounds {
+            None
+        } else {
+            Some((v(self.lower), v(self.upper)))
+        };
+        let feature_size = self.feature_size;
+        let tx = tx.clone();
+        rayon::spawn(move || {
+            let result = compute(tree, bounds, feature_size, cancel_);
+            tx.send(Message::MassProperties { id, result })
+        });
+        self.pending = Some(Pending { id, cancel });
+        self.result = None;
+    }
+}
+
+/// Computes mass properties (off-thread)
+///
+/// If `bounds` is `None`, then they're computed from the shape and padded by
+/// the feature size.
+fn compute(
+    tree: Tree,
+    bounds: Option<(Vec3, Vec3)>,
+    feature_size: f64,
+    cancel: fidget::render::CancelToken,
+) -> Result<MassProperties, String> {
+    let (lower, upper) = match bounds {
+        Some(b) => b,
+        None => {
+            let (lo, hi) = world::bounds(&tree)
+                .map_err(|e| e.to_string())?
+                .ok_or("shape is empty")?;
+            let d = feature_size;
+            (
+                Vec3 {
+                    x: lo.x - d,
+                    y: lo.y - d,
+                    z: lo.z - d,
+                },
+                Vec3 {
+                    x: hi.x + d,
+                    y: hi.y + d,
+                    z: hi.z + d,
+                },
+            )
+        }
+    };
+    if ![lower.x, lower.y, lower.z, upper.x, upper.y, upper.z]
+        .iter()
+        .all(|v| v.is_finite())
+    {
+        return Err("shape is unbounded; set bounds manually".to_owned());
+    }
+    world::mass_properties(tree, lower, upper, feature_size, cancel)
+        .map_err(|e| format!("{:#}", anyhow::Error::from(e)))
+}
+
+/// Draws the analysis window
+pub(crate) fn show<N: Notify>(
+    ctx: &egui::Context,
+    state: &mut AnalysisState,
+    world: &World,
+    tx: &MessageGenSender<N>,
+) {
+    let mut open = state.open;
+    egui::Window::new("Mass properties")
+        .open(&mut open)
+        .default_width(360.0)
+        .show(ctx, |ui| {
+            let trees = world
+                .order
+                .iter()
+                .flat_map(|i| {
+                    let block = &world[*i];
+                    block.tree_outputs().into_iter().map(move |(name, t)| {
+                        let label = if name == block.name() {
+                            name.clone()
+                        } else {
+                            format!("{} ({name})", block.name())
+                        };
+                        ((*i, name), label, t)
+                    })
+                })
+                .collect::<Vec<_>>();
+            if trees.is_empty() {
+                ui.weak("No blocks have tree outputs");
+                return;
+            }
+            if !state
+                .selected
+                .as_ref()
+                .is_some_and(|s| trees.iter().any(|(k, ..)| k == s))
+            {
+                state.selected = Some(trees[0].0.clone());
+                state.result = None;
+            }
+            settings_ui(ui, state, &trees);
+
+            ui.separator();
+            ui.horizontal(|ui| {
+                if state.pending.is_some() {
+                    if ui.button("Cancel").clicked() {
+                        state.pending = None;
+                    }
+                    ui.spinner();
+                } else if ui.button("Compute").clicked() {
+                    let (_, _, tree) = trees
+                        .iter()
+                        .find(|(k, ..)| Some(k) == state.selected.as_ref())
+                        .unwrap();
+                    state.start(tree.clone(), tx);
+                }
+            });
+            match &state.result {
+                Some(Ok(m)) => results_ui(ui, m),
+                Some(Err(e)) => {
+                    ui.horizontal(|ui| {
+                        ui.label(
+                            egui::RichText::new(WARN)
+                                .color(ui.style().visuals.error_fg_color),
+                        );
+                        ui.label(e);
+                    });
+                }
+                None => (),
+            }
+        });
+    state.open = open;
+    if !open {
+        state.pending = None;
+    }
+}
+
+/// Draws the shape selector, bounds, and feature size
+///
+/// Changing any setting clears the previous result.
+fn settings_ui(
+    ui: &mut egui::Ui,
+    state: &mut AnalysisState,
+    trees: &[((BlockIndex, String), String, Tree)],
+) {
+    let mut changed = false;
+    egui::Grid::new("analysis_settings")
+        .num_columns(2)
+        .show(ui, |ui| {
+            ui.label("Shape");
+            let selected = state.selected.clone();
+            let text = trees
+                .iter()
+                .find(|(k, ..)| Some(k) == selected.as_ref())
+                .map(|(_, label, _)| label.as_str())
+                .unwrap_or_default();
+            egui::ComboBox::from_id_salt("analysis_shape")
+                .selected_text(text)
+                .show_ui(ui, |ui| {
+                    for (k, label, _) in trees {
+                        changed |= ui
+                            .selectable_value(
+                                &mut state.selected,
+                                Some(k.clone()),
+                                label,
+                            )
+                            .changed();
+                    }
+                });
+            ui.end_row();
+
+            ui.label("Bounds");
+            changed |= ui
+                .checkbox(&mut state.auto_bounds, "Automatic")
+                .on_hover_text(
+                    "Compute bounds from the shape, padded by the minimum \
+                     feature size",
+                )
+                .changed();
+            ui.end_row();
+            if !state.auto_bounds {
+                for (label, v) in
+                    [("Lower", &mut state.lower), ("Upper", &mut state.upper)]
+                {
+                    ui.label(label);
+                    ui.horizontal(|ui| {
+                        for (name, v) in ["x", "y", "z"].iter().zip(v) {
+                            changed |= ui
+                                .add(
+                                    egui::DragValue::new(v)
+                                        .speed(0.01)
+                                        .prefix(format!("{name}: ")),
+                                )
+                                .changed();
+                        }
+                    });
+                    ui.end_row();
+                }
+            }
+
+            ui.label("Min feature");
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut state.feature_size)
+                        .speed(0.001)
+                        .range(1e-4..=f64::INFINITY),
+                )
+                .changed();
+            ui.end_row();
+        });
+    if changed {
+        state.result = None;
+    }
+}
+
+/// Draws computed mass properties
+fn results_ui(ui: &mut egui::Ui, m: &MassProperties) {
+    egui::Grid::new("analysis_results")
+        .num_columns(2)
+        .striped(true)
+        .show(ui, |ui| {
+            ui.label("Volume");
+            ui.monospace(format!("{:.6}", m.volume));
+            ui.end_row();
+
+            ui.label("Surface area");
+            ui.monospace(format!("{:.6}", m.area));
+            ui.end_row();
+
+            ui.label("Center of mass");
+            let c = m.centroid;
+            ui.monospace(format!("[{:.6}, {:.6}, {:.6}]", c.x, c.y, c.z));
+            ui.end_row();
+
+            ui.label("Inertia tensor")
+                .on_hover_text("About the center of mass, with unit density");
+            ui.vertical(|ui| {
+                for row in &m.inertia {
+                    ui.monospace(format!(
+                        "[{:.6}, {:.6}, {:.6}]",
+                        row[0], row[1], row[2]
+                    ));
+                }
+            });
+            ui.end_row();
+        });
+}
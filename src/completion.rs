@@ -0,0 +1,424 @@
+//! Autocompletion and signature hints for the script editor
+//!
+//! Completions are drawn from local variables in the script, upstream block
+//! names, shape constructors, and functions registered in the script engine.
+//! Completion is keyboard-driven: the popup opens when typing a partial name
+//! (or when pressing ctrl+space), arrow keys pick a candidate, tab or enter
+//! accepts it, and escape dismisses the popup.  Typing a complete name doesn't
+//! open the popup, so that enter and tab keep their usual meaning.
+use std::collections::HashSet;
+
+use crate::{
+    docs::{EntryKind, Reference},
+    world::{Block, BlockIndex, World},
+};
+use egui::text::{CCursor, CCursorRange};
+
+/// Maximum number of candidates shown in the popup
+const MAX_CANDIDATES: usize = 12;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    Local,
+    Block,
+    Shape,
+    Function,
+}
+
+struct Candidate {
+    name: String,
+    kind: Kind,
+    /// Extra text drawn next to the name (e.g. a signature)
+    detail: String,
+}
+
+/// Returns candidates which start with (but aren't equal to) the given prefix
+///
+/// Candidates are ordered by kind (locals, then blocks, shapes, and
+/// functions), then alphabetically.  The returned flag is `true` if the
+/// prefix is itself a complete name.
+fn candidates(
+    reference: &Reference,
+    upstream: &[String],
+    script: &str,
+    prefix: &str,
+) -> (Vec<Candidate>, bool) {
+    let mut seen = HashSet::new();
+    let mut out = vec![];
+    let mut exact = false;
+    let mut push = |name: &str, kind: Kind, detail: String| {
+        if name == prefix {
+            exact = true;
+        } else if name.starts_with(prefix) && seen.insert(name.to_owned()) {
+            out.push(Candidate {
+                name: name.to_owned(),
+                kind,
+                detail,
+            });
+        }
+    };
+    let mut locals = locals(script);
+    locals.sort();
+    for name in &locals {
+        push(name, Kind::Local, "local".to_owned());
+    }
+    let mut upstream = upstream.iter().collect::<Vec<_>>();
+    upstream.sort();
+    for name in upstream {
+        push(name, Kind::Block, "block".to_owned());
+    }
+    for (kind, entry_kind) in [
+        (Kind::Shape, EntryKind::Shape),
+        (Kind::Function, EntryKind::Function),
+    ] {
+        for (name, e) in reference.iter().filter(|(_, e)| e.kind == entry_kind)
+        {
+            let detail = match e.signatures.as_slice() {
+                [] => String::new(),
+                [s] => s.clone(),
+                [s, rest @ ..] => format!("{s} (+{} more)", rest.len()),
+            };
+            push(name, kind, detail);
+        }
+    }
+    out.truncate(MAX_CANDIDATES);
+    (out, exact)
+}
+
+/// Returns the names of (non-note) blocks above the given block
+pub fn upstream_names(world: &World, index: BlockIndex) -> Vec<String> {
+    world
+        .order
+        .iter()
+        .take_while(|i| **i != index)
+        .map(|i| &world.blocks[i])
+        .filter(|b| !matches!(b, Block::Note(..)))
+        .map(|b| b.name().to_owned())
+        .collect()
+}
+
+/// Finds names defined with `let`, `const`, `fn`, or as loop variables
+///
+/// This is a lexical scan rather than a parse, so that it works on scripts
+/// which are being edited (and may not be valid).
+fn locals(script: &str) -> Vec<String> {
+    let mut words = script
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|w| !w.is_empty());
+    let mut out = vec![];
+    while let Some(w) = words.next() {
+        match w {
+            "let" | "const" | "fn" => out.extend(words.next()),
+            "for" => {
+                for n in words.by_ref() {
+                    if n == "in" {
+                        break;
+                    }
+                    out.push(n);
+                }
+            }
+            _ => (),
+        }
+    }
+    let mut seen = HashSet::new();
+    out.into_iter()
+        .filter(|n| rhai::is_valid_identifier(n) && seen.insert(*n))
+        .map(str::to_owned)
+        .collect()
+}
+
+/// Returns the name of the function whose argument list contains the end of
+/// the given text, if any
+fn callee(before: &[char]) -> Option<String> {
+    let mut depth = 0usize;
+    for i in (0..before.len()).rev() {
+        match before[i] {
+            ')' | ']' | '}' => depth += 1,
+            '(' | '[' | '{' if depth > 0 => depth -= 1,
+            '(' => {
+                let end = before[..i]
+                    .iter()
+                    .rposition(|c| !c.is_whitespace())
+                    .map_or(0, |j| j + 1);
+                let start = before[..end]
+                    .iter()
+                    .rposition(|c| !(c.is_alphanumeric() || *c == '_'))
+                    .map_or(0, |j| j + 1);
+                let name = before[start..end].iter().collect::<String>();
+                return rhai::is_valid_identifier(&name).then_some(name);
+            }
+            // Array and map literals may be function arguments
+            '[' => (),
+            '{' if i > 0 && before[i - 1] == '#' => (),
+            '{' | ';' => return None,
+            _ => (),
+        }
+    }
+    None
+}
+
+/// Converts a character index into a byte index
+fn byte_index(text: &str, i: usize) -> usize {
+    text.char_indices().nth(i).map_or(text.len(), |(b, _)| b)
+}
+
+/// State of the completion popup, stored in `egui` temporary memory
+#[derive(Clone)]
+struct Popup {
+    /// Character index of the start of the word being completed
+    start: usize,
+    items: Vec<String>,
+    selected: usize,
+    /// The popup was opened explicitly, so it includes every candidate
+    requested: bool,
+}
+
+/// Handles completion keys; this must be called before drawing the editor
+///
+/// Returns `true` if a completion was accepted (modifying the text).
+pub fn pre_edit(ui: &egui::Ui, id: egui::Id, text: &mut String) -> bool {
+    if !ui.memory(|m| m.has_focus(id)) {
+        return false;
+    }
+    if ui.input_mut(|i| i.consume_key(egui::Modifiers::CTRL, egui::Key::Space))
+    {
+        ui.data_mut(|d| {
+            d.remove_temp::<usize>(id.with("dismissed"));
+            d.insert_temp(id.with("requested"), true);
+        });
+        return false;
+    }
+    let popup_id = id.with("completion");
+    let Some(mut popup) = ui.data(|d| d.get_temp::<Popup>(popup_id)) else {
+        return false;
+    };
+    let (down, up, accept, escape) = ui.input_mut(|i| {
+        use egui::{Key, Modifiers};
+        (
+            i.consume_key(Modifiers::NONE, Key::ArrowDown),
+            i.consume_key(Modifiers::NONE, Key::ArrowUp),
+            i.consume_key(Modifiers::NONE, Key::Tab)
+                || i.consume_key(Modifiers::NONE, Key::Enter),
+            i.consume_key(Modifiers::NONE, Key::Escape),
+        )
+    });
+    let n = popup.items.len();
+    if escape {
+        ui.data_mut(|d| {
+            d.remove_temp::<Popup>(popup_id);
+            d.insert_temp(id.with("dismissed"), popup.start);
+        });
+        return false;
+    } else if down || up {
+        popup.selected = if down {
+            (popup.selected + 1) % n
+        } else {
+            (popup.selected + n - 1) % n
+        };
+        ui.data_mut(|d| d.insert_temp(popup_id, popup));
+        return false;
+    } else if !accept {
+        return false;
+    }
+
+    ui.data_mut(|d| d.remove_temp::<Popup>(popup_id));
+    let Some(mut state) = egui::TextEdit::load_state(ui.ctx(), id) else {
+        return false;
+    };
+    let Some(cursor) = state.cursor.char_range().map(|c| c.primary.index)
+    else {
+        return false;
+    };
+    if cursor < popup.start {
+        return false;
+    }
+    let item = &popup.items[popup.selected];
+    let a = byte_index(text, popup.start);
+    let b = byte_index(text, cursor);
+    text.replace_range(a..b, item);
+    let end = popup.start + item.chars().count();
+    state
+        .cursor
+        .set_char_range(Some(CCursorRange::one(CCursor::new(end))));
+    state.store(ui.ctx(), id);
+    true
+}
+
+/// Draws the completion popup and signature hints for the editor
+///
+/// This must be called after drawing the editor.
+pub fn post_edit(
+    ui: &egui::Ui,
+    id: egui::Id,
+    reference: &Reference,
+    upstream: &[String],
+    text: &str,
+    r: &egui::text_edit::TextEditOutput,
+) {
+    let popup_id = id.with("completion");
+    let cursor = r
+        .state
+        .cursor
+        .char_range()
+        .filter(|c| c.is_empty() && r.response.has_focus())
+        .map(|c| c.primary.index);
+    let Some(cursor) = cursor else {
+        ui.data_mut(|d| d.remove_temp::<Popup>(popup_id));
+        return;
+    };
+    let before = text.chars().take(cursor).collect::<Vec<_>>();
+    let start = before
+        .iter()
+        .rposition(|c| !(c.is_alphanumeric() || *c == '_'))
+        .map_or(0, |i| i + 1);
+    let prefix = before[start..].iter().collect::<String>();
+    let line = before[..start]
+        .iter()
+        .rev()
+        .take_while(|c| **c != '\n')
+        .collect::<String>();
+    let in_comment = line.contains("//");
+
+    let cursor_rect = r
+        .galley
+        .pos_from_cursor(CCursor::new(cursor))
+        .translate(r.galley_pos.to_vec2());
+    let mono = egui::TextStyle::Monospace.resolve(ui.style());
+
+    // Show the completion popup below the cursor.  It's opened by typing or
+    // by an explicit request, and stays open while editing the same word.
+    let prev = ui
+        .data(|d| d.get_temp::<Popup>(popup_id))
+        .filter(|p| p.start == start);
+    let requested = ui
+        .data_mut(|d| d.remove_temp::<bool>(id.with("requested")))
+        .unwrap_or(false)
+        || prev.as_ref().is_some_and(|p| p.requested);
+    let dismissed = ui.data(|d| d.get_temp::<usize>(id.with("dismissed")));
+    let candidates = if requested && !in_comment {
+        candidates(reference, upstream, text, &prefix).0
+    } else if (prev.is_none() && !r.response.changed())
+        || prefix.is_empty()
+        || prefix.starts_with(|c: char| c.is_ascii_digit())
+        || in_comment
+        || dismissed == Some(start)
+    {
+        vec![]
+    } else {
+        // Don't offer completions for a name which is already complete, so
+        // that enter and tab aren't swallowed at the end of a word.
+        match candidates(reference, upstream, text, &prefix) {
+            (_, true) => vec![],
+            (c, false) => c,
+        }
+    };
+    if prefix.is_empty() {
+        ui.data_mut(|d| d.remove_temp::<usize>(id.with("dismissed")));
+    }
+    if candidates.is_empty() {
+        ui.data_mut(|d| d.remove_temp::<Popup>(popup_id));
+    } else {
+        let selected = prev.map_or(0, |p| p.selected.min(candidates.len() - 1));
+        egui::Area::new(popup_id)
+            .order(egui::Order::Tooltip)
+            .fixed_pos(cursor_rect.left_bottom())
+            .interactable(false)
+            .show(ui.ctx(), |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    for (i, c) in candidates.iter().enumerate() {
+                        let color = match c.kind {
+                            Kind::Local | Kind::Block => {
+                                ui.visuals().strong_text_color()
+                            }
+                            Kind::Shape | Kind::Function => {
+                                ui.visuals().text_color()
+                            }
+                        };
+                        let mut job = egui::text::LayoutJob::default();
+                        job.append(
+                            &c.name,
+                            0.0,
+                            egui::TextFormat::simple(mono.clone(), color),
+                        );
+                        job.append(
+                            &c.detail,
+                            12.0,
+                            egui::TextFormat::simple(
+                                mono.clone(),
+                                ui.visuals().weak_text_color(),
+                            ),
+                        );
+                        let _ = ui.add(
+                            egui::Button::selectable(i == selected, job)
+                                .truncate(),
+                        );
+                    }
+                });
+            });
+        let popup = Popup {
+            start,
+            items: candidates.into_iter().map(|c| c.name).collect(),
+            selected,
+            requested,
+        };
+        ui.data_mut(|d| d.insert_temp(popup_id, popup));
+    }
+
+    // Show signature hints above the cursor when inside a function call
+    let sigs = callee(&before)
+        .and_then(|name| reference.get(&name))
+        .map(|e| e.signatures.as_slice())
+        .unwrap_or_default();
+    if !sigs.is_empty() && !in_comment {
+        egui::Area::new(id.with("signature"))
+            .order(egui::Order::Tooltip)
+            .fixed_pos(cursor_rect.left_top())
+            .pivot(egui::Align2::LEFT_BOTTOM)
+            .interactable(false)
+            .show(ui.ctx(), |ui| {
+                egui::Frame::popup(ui.style()).show(ui, |ui| {
+                    const MAX_SIGNATURES: usize = 4;
+                    for s in sigs.iter().take(MAX_SIGNATURES) {
+                        ui.label(egui::RichText::new(s).font(mono.clone()));
+                    }
+                    if sigs.len() > MAX_SIGNATURES {
+                        ui.weak(format!(
+                            "(+{} more)",
+                            sigs.len() - MAX_SIGNATURES
+                        ));
+                    }
+                });
+            });
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn find_callee() {
+        let chars = |s: &str| s.chars().collect::<Vec<_>>();
+        assert_eq!(callee(&chars("let x = sphere(")).unwrap(), "sphere");
+        assert_eq!(callee(&chars("move(sphere(a), [1, ")).unwrap(), "move");
+        assert_eq!(callee(&chars("box(#{ lower: ")).unwrap(), "box");
+        assert!(callee(&chars("f(x); let y = ")).is_none());
+        assert!(callee(&chars("if x { ")).is_none());
+    }
+
+    #[test]
+    fn exact_candidates() {
+        let reference = Reference::new(&crate::world::ShapeLibrary::build());
+        let upstream = ["foo".to_owned(), "foobar".to_owned()];
+        let script = "let food = 1;\n";
+
+        let (c, exact) = candidates(&reference, &upstream, script, "foo");
+        assert!(exact);
+        let names = c.iter().map(|c| c.name.as_str()).collect::<Vec<_>>();
+        assert_eq!(names, ["food", "foobar"]);
+
+        let (c, exact) = candidates(&reference, &upstream, script, "foob");
+        assert!(!exact);
+        assert_eq!(c.len(), 1);
+    }
+}
@@ -0,0 +1,301 @@
+//! Expression graph of math trees
+//!
+//! A tree is imported into a [`fidget::Context`], which deduplicates shared
+//! subexpressions, then drawn as a layered graph: leaves (constants and
+//! inputs) are on the left, and the root is on the right.  The longest path
+//! from the root to a leaf is highlighted, because it's usually where an
+//! expression has exploded.
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::Arc,
+};
+
+use fidget::context::{Op, Tree};
+
+const NODE_WIDTH: f32 = 64.0;
+const NODE_HEIGHT: f32 = 18.0;
+const COLUMN_SPACING: f32 = 96.0;
+const ROW_SPACING: f32 = 24.0;
+
+/// Graphs with more nodes than this are summarized but not drawn
+const MAX_DRAWN_NODES: usize = 2000;
+
+struct Node {
+    /// Operation name, used for per-op counts
+    op: String,
+    /// Text drawn in the node (e.g. a constant's value)
+    label: String,
+    /// Indices of child nodes, which always precede this node
+    children: Vec<usize>,
+    /// Length of the longest path from this node to a leaf
+    height: usize,
+    /// Number of nodes which use this node
+    users: usize,
+}
+
+/// Deduplicated expression graph
+pub struct Graph {
+    /// Nodes in topological order, so the root is last
+    nodes: Vec<Node>,
+    /// Number of nodes for each operation, sorted by name
+    counts: BTreeMap<String, usize>,
+    /// Nodes on the longest path, from root to leaf
+    longest: Vec<usize>,
+}
+
+impl Graph {
+    pub fn new(tree: &Tree) -> Self {
+        let mut ctx = fidget::Context::new();
+        let root = ctx.import(tree);
+
+        // Iterative post-order traversal, so that children are pushed first
+        let mut nodes: Vec<Node> = vec![];
+        let mut seen: HashMap<fidget::context::Node, usize> = HashMap::new();
+        let mut todo = vec![(root, false)];
+        while let Some((node, expanded)) = todo.pop() {
+            if seen.contains_key(&node) {
+                continue;
+            }
+            let (op, label, children) = match ctx.get_op(node) {
+                Some(Op::Binary(op, a, b)) => {
+                    let op = format!("{op:?}").to_lowercase();
+                    (op.clone(), op, vec![*a, *b])
+                }
+                Some(Op::Unary(op, a)) => {
+                    let op = format!("{op:?}").to_lowercase();
+                    (op.clone(), op, vec![*a])
+                }
+                Some(Op::Const(c)) => {
+                    ("const".to_owned(), c.to_string(), vec![])
+                }
+                Some(Op::Input(v)) => {
+                    let v = format!("{v:?}").to_lowercase();
+                    ("input".to_owned(), v, vec![])
+                }
+                None => ("?".to_owned(), "?".to_owned(), vec![]),
+            };
+            if !expanded {
+                todo.push((node, true));
+                todo.extend(children.iter().map(|c| (*c, false)));
+                continue;
+            }
+            let children = children.iter().map(|c| seen[c]).collect::<Vec<_>>();
+            for c in &children {
+                nodes[*c].users += 1;
+            }
+            let height = children
+                .iter()
+                .map(|c| nodes[*c].height + 1)
+                .max()
+                .unwrap_or(0);
+            seen.insert(node, nodes.len());
+            nodes.push(Node {
+                op,
+                label,
+                children,
+                height,
+                users: 0,
+            });
+        }
+
+        let mut counts = BTreeMap::new();
+        for n in &nodes {
+            *counts.entry(n.op.clone()).or_default() += 1;
+        }
+
+        let mut longest = vec![nodes.len() - 1];
+        while let Some(next) = nodes[*longest.last().unwrap()]
+            .children
+            .iter()
+            .max_by_key(|c| nodes[**c].height)
+        {
+            longest.push(*next);
+        }
+        Self {
+            nodes,
+            counts,
+            longest,
+        }
+    }
+}
+
+/// Cached graph, along with the tree it was built from
+///
+/// Holding a clone of the tree keeps its allocation alive, so comparing tree
+/// pointers is enough to check whether the cache is still valid.
+#[derive(Clone)]
+struct Cached(Tree, Arc<Graph>);
+
+/// Returns the graph for a tree, building it if it isn't cached
+fn graph(ui: &egui::Ui, id: egui::Id, tree: &Tree) -> Arc<Graph> {
+    if let Some(Cached(t, g)) = ui.data(|d| d.get_temp::<Cached>(id))
+        && std::ptr::eq(&*t, &**tree)
+    {
+        return g;
+    }
+    let g = Arc::new(Graph::new(tree));
+    ui.data_mut(|d| d.insert_temp(id, Cached(tree.clone(), g.clone())));
+    g
+}
+
+/// Draws the expression graph for one of a block's tree outputs
+///
+/// `trees` is a list of `(output name, tree)` tuples; the user may pick which
+/// one is shown.
+pub fn show(ui: &mut egui::Ui, id: egui::Id, trees: &[(String, Tree)]) {
+    if trees.is_empty() {
+        ui.weak("This block has no tree outputs");
+        return;
+    }
+    let selected_id = id.with("selected");
+    let mut selected = ui
+        .data(|d| d.get_temp::<String>(selected_id))
+        .filter(|s| trees.iter().any(|(name, _)| name == s))
+        .unwrap_or_else(|| trees[0].0.clone());
+    if trees.len() > 1 {
+        egui::ComboBox::from_id_salt(id.with("select"))
+            .selected_text(&selected)
+            .show_ui(ui, |ui| {
+                for (name, _) in trees {
+                    ui.selectable_value(&mut selected, name.clone(), name);
+                }
+            });
+    }
+    let tree = &trees.iter().find(|(name, _)| *name == selected).unwrap().1;
+    ui.data_mut(|d| d.insert_temp(selected_id, selected));
+
+    let graph = graph(ui, id.with("graph"), tree);
+    let n = graph.nodes.len();
+    ui.label(format!(
+        "{n} unique node{}, longest path: {} node{}",
+        if n == 1 { "" } else { "s" },
+        graph.longest.len(),
+        if graph.longest.len() == 1 { "" } else { "s" },
+    ));
+    egui::CollapsingHeader::new("Operations")
+        .id_salt(id.with("counts"))
+        .show(ui, |ui| {
+            let mut counts = graph.counts.iter().collect::<Vec<_>>();
+            counts.sort_by_key(|(_, c)| std::cmp::Reverse(**c));
+            egui::Grid::new(id.with("counts_grid"))
+                .num_columns(2)
+                .striped(true)
+                .show(ui, |ui| {
+                    for (op, count) in counts {
+                        ui.monospace(op);
+                        ui.monospace(count.to_string());
+                        ui.end_row();
+                    }
+                });
+        });
+    ui.separator();
+    if n > MAX_DRAWN_NODES {
+        ui.weak(format!(
+            "Too many nodes to draw (the limit is {MAX_DRAWN_NODES})"
+        ));
+        return;
+    }
+    canvas(ui, id, &graph);
+}
+
+/// Draws the graph on a pannable canvas, filling the available space
+fn canvas(ui: &mut egui::Ui, id: egui::Id, graph: &Graph) {
+    let (canvas, painter) =
+        ui.allocate_painter(ui.available_size(), egui::Sense::drag());
+    let pan_id = id.with("pan");
+    let mut pan: egui::Vec2 =
+        ui.data(|d| d.get_temp(pan_id)).unwrap_or_default();
+    if canvas.dragged() {
+        pan += canvas.drag_delta();
+        ui.data_mut(|d| d.insert_temp(pan_id, pan));
+    }
+    let origin = canvas.rect.min + pan + egui::vec2(10.0, 10.0);
+
+    // Place nodes in columns by height, so every edge points rightwards
+    let mut rows: Vec<usize> = vec![];
+    let rects = graph
+        .nodes
+        .iter()
+        .map(|n| {
+            if rows.len() <= n.height {
+                rows.resize(n.height + 1, 0);
+            }
+            let row = &mut rows[n.height];
+            let pos = origin
+                + egui::vec2(
+                    n.height as f32 * COLUMN_SPACING,
+                    *row as f32 * ROW_SPACING,
+                );
+            *row += 1;
+            egui::Rect::from_min_size(pos, egui::vec2(NODE_WIDTH, NODE_HEIGHT))
+        })
+        .collect::<Vec<_>>();
+
+    // For each node on the longest path, the next node along it
+    let mut on_path = vec![None; graph.nodes.len()];
+    for w in graph.longest.windows(2) {
+        on_path[w[0]] = Some(w[1]);
+    }
+    if let Some(leaf) = graph.longest.last() {
+        on_path[*leaf] = Some(*leaf);
+    }
+
+    let visuals = ui.visuals().clone();
+    let clip = painter.clip_rect();
+    let edge_stroke =
+        egui::Stroke::new(1.0, visuals.widgets.inactive.fg_stroke.color);
+    let path_stroke = egui::Stroke::new(2.0, visuals.selection.stroke.color);
+    for (i, n) in graph.nodes.iter().enumerate() {
+        for c in &n.children {
+            let a = rects[*c].right_center();
+            let b = rects[i].left_center();
+            if !clip.intersects(egui::Rect::from_two_pos(a, b)) {
+                continue;
+            }
+            let stroke = if on_path[i] == Some(*c) {
+                path_stroke
+            } else {
+                edge_stroke
+            };
+            painter.line_segment([a, b], stroke);
+        }
+    }
+
+    let font = egui::TextStyle::Monospace.resolve(ui.style());
+    let font = egui::FontId::monospace(font.size * 0.8);
+    for (i, (n, rect)) in graph.nodes.iter().zip(&rects).enumerate() {
+        if !clip.intersects(*rect) {
+            continue;
+        }
+        let stroke = if on_path[i].is_some() {
+            path_stroke
+        } else {
+            visuals.widgets.noninteractive.bg_stroke
+        };
+        painter.rect_filled(*rect, 3.0, visuals.window_fill);
+        painter.rect_stroke(*rect, 3.0, stroke, egui::StrokeKind::Inside);
+        // Long labels (e.g. constants) are truncated; see the hover text
+        const MAX_LABEL: usize = 8;
+        let label = if n.label.chars().count() > MAX_LABEL {
+            let mut s = n.label.chars().take(MAX_LABEL - 1).collect::<String>();
+            s += "…";
+            s
+        } else {
+            n.label.clone()
+        };
+        painter.text(
+            rect.center(),
+            egui::Align2::CENTER_CENTER,
+            label,
+            font.clone(),
+            visuals.text_color(),
+        );
+        let r =
+            ui.interact(*rect, id.with("node").with(i), egui::Sense::hover());
+        r.on_hover_ui(|ui| {
+            ui.monospace(&n.label);
+            ui.label(format!("height: {}", n.height));
+            ui.label(format!("used by {} node(s)", n.users));
+        });
+    }
+}
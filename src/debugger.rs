@@ -0,0 +1,301 @@
+//! Debugger state and UI for script blocks
+//!
+//! Breakpoints are toggled by clicking in the gutter to the left of a script's
+//! line numbers.  While a debug run is paused, its current line is highlighted
+//! and local variables are shown in a side panel.
+use std::{collections::HashMap, sync::mpsc};
+
+use crate::world::{
+    BlockIndex, Breakpoints, DebugCommand, DebugStatus, DebugTask, World,
+};
+
+/// An active (or recently finished) debug run
+struct Session {
+    /// Unique id, used to discard messages from previous runs
+    id: u64,
+    index: BlockIndex,
+    commands: mpsc::Sender<DebugCommand>,
+    status: DebugStatus,
+}
+
+/// Breakpoints and the current debug run, if any
+#[derive(Default)]
+pub struct DebugState {
+    breakpoints: HashMap<BlockIndex, Breakpoints>,
+    session: Option<Session>,
+    next_id: u64,
+}
+
+impl DebugState {
+    /// Starts a debug run, returning its id and the task to be spawned
+    ///
+    /// Any previous run is stopped.
+    pub fn start(
+        &mut self,
+        world: &World,
+        index: BlockIndex,
+        cmd: DebugCommand,
+    ) -> Option<(u64, DebugTask)> {
+        let breakpoints = self.breakpoints.entry(index).or_default().clone();
+        let (commands, task) = world.debug_block(index, breakpoints, cmd)?;
+        let id = self.next_id;
+        self.next_id += 1;
+        // Replacing the session drops its command sender, which stops the run
+        self.session = Some(Session {
+            id,
+            index,
+            commands,
+            status: DebugStatus::Running,
+        });
+        Some((id, task))
+    }
+
+    /// Records a status update from a debug run
+    pub fn update(&mut self, id: u64, status: DebugStatus) {
+        if let Some(s) = &mut self.session
+            && s.id == id
+        {
+            s.status = status;
+        }
+    }
+
+    /// Stops the current run (if any) and clears its status
+    pub fn stop(&mut self) {
+        self.session = None;
+    }
+
+    /// Returns the current session, if it is debugging the given block
+    fn session(&self, index: BlockIndex) -> Option<&Session> {
+        self.session.as_ref().filter(|s| s.index == index)
+    }
+
+    /// Returns the line at which the given block is paused
+    pub fn paused_line(&self, index: BlockIndex) -> Option<usize> {
+        match self.session(index)?.status {
+            DebugStatus::Paused { line, .. } => Some(line),
+            _ => None,
+        }
+    }
+
+    fn send(&mut self, cmd: DebugCommand) {
+        if let Some(s) = &mut self.session
+            && s.commands.send(cmd).is_ok()
+        {
+            s.status = DebugStatus::Running;
+        }
+    }
+}
+
+/// Draws debugger controls for the given block
+///
+/// Returns a command if the user asked to start a new debug run
+#[must_use]
+pub fn controls(
+    ui: &mut egui::Ui,
+    state: &mut DebugState,
+    index: BlockIndex,
+) -> Option<DebugCommand> {
+    let mut out = None;
+    ui.horizontal(|ui| {
+        let Some(session) = state.session(index) else {
+            if ui
+                .button("Debug")
+                .on_hover_text("Run this block until a breakpoint")
+                .clicked()
+            {
+                out = Some(DebugCommand::Continue);
+            }
+            if ui
+                .button("Step")
+                .on_hover_text("Run this block, pausing at the first line")
+                .clicked()
+            {
+                out = Some(DebugCommand::StepInto);
+            }
+            return;
+        };
+        let mut cmd = None;
+        match &session.status {
+            DebugStatus::Running => {
+                ui.spinner();
+                ui.label("Running");
+                if ui.button("Stop").clicked() {
+                    cmd = Some(DebugCommand::Stop);
+                }
+            }
+            DebugStatus::Paused { line, .. } => {
+                for (label, hover, c) in [
+                    (
+                        "Continue",
+                        "Run to the next breakpoint",
+                        DebugCommand::Continue,
+                    ),
+                    (
+                        "Step into",
+                        "Run to the next line, entering function calls",
+                        DebugCommand::StepInto,
+                    ),
+                    (
+                        "Step over",
+                        "Run to the next line in this function",
+                        DebugCommand::StepOver,
+                    ),
+                    (
+                        "Step out",
+                        "Run until this function returns",
+                        DebugCommand::StepOut,
+                    ),
+                    ("Stop", "Stop debugging", DebugCommand::Stop),
+                ] {
+                    if ui.button(label).on_hover_text(hover).clicked() {
+                        cmd = Some(c);
+                    }
+                }
+                ui.label(format!("Paused at line {line}"));
+            }
+            DebugStatus::Finished(r) => {
+                match r {
+                    None => ui.label("Finished"),
+                    Some(e) => ui.colored_label(ui.visuals().error_fg_color, e),
+                };
+                if ui.button("Close").clicked() {
+                    cmd = Some(DebugCommand::Stop);
+                }
+            }
+        }
+        match cmd {
+            Some(DebugCommand::Stop) => state.stop(),
+            Some(c) => state.send(c),
+            None => (),
+        }
+    });
+    out
+}
+
+/// Draws local variables and the call stack for a paused block
+pub fn locals_panel(ui: &mut egui::Ui, state: &DebugState, index: BlockIndex) {
+    let Some(DebugStatus::Paused { locals, stack, .. }) =
+        state.session(index).map(|s| &s.status)
+    else {
+        return;
+    };
+    egui::Panel::right(index.id().with("debug_locals"))
+        .resizable(true)
+        .show_inside(ui, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                ui.strong("Locals");
+                if locals.is_empty() {
+                    ui.weak("(none)");
+                }
+                egui::Grid::new(index.id().with("debug_locals_grid"))
+                    .num_columns(2)
+                    .striped(true)
+                    .show(ui, |ui| {
+                        for v in locals {
+                            ui.monospace(&v.name).on_hover_text(&v.ty);
+                            ui.add(egui::Label::new(&v.value).truncate())
+                                .on_hover_text(&v.value);
+                            ui.end_row();
+                        }
+                    });
+                if !stack.is_empty() {
+                    ui.separator();
+                    ui.strong("Call stack");
+                    for f in stack.iter().rev() {
+                        ui.monospace(f);
+                    }
+                }
+            });
+        });
+}
+
+/// Draws the breakpoint gutter, to the left of a script's line numbers
+pub fn gutter(
+    ui: &mut egui::Ui,
+    state: &mut DebugState,
+    index: BlockIndex,
+    script: &str,
+) {
+    // This must match the margin of the line number text edit
+    const MARGIN: f32 = 2.0;
+    let mut line_count = script.lines().count();
+    if script.is_empty() || script.ends_with('\n') {
+        line_count += 1;
+    }
+    let font = egui::TextStyle::Monospace.resolve(ui.style());
+    let row_height = ui.fonts_mut(|f| f.row_height(&font));
+    let (rect, r) = ui.allocate_exact_size(
+        egui::vec2(row_height * 0.8, row_height * line_count as f32 + MARGIN),
+        egui::Sense::click(),
+    );
+    let top = rect.top() + MARGIN;
+    let line_at = |y: f32| {
+        let line = ((y - top) / row_height).floor() as usize + 1;
+        (y >= top && line <= line_count).then_some(line)
+    };
+    let center = |line: usize| {
+        egui::pos2(rect.center().x, top + (line as f32 - 0.5) * row_height)
+    };
+
+    let breakpoints = state.breakpoints.entry(index).or_default();
+    if r.clicked()
+        && let Some(line) = r.interact_pointer_pos().and_then(|p| line_at(p.y))
+    {
+        let mut b = breakpoints.write().unwrap();
+        if !b.remove(&line) {
+            b.insert(line);
+        }
+    }
+
+    let painter = ui.painter_at(rect);
+    let radius = row_height * 0.25;
+    let color = ui.visuals().error_fg_color;
+    if let Some(line) = r.hover_pos().and_then(|p| line_at(p.y)) {
+        painter.circle_filled(center(line), radius, color.gamma_multiply(0.4));
+    }
+    for line in breakpoints.read().unwrap().iter() {
+        painter.circle_filled(center(*line), radius, color);
+    }
+    if let Some(line) = state.paused_line(index) {
+        let c = center(line);
+        let s = radius * 1.5;
+        painter.add(egui::Shape::convex_polygon(
+            vec![
+                c + egui::vec2(-s * 0.6, -s),
+                c + egui::vec2(s, 0.0),
+                c + egui::vec2(-s * 0.6, s),
+            ],
+            crate::color::YELLOW,
+            egui::Stroke::NONE,
+        ));
+    }
+}
+
+/// Highlights the paused line in a script editor
+pub fn highlight_line(
+    ui: &egui::Ui,
+    state: &DebugState,
+    index: BlockIndex,
+    text: &str,
+    r: &egui::text_edit::TextEditOutput,
+) {
+    let Some(line) = state.paused_line(index) else {
+        return;
+    };
+    let start = text
+        .split('\n')
+        .take(line - 1)
+        .map(|s| s.chars().count() + 1)
+        .sum();
+    let row = r
+        .galley
+        .pos_from_cursor(egui::text::CCursor::new(start))
+        .translate(r.galley_pos.to_vec2());
+    let rect =
+        egui::Rect::from_x_y_ranges(r.response.rect.x_range(), row.y_range());
+    ui.painter().rect_filled(
+        rect,
+        0.0,
+        crate::color::YELLOW.gamma_multiply(0.15),
+    );
+}
@@ -0,0 +1,307 @@
+//! Reference documentation for functions available to block scripts
+//!
+//! Signatures come from the script engine's function metadata.  Shape
+//! constructors are documented by their `facet` doc comments; functions which
+//! are registered by `halfspace` itself are documented in [`BUILTIN_DOCS`].
+use std::collections::BTreeMap;
+
+use crate::world::{self, ShapeLibrary};
+use egui::text::CCursor;
+
+/// Documentation for functions registered by `halfspace`
+const BUILTIN_DOCS: &[(&str, &str)] = &[
+    (
+        "input",
+        "Declares an input to the block, returning its evaluated value. \
+         The input's expression is edited in the block's input panel.",
+    ),
+    (
+        "output",
+        "Declares a named output from the block, which is visible to \
+         downstream blocks.",
+    ),
+    (
+        "view",
+        "Draws a tree, drawable, or scene in the block's view. \
+         Only one value may be viewed per block.",
+    ),
+    (
+        "export_mesh",
+        "Exports a mesh of the shape (a tree or scene) within the given \
+         bounds, with the given minimum feature size.  An optional format \
+         may be \"stl\" (the default), \"stl_ascii\", \"obj\", \"ply\", \
+         \"3mf\", or \"glb\", and can be changed next to the export button; \
+         a scene is exported as one object per shape if the format allows \
+         it.  PLY, 3MF, and glTF files also store each shape's color per \
+         vertex.  Only one export is allowed per block.",
+    ),
+    (
+        "export_image",
+        "Exports an image of the scene within the given bounds, at the given \
+         resolution (in pixels per unit).  Only one export is allowed per \
+         block.",
+    ),
+    (
+        "scene",
+        "Builds a scene from one or more trees or drawables (or an array of \
+         them), for viewing or exporting.",
+    ),
+    ("draw", "Pairs a tree with a color, for use in a scene."),
+    (
+        "rgb",
+        "Builds a color from red, green, and blue channels, each in the \
+         0.0 - 1.0 range.  Channels may be trees, which are evaluated \
+         per-pixel.",
+    ),
+    (
+        "hsl",
+        "Builds a color from hue (in radians), saturation, and lightness \
+         (in the 0.0 - 1.0 range).  Channels may be trees, which are \
+         evaluated per-pixel.",
+    ),
+    (
+        "eval_at",
+        "Evaluates a tree at a point, given as x, y, z values or a vec3.  \
+         The result is negative inside the shape.  The most recently queried \
+         tree is cached, so repeated queries of the same tree are fast.",
+    ),
+    (
+        "gradient_at",
+        "Returns the gradient of a tree at a point (given as x, y, z values \
+         or a vec3), as a vec3.  On the surface of a distance field, this is \
+         the outward-facing normal.",
+    ),
+    (
+        "raycast",
+        "Casts a ray from an origin along a direction, returning the first \
+         point (as a vec3) where the tree is zero or negative, or () if the \
+         ray doesn't hit the shape within the (finite) maximum distance.",
+    ),
+    (
+        "bounds",
+        "Returns an axis-aligned bounding box for a tree or scene, as \
+         #{ lower, upper } vec3 corners, or () if the shape is empty.  Bounds \
+         are conservative, and are infinite on axes where the shape is \
+         unbounded (e.g. Z for 2D shapes).",
+    ),
+    (
+        "mass_properties",
+        "Estimates the volume, surface area, center of mass (centroid), and \
+         inertia tensor (about the centroid, with unit density) of a shape, \
+         by meshing it within the given bounds at the given minimum feature \
+         size.",
+    ),
+    (
+        "warn",
+        "Records a warning for this block, without stopping evaluation.  \
+         Only available in script blocks.",
+    ),
+    (
+        "assert",
+        "Stops evaluation with the given message if the condition is false.  \
+         Only available in script blocks.",
+    ),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EntryKind {
+    Shape,
+    Function,
+}
+
+/// Documentation for a single function name (which may be overloaded)
+pub struct Entry {
+    pub kind: EntryKind,
+    pub signatures: Vec<String>,
+    /// Documentation, which may be empty
+    pub doc: String,
+    /// Fields of the object passed to a shape constructor, with documentation
+    pub fields: Vec<(String, String)>,
+}
+
+/// Reference for every function available to block scripts
+pub struct Reference {
+    entries: BTreeMap<String, Entry>,
+}
+
+impl Reference {
+    pub fn new(library: &ShapeLibrary) -> Self {
+        let mut entries: BTreeMap<String, Entry> = BTreeMap::new();
+        for sig in world::function_signatures() {
+            let Some((name, _)) = sig.split_once('(') else {
+                continue;
+            };
+            // Skip operators, property getters / setters, and namespaced
+            // functions, which can't be called by name.
+            if !rhai::is_valid_identifier(name) {
+                continue;
+            }
+            let e = entries.entry(name.to_owned()).or_insert_with(|| Entry {
+                kind: EntryKind::Function,
+                signatures: vec![],
+                doc: BUILTIN_DOCS
+                    .iter()
+                    .find(|(n, _)| *n == name)
+                    .map(|(_, d)| d.to_string())
+                    .unwrap_or_default(),
+                fields: vec![],
+            });
+            if !e.signatures.contains(&sig) {
+                e.signatures.push(sig);
+            }
+        }
+        // Shape constructors replace their (less informative) engine
+        // signatures, which only show an untyped object map.
+        for c in &library.constructors {
+            entries.insert(
+                c.name.clone(),
+                Entry {
+                    kind: EntryKind::Shape,
+                    signatures: vec![c.signature()],
+                    doc: c.doc.clone(),
+                    fields: c.fields.clone(),
+                },
+            );
+        }
+        Self { entries }
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Entry> {
+        self.entries.get(name)
+    }
+
+    /// Iterates over entries, sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Entry)> {
+        self.entries.iter().map(|(k, v)| (k.as_str(), v))
+    }
+}
+
+/// Persistent state for the function reference window
+#[derive(Default)]
+pub struct ReferenceState {
+    pub open: bool,
+    query: String,
+}
+
+/// Draws documentation for a single entry
+fn entry_ui(ui: &mut egui::Ui, name: &str, entry: &Entry) {
+    let mono = egui::TextStyle::Monospace.resolve(ui.style());
+    for s in &entry.signatures {
+        ui.label(egui::RichText::new(s).font(mono.clone()).strong());
+    }
+    if entry.signatures.is_empty() {
+        ui.label(egui::RichText::new(name).font(mono.clone()).strong());
+    }
+    if !entry.doc.is_empty() {
+        ui.label(&entry.doc);
+    }
+    for (field, doc) in &entry.fields {
+        ui.horizontal_wrapped(|ui| {
+            ui.label(egui::RichText::new(field).font(mono.clone()));
+            if !doc.is_empty() {
+                ui.weak(doc);
+            }
+        });
+    }
+}
+
+/// Draws the function reference window
+pub fn show(
+    ctx: &egui::Context,
+    state: &mut ReferenceState,
+    reference: &Reference,
+) {
+    let mut open = state.open;
+    egui::Window::new("Function reference")
+        .open(&mut open)
+        .default_width(450.0)
+        .default_height(500.0)
+        .show(ctx, |ui| {
+            ui.add(
+                egui::TextEdit::singleline(&mut state.query)
+                    .hint_text("Search")
+                    .desired_width(f32::INFINITY),
+            );
+            ui.separator();
+            let query = state.query.to_lowercase();
+            let matches = |name: &str, e: &Entry| {
+                query.is_empty()
+                    || name.contains(&query)
+                    || e.doc.to_lowercase().contains(&query)
+            };
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for (kind, title) in [
+                    (EntryKind::Shape, "Shapes"),
+                    (EntryKind::Function, "Functions"),
+                ] {
+                    let entries = reference
+                        .iter()
+                        .filter(|(n, e)| e.kind == kind && matches(n, e))
+                        .collect::<Vec<_>>();
+                    if entries.is_empty() {
+                        continue;
+                    }
+                    ui.heading(title);
+                    for (name, e) in entries {
+                        ui.add_space(4.0);
+                        entry_ui(ui, name, e);
+                    }
+                    ui.add_space(8.0);
+                }
+            });
+        });
+    state.open = open;
+}
+
+/// Shows documentation when hovering over a known name in a script editor
+pub fn hover(
+    ui: &egui::Ui,
+    id: egui::Id,
+    reference: &Reference,
+    text: &str,
+    r: &egui::text_edit::TextEditOutput,
+) {
+    let Some(p) = r.response.hover_pos() else {
+        return;
+    };
+    let chars = text.chars().collect::<Vec<_>>();
+    let i = r
+        .galley
+        .cursor_from_pos(p - r.galley_pos)
+        .index
+        .min(chars.len());
+    let is_ident = |c: &char| c.is_alphanumeric() || *c == '_';
+    let start = chars[..i]
+        .iter()
+        .rposition(|c| !is_ident(c))
+        .map_or(0, |j| j + 1);
+    let end = chars[i..]
+        .iter()
+        .position(|c| !is_ident(c))
+        .map_or(chars.len(), |j| i + j);
+    if start == end {
+        return;
+    }
+
+    // The nearest cursor may be past the end of a line, so check that the
+    // pointer is actually over the word.
+    let a = r.galley.pos_from_cursor(CCursor::new(start));
+    let b = r.galley.pos_from_cursor(CCursor::new(end));
+    let rect = egui::Rect::from_min_max(a.left_top(), b.max)
+        .translate(r.galley_pos.to_vec2());
+    if !rect.contains(p) {
+        return;
+    }
+    let name = chars[start..end].iter().collect::<String>();
+    if let Some(entry) = reference.get(&name) {
+        egui::show_tooltip_at_pointer(
+            ui.ctx(),
+            ui.layer_id(),
+            id.with("doc"),
+            |ui| {
+                ui.set_max_width(400.0);
+                entry_ui(ui, &name, entry)
+            },
+        );
+    }
+}
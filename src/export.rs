@@ -1,5 +1,6 @@
 use crate::{
-    render::{RenderFunction, RenderShape, image_to_bitfield},
+    formats::{MeshFormat, MeshObject},
+    render::{RenderFunction, RenderShape, image_to_bitfield, vertex_colors},
     world::Scene,
 };
 
@@ -44,6 +45,9 @@ pub enum ExportError {
 
     #[error("image error")]
     ImageError(#[from] image::ImageError),
+
+    #[error("export is too large for this file format")]
+    TooLarge,
 }
 
 pub(crate) fn mesh_settings(
@@ -87,14 +91,14 @@ pub(crate) fn mesh_settings(
     Ok(settings)
 }
 
-/// Returns an exported STL
-pub(crate) fn build_stl(
+/// Builds a mesh of the shape within the given bounds
+pub(crate) fn build_mesh(
     tree: Tree,
     lower: Vec3,
     upper: Vec3,
     feature_size: f64,
     cancel_token: fidget::render::CancelToken,
-) -> Result<Vec<u8>, ExportError> {
+) -> Result<fidget::mesh::Mesh, ExportError> {
     // We intersect the shape with the render bounds, then render with slightly
     // extended bounds (1% larger)
     let bounded: Tree = Intersection {
@@ -109,10 +113,39 @@ pub(crate) fn build_stl(
     settings.cancel = cancel_token;
 
     let o = Octree::build(&shape, &settings).ok_or(ExportError::Cancelled)?;
-    let mesh = o.walk_dual();
-    let mut stl = vec![];
-    mesh.write_stl(&mut stl).unwrap();
-    Ok(stl)
+    Ok(o.walk_dual())
+}
+
+/// Returns an exported mesh file, with one mesh per shape in the scene
+///
+/// If the format supports it, each shape's color is evaluated at its vertices.
+pub(crate) fn build_mesh_file(
+    scene: Scene,
+    lower: Vec3,
+    upper: Vec3,
+    feature_size: f64,
+    format: MeshFormat,
+    cancel_token: fidget::render::CancelToken,
+) -> Result<Vec<u8>, ExportError> {
+    let objects = scene
+        .shapes
+        .into_iter()
+        .map(|d| {
+            let mesh = build_mesh(
+                d.tree,
+                lower,
+                upper,
+                feature_size,
+                cancel_token.clone(),
+            )?;
+            let colors = d
+                .color
+                .filter(|_| format.has_colors())
+                .map(|c| vertex_colors(&mesh.vertices, &c));
+            Ok(MeshObject { mesh, colors })
+        })
+        .collect::<Result<Vec<_>, ExportError>>()?;
+    format.write(&objects)
 }
 
 fn image_view(
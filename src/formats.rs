@@ -0,0 +1,702 @@
+//! Mesh file formats
+//!
+//! Every format except STL stores shared vertices, rather than a list of
+//! independent triangles.  3MF files are zip archives, which we write without
+//! compression.
+//!
+//! PLY, 3MF, and glTF also store per-vertex colors, if present.
+use std::fmt::Write;
+
+use fidget::mesh::Mesh;
+
+use crate::export::ExportError;
+
+/// File format for mesh export
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum MeshFormat {
+    #[default]
+    StlBinary,
+    StlAscii,
+    Obj,
+    Ply,
+    ThreeMf,
+    Glb,
+}
+
+/// A single mesh to be written, with optional per-vertex colors
+pub struct MeshObject {
+    pub mesh: Mesh,
+    /// sRGB colors, with one entry per vertex in `mesh`
+    pub colors: Option<Vec<[u8; 3]>>,
+}
+
+impl MeshFormat {
+    pub const ALL: [MeshFormat; 6] = [
+        MeshFormat::StlBinary,
+        MeshFormat::StlAscii,
+        MeshFormat::Obj,
+        MeshFormat::Ply,
+        MeshFormat::ThreeMf,
+        MeshFormat::Glb,
+    ];
+
+    /// Returns the name used to select this format in scripts
+    pub fn script_name(&self) -> &'static str {
+        match self {
+            MeshFormat::StlBinary => "stl",
+            MeshFormat::StlAscii => "stl_ascii",
+            MeshFormat::Obj => "obj",
+            MeshFormat::Ply => "ply",
+            MeshFormat::ThreeMf => "3mf",
+            MeshFormat::Glb => "glb",
+        }
+    }
+
+    /// Looks up a format by its script name
+    pub fn from_script_name(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.script_name() == s)
+    }
+
+    /// Returns a human-readable description, e.g. for file dialogs
+    pub fn description(&self) -> &'static str {
+        match self {
+            MeshFormat::StlBinary => "STL (binary)",
+            MeshFormat::StlAscii => "STL (ASCII)",
+            MeshFormat::Obj => "Wavefront OBJ",
+            MeshFormat::Ply => "PLY",
+            MeshFormat::ThreeMf => "3MF",
+            MeshFormat::Glb => "glTF (binary)",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            MeshFormat::StlBinary | MeshFormat::StlAscii => "stl",
+            MeshFormat::Obj => "obj",
+            MeshFormat::Ply => "ply",
+            MeshFormat::ThreeMf => "3mf",
+            MeshFormat::Glb => "glb",
+        }
+    }
+
+    /// Checks whether this format stores vertex colors
+    pub fn has_colors(&self) -> bool {
+        matches!(
+            self,
+            MeshFormat::Ply | MeshFormat::ThreeMf | MeshFormat::Glb
+        )
+    }
+
+    /// Writes a set of meshes to a file
+    ///
+    /// Formats which support multiple objects (OBJ, 3MF, and glTF) store one
+    /// object per mesh; other formats merge the meshes together.  Colors are
+    /// ignored by formats which don't support them.
+    pub fn write(
+        &self,
+        objects: &[MeshObject],
+    ) -> Result<Vec<u8>, ExportError> {
+        Ok(match self {
+            MeshFormat::StlBinary => write_stl_binary(objects)?,
+            MeshFormat::StlAscii => write_stl_ascii(objects).into_bytes(),
+            MeshFormat::Obj => write_obj(objects).into_bytes(),
+            MeshFormat::Ply => write_ply(objects)?,
+            MeshFormat::ThreeMf => write_3mf(objects)?,
+            MeshFormat::Glb => write_glb(objects)?,
+        })
+    }
+}
+
+/// Color used for uncolored objects in formats which require a color
+const DEFAULT_COLOR: [u8; 3] = [255; 3];
+
+/// Iterates over every triangle in a set of meshes, as vertex positions
+fn triangles(
+    objects: &[MeshObject],
+) -> impl Iterator<Item = [nalgebra::Vector3<f32>; 3]> + '_ {
+    objects.iter().map(|o| &o.mesh).flat_map(|m| {
+        m.triangles
+            .iter()
+            .map(|t| [t.x, t.y, t.z].map(|i| m.vertices[i]))
+    })
+}
+
+/// Returns the unit normal of a triangle, or zero if it's degenerate
+fn normal([a, b, c]: [nalgebra::Vector3<f32>; 3]) -> nalgebra::Vector3<f32> {
+    (b - a)
+        .cross(&(c - a))
+        .try_normalize(0.0)
+        .unwrap_or_else(nalgebra::Vector3::zeros)
+}
+
+/// Writes a binary STL file, merging all objects
+fn write_stl_binary(objects: &[MeshObject]) -> Result<Vec<u8>, ExportError> {
+    let meshes = objects.iter().map(|o| &o.mesh);
+    let count: usize = meshes.clone().map(|m| m.triangles.len()).sum();
+    u32::try_from(count).map_err(|_| ExportError::TooLarge)?;
+
+    let mut merged = Mesh {
+        vertices: vec![],
+        triangles: Vec::with_capacity(count),
+    };
+    for m in meshes {
+        let offset = merged.vertices.len();
+        merged.vertices.extend(&m.vertices);
+        merged
+            .triangles
+            .extend(m.triangles.iter().map(|t| t.map(|i| i + offset)));
+    }
+    let mut out = vec![];
+    merged.write_stl(&mut out).unwrap(); // writing to a Vec can't fail
+    Ok(out)
+}
+
+fn write_stl_ascii(objects: &[MeshObject]) -> String {
+    let mut out = "solid halfspace\n".to_owned();
+    for t in triangles(objects) {
+        let n = normal(t);
+        writeln!(out, "facet normal {} {} {}", n.x, n.y, n.z).unwrap();
+        writeln!(out, "  outer loop").unwrap();
+        for v in t {
+            writeln!(out, "    vertex {} {} {}", v.x, v.y, v.z).unwrap();
+        }
+        writeln!(out, "  endloop").unwrap();
+        writeln!(out, "endfacet").unwrap();
+    }
+    out += "endsolid halfspace\n";
+    out
+}
+
+fn write_obj(objects: &[MeshObject]) -> String {
+    let mut out = "# exported by halfspace\n".to_owned();
+    let mut offset = 1; // OBJ indices are 1-based
+    for (i, m) in objects.iter().map(|o| &o.mesh).enumerate() {
+        writeln!(out, "o shape_{i}").unwrap();
+        for v in &m.vertices {
+            writeln!(out, "v {} {} {}", v.x, v.y, v.z).unwrap();
+        }
+        for t in &m.triangles {
+            let [a, b, c] = [t.x, t.y, t.z].map(|i| i + offset);
+            writeln!(out, "f {a} {b} {c}").unwrap();
+        }
+        offset += m.vertices.len();
+    }
+    out
+}
+
+/// Writes a binary PLY file, merging all objects
+///
+/// If any object has colors, then every vertex gets a color; uncolored objects
+/// use [`DEFAULT_COLOR`].
+fn write_ply(objects: &[MeshObject]) -> Result<Vec<u8>, ExportError> {
+    let meshes = objects.iter().map(|o| &o.mesh);
+    let vertex_count: usize = meshes.clone().map(|m| m.vertices.len()).sum();
+    let face_count: usize = meshes.clone().map(|m| m.triangles.len()).sum();
+    let has_colors = objects.iter().any(|o| o.colors.is_some());
+    let color_props = if has_colors {
+        "property uchar red\n\
+         property uchar green\n\
+         property uchar blue\n"
+    } else {
+        ""
+    };
+    let mut out = format!(
+        "ply\n\
+         format binary_little_endian 1.0\n\
+         comment exported by halfspace\n\
+         element vertex {vertex_count}\n\
+         property float x\n\
+         property float y\n\
+         property float z\n\
+         {color_props}\
+         element face {face_count}\n\
+         property list uchar int vertex_indices\n\
+         end_header\n"
+    )
+    .into_bytes();
+    for o in objects {
+        for (i, v) in o.mesh.vertices.iter().enumerate() {
+            for c in v.iter() {
+                out.extend(c.to_le_bytes());
+            }
+            if has_colors {
+                let c = o.colors.as_ref().map_or(DEFAULT_COLOR, |c| c[i]);
+                out.extend(c);
+            }
+        }
+    }
+    let mut offset = 0;
+    for m in meshes {
+        for t in &m.triangles {
+            out.push(3);
+            for i in [t.x, t.y, t.z] {
+                let i = i32::try_from(i + offset)
+                    .map_err(|_| ExportError::TooLarge)?;
+                out.extend(i.to_le_bytes());
+            }
+        }
+        offset += m.vertices.len();
+    }
+    Ok(out)
+}
+
+/// XML namespace for the 3MF materials extension, used for vertex colors
+const MATERIALS_NS: &str =
+    "http://schemas.microsoft.com/3dmanufacturing/material/2015/02";
+
+const CONTENT_TYPES_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types">
+  <Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/>
+  <Default Extension="model" ContentType="application/vnd.ms-package.3dmanufacturing-3dmodel+xml"/>
+</Types>
+"#;
+
+const RELS_XML: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+  <Relationship Target="/3D/3dmodel.model" Id="rel0" Type="http://schemas.microsoft.com/3dmanufacturing/2013/01/3dmodel"/>
+</Relationships>
+"#;
+
+/// Writes a 3MF model, with one object per (non-empty) mesh
+///
+/// Model units are millimeters.  Vertex colors are stored as a color group
+/// from the materials extension, with one color per vertex.
+fn write_3mf(objects: &[MeshObject]) -> Result<Vec<u8>, ExportError> {
+    let mut model = format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<model unit="millimeter" xml:lang="en-US" xmlns="http://schemas.microsoft.com/3dmanufacturing/core/2015/02" xmlns:m="{MATERIALS_NS}">
+  <resources>
+"#
+    );
+    // Resource IDs must be positive and unique across objects and color groups
+    let mut next_id = 1..;
+    let mut items = vec![];
+    for o in objects.iter().filter(|o| !o.mesh.triangles.is_empty()) {
+        let m = &o.mesh;
+        let group = o.colors.as_ref().map(|colors| {
+            let id = next_id.next().unwrap();
+            writeln!(model, r#"    <m:colorgroup id="{id}">"#).unwrap();
+            for [r, g, b] in colors {
+                writeln!(
+                    model,
+                    r##"      <m:color color="#{r:02X}{g:02X}{b:02X}"/>"##
+                )
+                .unwrap();
+            }
+            model += "    </m:colorgroup>\n";
+            id
+        });
+        let id = next_id.next().unwrap();
+        items.push(id);
+        match group {
+            Some(g) => writeln!(
+                model,
+                r#"    <object id="{id}" type="model" pid="{g}" pindex="0">"#
+            ),
+            None => writeln!(model, r#"    <object id="{id}" type="model">"#),
+        }
+        .unwrap();
+        model += "      <mesh>\n        <vertices>\n";
+        for v in &m.vertices {
+            writeln!(
+                model,
+                r#"          <vertex x="{}" y="{}" z="{}"/>"#,
+                v.x, v.y, v.z
+            )
+            .unwrap();
+        }
+        model += "        </vertices>\n        <triangles>\n";
+        for t in &m.triangles {
+            write!(
+                model,
+                r#"          <triangle v1="{}" v2="{}" v3="{}""#,
+                t.x, t.y, t.z
+            )
+            .unwrap();
+            // Each vertex's color has the same index as the vertex
+            if let Some(g) = group {
+                write!(
+                    model,
+                    r#" pid="{g}" p1="{}" p2="{}" p3="{}""#,
+                    t.x, t.y, t.z
+                )
+                .unwrap();
+            }
+            model += "/>\n";
+        }
+        model += "        </triangles>\n      </mesh>\n    </object>\n";
+    }
+    model += "  </resources>\n  <build>\n";
+    for id in &items {
+        writeln!(model, r#"    <item objectid="{id}"/>"#).unwrap();
+    }
+    model += "  </build>\n</model>\n";
+
+    write_zip(&[
+        ("[Content_Types].xml", CONTENT_TYPES_XML.as_bytes()),
+        ("_rels/.rels", RELS_XML.as_bytes()),
+        ("3D/3dmodel.model", model.as_bytes()),
+    ])
+}
+
+/// Binary buffer and JSON descriptions for a glTF file
+#[derive(Default)]
+struct GltfBuffer {
+    bin: Vec<u8>,
+    views: Vec<serde_json::Value>,
+    accessors: Vec<serde_json::Value>,
+}
+
+impl GltfBuffer {
+    /// Appends data as a new buffer view and accessor, returning its index
+    ///
+    /// `accessor` is extended with the buffer view index.
+    fn push(
+        &mut self,
+        data: &[u8],
+        target: u32,
+        mut accessor: serde_json::Value,
+    ) -> usize {
+        // Every component we write is 4 bytes, so views stay aligned
+        let view = self.views.len();
+        self.views.push(serde_json::json!({
+            "buffer": 0,
+            "byteOffset": self.bin.len(),
+            "byteLength": data.len(),
+            "target": target,
+        }));
+        self.bin.extend(data);
+        accessor["bufferView"] = view.into();
+        self.accessors.push(accessor);
+        self.accessors.len() - 1
+    }
+}
+
+/// Writes a binary glTF file, with one mesh and node per (non-empty) object
+///
+/// Vertex colors are stored as `COLOR_0`, converted into linear RGB as
+/// required by the glTF spec.
+fn write_glb(objects: &[MeshObject]) -> Result<Vec<u8>, ExportError> {
+    const ARRAY_BUFFER: u32 = 34962;
+    const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+    const FLOAT: u32 = 5126;
+    const UNSIGNED_INT: u32 = 5125;
+
+    let mut buf = GltfBuffer::default();
+    let mut meshes = vec![];
+    for (i, o) in objects.iter().enumerate() {
+        let m = &o.mesh;
+        if m.triangles.is_empty() {
+            continue;
+        }
+        let (lo, hi) = m.vertices.iter().fold(
+            ([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]),
+            |(lo, hi), v| {
+                (
+                    std::array::from_fn(|j| lo[j].min(v[j])),
+                    std::array::from_fn(|j| hi[j].max(v[j])),
+                )
+            },
+        );
+        let positions = m
+            .vertices
+            .iter()
+            .flat_map(|v| v.iter().flat_map(|c| c.to_le_bytes()))
+            .collect::<Vec<u8>>();
+        let mut attributes = serde_json::json!({
+            "POSITION": buf.push(&positions, ARRAY_BUFFER, serde_json::json!({
+                "componentType": FLOAT,
+                "count": m.vertices.len(),
+                "type": "VEC3",
+                "min": lo,
+                "max": hi,
+            })),
+        });
+        if let Some(colors) = &o.colors {
+            let data = colors
+                .iter()
+                .flatten()
+                .flat_map(|c| srgb_to_linear(*c).to_le_bytes())
+                .collect::<Vec<u8>>();
+            attributes["COLOR_0"] = buf
+                .push(
+                    &data,
+                    ARRAY_BUFFER,
+                    serde_json::json!({
+                        "componentType": FLOAT,
+                        "count": colors.len(),
+                        "type": "VEC3",
+                    }),
+                )
+                .into();
+        }
+        let mut indices = Vec::with_capacity(m.triangles.len() * 12);
+        for t in &m.triangles {
+            for v in [t.x, t.y, t.z] {
+                let v = u32::try_from(v).map_err(|_| ExportError::TooLarge)?;
+                indices.extend(v.to_le_bytes());
+            }
+        }
+        let indices = buf.push(
+            &indices,
+            ELEMENT_ARRAY_BUFFER,
+            serde_json::json!({
+                "componentType": UNSIGNED_INT,
+                "count": m.triangles.len() * 3,
+                "type": "SCALAR",
+            }),
+        );
+        meshes.push(serde_json::json!({
+            "name": format!("shape_{i}"),
+            "primitives": [{
+                "attributes": attributes,
+                "indices": indices,
+                "mode": 4, // triangles
+            }],
+        }));
+    }
+
+    let nodes = (0..meshes.len())
+        .map(|i| serde_json::json!({ "mesh": i }))
+        .collect::<Vec<_>>();
+    let mut json = serde_json::json!({
+        "asset": { "version": "2.0", "generator": "halfspace" },
+        "scene": 0,
+        "scenes": [{ "nodes": (0..nodes.len()).collect::<Vec<_>>() }],
+        "nodes": nodes,
+        "meshes": meshes,
+    });
+    // Empty buffers aren't allowed, so only write one if there's data
+    if !buf.bin.is_empty() {
+        json["buffers"] = serde_json::json!([{ "byteLength": buf.bin.len() }]);
+        json["bufferViews"] = buf.views.into();
+        json["accessors"] = buf.accessors.into();
+    }
+
+    // Chunks are padded to 4-byte alignment
+    let mut json = serde_json::to_vec(&json).unwrap();
+    json.resize(json.len().next_multiple_of(4), b' ');
+    let mut bin = buf.bin;
+    bin.resize(bin.len().next_multiple_of(4), 0);
+
+    let mut chunks = vec![(0x4E4F534Au32, json)]; // "JSON"
+    if !bin.is_empty() {
+        chunks.push((0x004E4942, bin)); // "BIN\0"
+    }
+    let total = 12 + chunks.iter().map(|(_, c)| 8 + c.len()).sum::<usize>();
+    let total = u32::try_from(total).map_err(|_| ExportError::TooLarge)?;
+
+    let mut out = Vec::with_capacity(total as usize);
+    out.extend(b"glTF");
+    out.extend(2u32.to_le_bytes());
+    out.extend(total.to_le_bytes());
+    for (ty, data) in chunks {
+        out.extend((data.len() as u32).to_le_bytes());
+        out.extend(ty.to_le_bytes());
+        out.extend(data);
+    }
+    Ok(out)
+}
+
+/// Converts an sRGB channel into linear RGB
+fn srgb_to_linear(c: u8) -> f32 {
+    let c = c as f32 / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Writes an uncompressed zip archive
+///
+/// Files must be smaller than 4 GiB, because we don't support Zip64.
+fn write_zip(files: &[(&str, &[u8])]) -> Result<Vec<u8>, ExportError> {
+    // Timestamps are fixed at 1980-01-01 00:00, the earliest DOS date
+    const TIME: u16 = 0;
+    const DATE: u16 = (1 << 5) | 1;
+    const VERSION: u16 = 20;
+
+    let mut out = vec![];
+    let mut central = vec![];
+    for (name, data) in files {
+        let offset =
+            u32::try_from(out.len()).map_err(|_| ExportError::TooLarge)?;
+        let size =
+            u32::try_from(data.len()).map_err(|_| ExportError::TooLarge)?;
+        let crc = crc32fast::hash(data);
+        let name_len = name.len() as u16;
+
+        out.extend(0x04034b50u32.to_le_bytes());
+        for v in [VERSION, 0, 0, TIME, DATE] {
+            out.extend(v.to_le_bytes());
+        }
+        for v in [crc, size, size] {
+            out.extend(v.to_le_bytes());
+        }
+        for v in [name_len, 0] {
+            out.extend(v.to_le_bytes());
+        }
+        out.extend(name.as_bytes());
+        out.extend(*data);
+
+        central.extend(0x02014b50u32.to_le_bytes());
+        for v in [VERSION, VERSION, 0, 0, TIME, DATE] {
+            central.extend(v.to_le_bytes());
+        }
+        for v in [crc, size, size] {
+            central.extend(v.to_le_bytes());
+        }
+        for v in [name_len, 0, 0, 0, 0] {
+            central.extend(v.to_le_bytes());
+        }
+        for v in [0, offset] {
+            central.extend(v.to_le_bytes());
+        }
+        central.extend(name.as_bytes());
+    }
+    let central_offset =
+        u32::try_from(out.len()).map_err(|_| ExportError::TooLarge)?;
+    let central_size = central.len() as u32;
+    out.extend(central);
+
+    let count = files.len() as u16;
+    out.extend(0x06054b50u32.to_le_bytes());
+    for v in [0, 0, count, count] {
+        out.extend(v.to_le_bytes());
+    }
+    for v in [central_size, central_offset] {
+        out.extend(v.to_le_bytes());
+    }
+    out.extend(0u16.to_le_bytes()); // comment length
+    Ok(out)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Builds a tetrahedron with shared vertices
+    fn tetrahedron() -> Mesh {
+        let v = nalgebra::Vector3::<f32>::new;
+        let t = nalgebra::Vector3::<usize>::new;
+        Mesh {
+            vertices: vec![
+                v(0.0, 0.0, 0.0),
+                v(1.0, 0.0, 0.0),
+                v(0.0, 1.0, 0.0),
+                v(0.0, 0.0, 1.0),
+            ],
+            triangles: vec![t(0, 2, 1), t(0, 1, 3), t(0, 3, 2), t(1, 2, 3)],
+        }
+    }
+
+    #[test]
+    fn formats() {
+        let meshes = [tetrahedron(), tetrahedron()]
+            .map(|mesh| MeshObject { mesh, colors: None });
+
+        let stl = MeshFormat::StlBinary.write(&meshes).unwrap();
+        assert_eq!(stl.len(), 84 + 8 * 50);
+        assert_eq!(u32::from_le_bytes(stl[80..84].try_into().unwrap()), 8);
+
+        let ascii = MeshFormat::StlAscii.write(&meshes).unwrap();
+        let ascii = String::from_utf8(ascii).unwrap();
+        assert_eq!(ascii.matches("endfacet").count(), 8);
+
+        // Vertices are shared, and indices are offset for the second object
+        let obj = MeshFormat::Obj.write(&meshes).unwrap();
+        let obj = String::from_utf8(obj).unwrap();
+        assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), 8);
+        assert!(obj.contains("o shape_1\n"));
+        assert!(obj.contains("\nf 6 7 8\n"));
+
+        let ply = MeshFormat::Ply.write(&meshes).unwrap();
+        let header_end = b"end_header\n";
+        let start = ply
+            .windows(header_end.len())
+            .position(|w| w == header_end)
+            .unwrap()
+            + header_end.len();
+        let header = std::str::from_utf8(&ply[..start]).unwrap();
+        assert!(header.contains("element vertex 8\n"));
+        assert!(header.contains("element face 8\n"));
+        assert_eq!(ply.len() - start, 8 * 12 + 8 * 13);
+
+        // The zip's end-of-central-directory record lists three files
+        let zip = MeshFormat::ThreeMf.write(&meshes).unwrap();
+        let eocd = &zip[zip.len() - 22..];
+        assert_eq!(&eocd[..4], &0x06054b50u32.to_le_bytes());
+        assert_eq!(u16::from_le_bytes([eocd[10], eocd[11]]), 3);
+        let model = String::from_utf8_lossy(&zip);
+        assert!(model.contains(r#"<object id="2" type="model">"#));
+        assert!(model.contains(r#"unit="millimeter""#));
+
+        let glb = MeshFormat::Glb.write(&meshes).unwrap();
+        assert_eq!(&glb[..4], b"glTF");
+        assert_eq!(
+            u32::from_le_bytes(glb[8..12].try_into().unwrap()) as usize,
+            glb.len()
+        );
+        assert_eq!(glb.len() % 4, 0);
+    }
+
+    #[test]
+    fn colors() {
+        let meshes = [
+            MeshObject {
+                mesh: tetrahedron(),
+                colors: Some(vec![
+                    [255, 0, 0],
+                    [0, 255, 0],
+                    [0, 0, 255],
+                    [0; 3],
+                ]),
+            },
+            MeshObject {
+                mesh: tetrahedron(),
+                colors: None,
+            },
+        ];
+
+        // Every vertex has a color, with a default for the uncolored object
+        let ply = MeshFormat::Ply.write(&meshes).unwrap();
+        let header_end = b"end_header\n";
+        let start = ply
+            .windows(header_end.len())
+            .position(|w| w == header_end)
+            .unwrap()
+            + header_end.len();
+        let header = std::str::from_utf8(&ply[..start]).unwrap();
+        assert!(header.contains("property uchar red\n"));
+        assert_eq!(ply.len() - start, 8 * 15 + 8 * 13);
+        assert_eq!(&ply[start + 12..start + 15], &[255, 0, 0]);
+        assert_eq!(&ply[start + 7 * 15 + 12..start + 8 * 15], &DEFAULT_COLOR);
+
+        // The color group takes the first resource ID
+        let zip = MeshFormat::ThreeMf.write(&meshes).unwrap();
+        let model = String::from_utf8_lossy(&zip);
+        assert!(model.contains(r#"<m:colorgroup id="1">"#));
+        assert!(model.contains(r##"<m:color color="#00FF00"/>"##));
+        assert!(
+            model
+                .contains(r#"<object id="2" type="model" pid="1" pindex="0">"#)
+        );
+        assert!(model.contains(r#"<object id="3" type="model">"#));
+        assert!(model.contains(
+            r#"v1="0" v2="2" v3="1" pid="1" p1="0" p2="2" p3="1"/>"#
+        ));
+
+        // Only the first mesh has a COLOR_0 attribute
+        let glb = MeshFormat::Glb.write(&meshes).unwrap();
+        let len = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+        let json: serde_json::Value =
+            serde_json::from_slice(&glb[20..20 + len]).unwrap();
+        let meshes = json["meshes"].as_array().unwrap();
+        assert_eq!(meshes.len(), 2);
+        let attrs = |i: usize| &meshes[i]["primitives"][0]["attributes"];
+        assert!(attrs(0).get("COLOR_0").is_some());
+        assert!(attrs(1).get("COLOR_0").is_none());
+        assert_eq!(json["nodes"].as_array().unwrap().len(), 2);
+    }
+}
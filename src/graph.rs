@@ -0,0 +1,375 @@
+//! Node-graph view of the world
+//!
+//! Each script or value block is drawn as a node.  Values declared with
+//! `input(..)` are ports on the left side of a node; values declared with
+//! `output(..)` are ports on the right.  An input whose expression is a bare
+//! reference to an upstream output is drawn as an edge.
+//!
+//! Dragging from an output port to an input port of a downstream block writes
+//! the corresponding expression into that input.
+use std::collections::HashMap;
+
+use crate::{
+    BlockResponse,
+    state::GraphState,
+    world::{Block, BlockIndex, IoValue, World},
+};
+
+const NODE_WIDTH: f32 = 150.0;
+const HEADER_HEIGHT: f32 = 22.0;
+const ROW_HEIGHT: f32 = 18.0;
+const PORT_RADIUS: f32 = 4.0;
+const COLUMN_SPACING: f32 = 190.0;
+const ROW_SPACING: f32 = 30.0;
+
+/// A port on a node
+struct Port {
+    /// Label drawn next to the port
+    label: String,
+    /// For input ports, the name of the input; for output ports, the
+    /// expression which refers to this output from a downstream block
+    key: String,
+}
+
+/// Ports and layout of a single node
+struct Node {
+    index: BlockIndex,
+    name: String,
+    inputs: Vec<Port>,
+    outputs: Vec<Port>,
+    rect: egui::Rect,
+}
+
+impl Node {
+    fn input_pos(&self, i: usize) -> egui::Pos2 {
+        egui::pos2(self.rect.left(), self.row_y(i))
+    }
+    fn output_pos(&self, i: usize) -> egui::Pos2 {
+        egui::pos2(self.rect.right(), self.row_y(i + self.inputs.len()))
+    }
+    fn row_y(&self, i: usize) -> f32 {
+        self.rect.top() + HEADER_HEIGHT + ROW_HEIGHT * (i as f32 + 0.5)
+    }
+}
+
+/// Returns the input and output ports for a block
+///
+/// Output port keys follow the same rules as the world's input scope: a block
+/// with a single output (or a single input and no outputs) is referred to by
+/// name; otherwise, each output is a field of an object map.
+fn block_ports(block: &Block) -> Option<(Vec<Port>, Vec<Port>)> {
+    match block {
+        Block::Script(s) => {
+            let mut inputs = vec![];
+            let mut outputs = vec![];
+            if let Some(data) = &s.data {
+                for (name, v) in &data.io_values {
+                    match v {
+                        IoValue::Input { .. } => inputs.push(name.clone()),
+                        IoValue::Output { .. } => outputs.push(name.clone()),
+                    }
+                }
+            } else {
+                inputs = s.inputs.keys().cloned().collect();
+                inputs.sort();
+            }
+            let outputs = if outputs.len() == 1 {
+                vec![Port {
+                    label: outputs.pop().unwrap(),
+                    key: s.name.clone(),
+                }]
+            } else if outputs.is_empty() && inputs.len() == 1 {
+                vec![Port {
+                    label: inputs[0].clone(),
+                    key: s.name.clone(),
+                }]
+            } else {
+                outputs
+                    .into_iter()
+                    .map(|o| Port {
+                        key: format!("{}.{o}", s.name),
+                        label: o,
+                    })
+                    .collect()
+            };
+            let inputs = inputs
+                .into_iter()
+                .map(|i| Port {
+                    label: i.clone(),
+                    key: i,
+                })
+                .collect();
+            Some((inputs, outputs))
+        }
+        Block::Value(v) => Some((
+            vec![Port {
+                label: "input".to_owned(),
+                key: String::new(),
+            }],
+            vec![Port {
+                label: "value".to_owned(),
+                key: v.name.clone(),
+            }],
+        )),
+        Block::Note(..) => None,
+    }
+}
+
+/// Returns the expression for a particular input of a block
+fn input_text<'a>(block: &'a Block, key: &str) -> Option<&'a str> {
+    match block {
+        Block::Script(s) => s.inputs.get(key).map(String::as_str),
+        Block::Value(v) => Some(&v.input),
+        Block::Note(..) => None,
+    }
+}
+
+/// Assigns positions to blocks which don't yet have one
+///
+/// Blocks are placed in columns based on their depth in the dependency graph.
+fn auto_layout(world: &World, state: &mut GraphState) {
+    let mut depth: HashMap<BlockIndex, usize> = HashMap::new();
+    let mut column_bottom: HashMap<usize, f32> = HashMap::new();
+    for p in state.nodes.values() {
+        let col = (p[0] / COLUMN_SPACING).round().max(0.0) as usize;
+        let y = column_bottom.entry(col).or_default();
+        *y = y.max(p[1] + HEADER_HEIGHT + ROW_HEIGHT * 2.0 + ROW_SPACING);
+    }
+    for index in &world.order {
+        if matches!(world.blocks[index], Block::Note(..)) {
+            continue;
+        }
+        let d = world
+            .references
+            .iter()
+            .filter(|r| r.from == *index)
+            .filter_map(|r| depth.get(&r.to))
+            .map(|d| d + 1)
+            .max()
+            .unwrap_or(0);
+        depth.insert(*index, d);
+        if state.nodes.contains_key(index) {
+            continue;
+        }
+        let rows = block_ports(&world.blocks[index])
+            .map(|(i, o)| i.len() + o.len())
+            .unwrap_or(0);
+        let y = column_bottom.entry(d).or_default();
+        state
+            .nodes
+            .insert(*index, [d as f32 * COLUMN_SPACING + 10.0, *y + 10.0]);
+        *y += HEADER_HEIGHT + ROW_HEIGHT * rows as f32 + ROW_SPACING;
+    }
+}
+
+/// Draws the node graph, filling the available space
+///
+/// Returns a list of per-block responses, for handling by the caller
+#[must_use]
+pub fn show(
+    ui: &mut egui::Ui,
+    world: &mut World,
+    state: &mut GraphState,
+) -> Vec<(BlockIndex, BlockResponse)> {
+    state.nodes.retain(|i, _| {
+        world
+            .blocks
+            .get(i)
+            .is_some_and(|b| !matches!(b, Block::Note(..)))
+    });
+    auto_layout(world, state);
+
+    let (canvas, painter) =
+        ui.allocate_painter(ui.available_size(), egui::Sense::drag());
+    let pan_id = ui.id().with("graph_pan");
+    let mut pan: egui::Vec2 =
+        ui.data(|d| d.get_temp(pan_id)).unwrap_or_default();
+    if canvas.dragged() {
+        pan += canvas.drag_delta();
+        ui.data_mut(|d| d.insert_temp(pan_id, pan));
+    }
+    let origin = canvas.rect.min + pan;
+
+    // Lay out nodes, in block order
+    let mut nodes = vec![];
+    for index in &world.order {
+        let block = &world.blocks[index];
+        let Some((inputs, outputs)) = block_ports(block) else {
+            continue;
+        };
+        let [x, y] = state.nodes[index];
+        let rows = (inputs.len() + outputs.len()) as f32;
+        let rect = egui::Rect::from_min_size(
+            origin + egui::vec2(x, y),
+            egui::vec2(NODE_WIDTH, HEADER_HEIGHT + ROW_HEIGHT * rows + 4.0),
+        );
+        nodes.push(Node {
+            index: *index,
+            name: block.name().to_owned(),
+            inputs,
+            outputs,
+            rect,
+        });
+    }
+
+    // Find edges, which connect an upstream output to a downstream input
+    let mut sources: HashMap<&str, (usize, usize)> = HashMap::new();
+    let mut edges = vec![];
+    for (n, node) in nodes.iter().enumerate() {
+        let block = &world.blocks[&node.index];
+        for (i, port) in node.inputs.iter().enumerate() {
+            if let Some(txt) = input_text(block, &port.key)
+                && let Some(&(m, o)) = sources.get(txt.trim())
+            {
+                edges.push((nodes[m].output_pos(o), node.input_pos(i)));
+            }
+        }
+        for (o, port) in node.outputs.iter().enumerate() {
+            sources.insert(&port.key, (n, o));
+        }
+    }
+
+    let visuals = ui.visuals().clone();
+    let edge_stroke =
+        egui::Stroke::new(2.0, visuals.widgets.inactive.fg_stroke.color);
+    for (a, b) in edges {
+        painter.add(bezier(a, b, edge_stroke));
+    }
+
+    let mut out = vec![];
+    let mut connect = None;
+    let font = egui::TextStyle::Body.resolve(ui.style());
+    let small = egui::FontId::proportional(font.size * 0.8);
+    for (n, node) in nodes.iter().enumerate() {
+        let block = &world.blocks[&node.index];
+        let id = node.index.id().with("graph_node");
+
+        // Drag the node by its header
+        let header = egui::Rect::from_min_size(
+            node.rect.min,
+            egui::vec2(NODE_WIDTH, HEADER_HEIGHT),
+        );
+        let r = ui.interact(header, id, egui::Sense::click_and_drag());
+        if r.dragged() {
+            let p = state.nodes.get_mut(&node.index).unwrap();
+            let d = r.drag_delta();
+            p[0] += d.x;
+            p[1] += d.y;
+        }
+        if r.drag_stopped() {
+            out.push((node.index, BlockResponse::MOVED));
+        }
+        if r.double_clicked() {
+            out.push((node.index, BlockResponse::TOGGLE_EDIT));
+        }
+
+        let stroke = if block.error().is_some() {
+            egui::Stroke::new(2.0, visuals.error_fg_color)
+        } else if r.hovered() || r.dragged() {
+            visuals.widgets.hovered.bg_stroke
+        } else {
+            visuals.widgets.noninteractive.bg_stroke
+        };
+        painter.rect_filled(node.rect, 4.0, visuals.window_fill);
+        painter.rect_filled(header, 4.0, visuals.faint_bg_color);
+        painter.rect_stroke(node.rect, 4.0, stroke, egui::StrokeKind::Inside);
+        let text_color = if block.is_disabled() {
+            visuals.weak_text_color()
+        } else {
+            visuals.strong_text_color()
+        };
+        painter.text(
+            header.left_center() + egui::vec2(6.0, 0.0),
+            egui::Align2::LEFT_CENTER,
+            &node.name,
+            font.clone(),
+            text_color,
+        );
+
+        let port_color = visuals.widgets.inactive.fg_stroke.color;
+        for (i, port) in node.inputs.iter().enumerate() {
+            let p = node.input_pos(i);
+            painter.circle_filled(p, PORT_RADIUS, port_color);
+            painter.text(
+                p + egui::vec2(PORT_RADIUS * 2.0, 0.0),
+                egui::Align2::LEFT_CENTER,
+                &port.label,
+                small.clone(),
+                visuals.text_color(),
+            );
+        }
+        for (o, port) in node.outputs.iter().enumerate() {
+            let p = node.output_pos(o);
+            let rect = egui::Rect::from_center_size(
+                p,
+                egui::Vec2::splat(PORT_RADIUS * 4.0),
+            );
+            let r = ui.interact(rect, id.with(o), egui::Sense::drag());
+            let color = if r.hovered() || r.dragged() {
+                visuals.widgets.hovered.fg_stroke.color
+            } else {
+                port_color
+            };
+            painter.circle_filled(p, PORT_RADIUS, color);
+            painter.text(
+                p - egui::vec2(PORT_RADIUS * 2.0, 0.0),
+                egui::Align2::RIGHT_CENTER,
+                &port.label,
+                small.clone(),
+                visuals.text_color(),
+            );
+
+            // Draw an in-progress edge, then connect it on release
+            let pointer = ui.ctx().pointer_latest_pos();
+            if r.dragged()
+                && let Some(pointer) = pointer
+            {
+                painter.add(bezier(
+                    p,
+                    pointer,
+                    egui::Stroke::new(2.0, visuals.selection.stroke.color),
+                ));
+            }
+            if r.drag_stopped()
+                && let Some(pointer) = pointer
+            {
+                // Only downstream blocks may refer to this output
+                let target = nodes[n + 1..].iter().find_map(|m| {
+                    (0..m.inputs.len())
+                        .find(|i| {
+                            m.input_pos(*i).distance(pointer)
+                                < PORT_RADIUS * 3.0
+                        })
+                        .map(|i| (m.index, m.inputs[i].key.clone()))
+                });
+                if let Some((target, input)) = target {
+                    connect = Some((target, input, port.key.clone()));
+                }
+            }
+        }
+    }
+
+    if let Some((target, input, expr)) = connect {
+        match world.blocks.get_mut(&target).unwrap() {
+            Block::Script(s) => {
+                s.inputs.insert(input, expr);
+            }
+            Block::Value(v) => v.input = expr,
+            Block::Note(..) => unreachable!("notes have no ports"),
+        }
+        out.push((target, BlockResponse::CHANGED));
+    }
+    out
+}
+
+/// Builds a horizontal Bézier curve between two points
+fn bezier(a: egui::Pos2, b: egui::Pos2, stroke: egui::Stroke) -> egui::Shape {
+    let dx = ((b.x - a.x).abs() / 2.0).max(30.0);
+    egui::epaint::CubicBezierShape::from_points_stroke(
+        [a, a + egui::vec2(dx, 0.0), b - egui::vec2(dx, 0.0), b],
+        false,
+        egui::Color32::TRANSPARENT,
+        stroke,
+    )
+    .into()
+}
@@ -1,15 +1,21 @@
 //! Functions to draw our data into an `egui` context
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 pub use crate::state::{Tab, TabMode};
 use crate::{
-    BlockResponse, MessageGenSender, ViewResponse, export,
+    BlockResponse, Message, MessageGenSender, ViewResponse, completion,
+    debugger::{self, DebugState},
+    docs::{self, Reference},
+    export,
+    formats::MeshFormat,
     platform::Notify,
     view::{self, ViewCanvas, ViewData, ViewImage, ViewMode2, ViewMode3},
     world::{
-        Block, BlockError, BlockIndex, IoValue, ScriptBlock, ValueBlock, World,
+        Block, BlockError, BlockIndex, IoValue, NameError, NoteBlock,
+        ScriptBlock, ShapeLibrary, ValueBlock, Warning, World,
     },
 };
+use egui::text::CCursor;
 use fidget::shapes::types::{Vec2, Vec3};
 
 pub struct WorldView<'a, N: Notify> {
@@ -18,6 +24,8 @@ pub struct WorldView<'a, N: Notify> {
     pub views: &'a mut HashMap<BlockIndex, ViewData>,
     pub out: &'a mut Vec<(BlockIndex, ViewResponse)>,
     pub tx: &'a MessageGenSender<N>,
+    pub reference: &'a Reference,
+    pub debug: &'a mut DebugState,
 }
 
 impl Tab {
@@ -42,6 +50,8 @@ impl<'a, N: Notify> egui_dock::TabViewer for WorldView<'a, N> {
         tab.index.id().with(match tab.mode {
             TabMode::Script => "tab_script",
             TabMode::View => "tab_view",
+            TabMode::Refs => "tab_refs",
+            TabMode::Dag => "tab_dag",
         })
     }
 
@@ -50,15 +60,22 @@ impl<'a, N: Notify> egui_dock::TabViewer for WorldView<'a, N> {
         match tab.mode {
             TabMode::Script => (),
             TabMode::View => name += " (view)",
+            TabMode::Refs => name += " (references)",
+            TabMode::Dag => name += " (expression graph)",
         };
         egui::WidgetText::from(&name)
     }
 
-    /// Draw a dock tab as either a script or view pane
+    /// Draw a dock tab as a script, view, reference, or expression graph pane
     fn ui(&mut self, ui: &mut egui::Ui, tab: &mut Tab) {
         let r = match tab.mode {
-            TabMode::Script => self.script_ui(ui, tab.index),
+            TabMode::Script => match &self.world[tab.index] {
+                Block::Note(..) => self.note_ui(ui, tab.index),
+                _ => self.script_ui(ui, tab.index),
+            },
             TabMode::View => self.view_ui(ui, tab.index),
+            TabMode::Refs => self.refs_ui(ui, tab.index),
+            TabMode::Dag => self.dag_ui(ui, tab.index),
         };
         if !r.is_empty() {
             self.out.push((tab.index, r))
@@ -364,19 +381,123 @@ impl<'a, N: Notify> WorldView<'a, N> {
         out
     }
 
+    fn refs_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        index: BlockIndex,
+    ) -> ViewResponse {
+        // Draws a clickable reference to a block, which focuses that block
+        let mut link =
+            |ui: &mut egui::Ui, block: BlockIndex, input: &Option<String>| {
+                let Some(b) = self.world.blocks.get(&block) else {
+                    return;
+                };
+                let text = match input {
+                    Some(input) => format!("{} (input `{input}`)", b.name()),
+                    None => b.name().to_owned(),
+                };
+                if ui.link(text).clicked() {
+                    self.out.push((block, ViewResponse::FOCUS_SCRIPT));
+                }
+            };
+        let reads = self
+            .world
+            .references
+            .iter()
+            .filter(|r| r.from == index)
+            .cloned()
+            .collect::<Vec<_>>();
+        let read_by = self
+            .world
+            .references
+            .iter()
+            .filter(|r| r.to == index)
+            .cloned()
+            .collect::<Vec<_>>();
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            ui.strong("Reads");
+            if reads.is_empty() {
+                ui.weak("no upstream blocks");
+            }
+            for r in &reads {
+                ui.horizontal(|ui| {
+                    link(ui, r.to, &None);
+                    if let Some(input) = &r.input {
+                        ui.weak(format!("from input `{input}`"));
+                    }
+                });
+            }
+            ui.separator();
+            ui.strong("Referenced by");
+            if read_by.is_empty() {
+                ui.weak("no downstream blocks");
+            }
+            for r in &read_by {
+                link(ui, r.from, &r.input);
+            }
+        });
+        ViewResponse::empty()
+    }
+
+    fn dag_ui(&mut self, ui: &mut egui::Ui, index: BlockIndex) -> ViewResponse {
+        let trees = self.world[index].tree_outputs();
+        crate::dag::show(ui, index.id().with("dag"), &trees);
+        ViewResponse::empty()
+    }
+
+    fn note_ui(
+        &mut self,
+        ui: &mut egui::Ui,
+        index: BlockIndex,
+    ) -> ViewResponse {
+        let Block::Note(block) = &mut self.world[index] else {
+            panic!("can't show note UI for non-note block");
+        };
+        let mut out = ViewResponse::empty();
+        let r = ui.add(
+            egui::TextEdit::multiline(&mut block.text)
+                .desired_rows(10)
+                .lock_focus(true)
+                .desired_width(f32::INFINITY),
+        );
+        if r.changed() {
+            out |= ViewResponse::CHANGED;
+        }
+        ui.separator();
+        egui::ScrollArea::vertical().show(ui, |ui| {
+            crate::markdown::show(ui, &block.text);
+        });
+        out
+    }
+
     fn script_ui(
         &mut self,
         ui: &mut egui::Ui,
         index: BlockIndex,
     ) -> ViewResponse {
+        let upstream = completion::upstream_names(self.world, index);
+        if let Some(cmd) = debugger::controls(ui, self.debug, index)
+            && let Some((id, task)) = self.debug.start(self.world, index, cmd)
+        {
+            let tx = self.tx.clone();
+            task.spawn(move |status| tx.send(Message::Debug { id, status }));
+        }
+        debugger::locals_panel(ui, self.debug, index);
         let Block::Script(block) = &mut self.world[index] else {
             panic!("can't show script UI for non-script block");
         };
         let mut out = ViewResponse::empty();
         let theme =
             egui_extras::syntax_highlighting::CodeTheme::from_style(ui.style());
+        let err = block.data.as_ref().and_then(|d| d.error.as_ref());
+        let err_span = err
+            .and_then(|e| e.position())
+            .and_then(|pos| error_span(&block.script, pos));
+        let err_text = err.map(|e| e.print_chain());
+        let focus_err = take_error_focus(ui.ctx(), index);
         let out = ui
             .with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
+                debugger::gutter(ui, self.debug, index, &block.script);
                 draw_line_numbers(ui, index, block);
 
                 let mut layouter =
@@ -395,17 +516,74 @@ impl<'a, N: Notify> WorldView<'a, N> {
                         layout_job.wrap.max_width = f32::INFINITY;
                         ui.fonts_mut(|f| f.layout_job(layout_job))
                     };
-                let r = ui.add(
-                    egui::TextEdit::multiline(&mut block.script)
-                        .font(egui::TextStyle::Monospace) // for cursor height
-                        .code_editor()
-                        .desired_rows(10)
-                        .lock_focus(true)
-                        .desired_width(f32::INFINITY)
-                        .layouter(&mut layouter),
+                let text_edit_id = index.id().with("script_edit");
+                if completion::pre_edit(ui, text_edit_id, &mut block.script) {
+                    out |= ViewResponse::CHANGED;
+                }
+                let mut r = egui::TextEdit::multiline(&mut block.script)
+                    .id(text_edit_id)
+                    .font(egui::TextStyle::Monospace) // for cursor height
+                    .code_editor()
+                    .desired_rows(10)
+                    .lock_focus(true)
+                    .desired_width(f32::INFINITY)
+                    .layouter(&mut layouter)
+                    .show(ui);
+                completion::post_edit(
+                    ui,
+                    text_edit_id,
+                    self.reference,
+                    &upstream,
+                    &block.script,
+                    &r,
+                );
+                debugger::highlight_line(
+                    ui,
+                    self.debug,
+                    index,
+                    &block.script,
+                    &r,
                 );
-                if r.changed() {
+                let mut err_hovered = false;
+                if r.response.changed() {
                     out |= ViewResponse::CHANGED;
+                } else if let Some((start, end)) = err_span {
+                    // Underline the error, show its text on hover, and move
+                    // the cursor to it if requested.
+                    let a = r.galley.pos_from_cursor(CCursor::new(start));
+                    let b = r.galley.pos_from_cursor(CCursor::new(end));
+                    let rect = egui::Rect::from_min_max(a.left_top(), b.max)
+                        .translate(r.galley_pos.to_vec2());
+                    draw_squiggle(ui, rect);
+                    if let Some(p) = r.response.hover_pos()
+                        && rect.expand(2.0).contains(p)
+                        && let Some(err_text) = err_text
+                    {
+                        err_hovered = true;
+                        egui::show_tooltip_at_pointer(
+                            ui.ctx(),
+                            ui.layer_id(),
+                            text_edit_id.with("error"),
+                            |ui| ui.label(err_text),
+                        );
+                    }
+                    if focus_err {
+                        r.state.cursor.set_char_range(Some(
+                            egui::text::CCursorRange::one(CCursor::new(start)),
+                        ));
+                        r.state.store(ui.ctx(), r.response.id);
+                        r.response.request_focus();
+                        ui.scroll_to_rect(rect, Some(egui::Align::Center));
+                    }
+                }
+                if !err_hovered {
+                    docs::hover(
+                        ui,
+                        text_edit_id,
+                        self.reference,
+                        &block.script,
+                        &r,
+                    );
                 }
                 out
             })
@@ -421,7 +599,13 @@ impl<'a, N: Notify> WorldView<'a, N> {
                 );
             }
             if let Some(e) = &block_data.error
-                && matches!(e, BlockError::Parse(..) | BlockError::Eval(..))
+                && matches!(
+                    e,
+                    BlockError::Parse(..)
+                        | BlockError::Eval(..)
+                        | BlockError::Assertion { .. }
+                        | BlockError::Upstream { .. }
+                )
             {
                 ui.label("Errors");
                 let mut text = e.print_chain();
@@ -440,6 +624,77 @@ impl<'a, N: Notify> WorldView<'a, N> {
     }
 }
 
+/// Requests that the block's script editor move its cursor to the error
+pub fn request_error_focus(ctx: &egui::Context, index: BlockIndex) {
+    ctx.data_mut(|d| d.insert_temp(index.id().with("error_focus"), true));
+}
+
+/// Checks (and clears) a request from [`request_error_focus`]
+fn take_error_focus(ctx: &egui::Context, index: BlockIndex) -> bool {
+    ctx.data_mut(|d| d.remove_temp::<bool>(index.id().with("error_focus")))
+        .unwrap_or(false)
+}
+
+/// Requests that a value block's input be focused and scrolled into view
+pub fn request_value_focus(ctx: &egui::Context, index: BlockIndex) {
+    ctx.data_mut(|d| d.insert_temp(index.id().with("value_focus"), true));
+}
+
+/// Checks (and clears) a request from [`request_value_focus`]
+fn take_value_focus(ctx: &egui::Context, index: BlockIndex) -> bool {
+    ctx.data_mut(|d| d.remove_temp::<bool>(index.id().with("value_focus")))
+        .unwrap_or(false)
+}
+
+/// Converts an error position into a range of characters within the script
+///
+/// The range covers the identifier at the error position, or a single
+/// character if the error isn't on an identifier.
+fn error_span(script: &str, pos: rhai::Position) -> Option<(usize, usize)> {
+    let line = pos.line()?;
+    let col = pos.position().unwrap_or(1).saturating_sub(1);
+    let mut start = 0;
+    for (i, text) in script.split('\n').enumerate() {
+        let len = text.chars().count();
+        if i + 1 == line {
+            let col = col.min(len);
+            let word = text
+                .chars()
+                .skip(col)
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .count();
+            return Some(if word > 0 {
+                (start + col, start + col + word)
+            } else if col < len {
+                (start + col, start + col + 1)
+            } else {
+                // The error is at the end of the line, so underline the last
+                // character (if present)
+                (start + col.saturating_sub(1), start + col)
+            });
+        }
+        start += len + 1;
+    }
+    None
+}
+
+/// Draws a wavy underline along the bottom of the given rectangle
+fn draw_squiggle(ui: &egui::Ui, rect: egui::Rect) {
+    let width = rect.width().max(4.0);
+    let step = 2.0;
+    let y = rect.bottom() - 1.0;
+    let points = (0..=(width / step).ceil() as usize)
+        .map(|i| {
+            let dy = if i % 2 == 0 { 0.0 } else { -step };
+            egui::pos2(rect.left() + i as f32 * step, y + dy)
+        })
+        .collect();
+    ui.painter().add(egui::Shape::line(
+        points,
+        egui::Stroke::new(1.0, ui.visuals().error_fg_color),
+    ));
+}
+
 #[derive(Copy, Clone, Default)]
 struct NameEdit {
     needs_focus: bool,
@@ -451,6 +706,8 @@ pub struct BlockUiFlags {
     pub is_last: bool,
     pub is_dragged: bool,
     pub is_view_open: Option<bool>,
+    /// Thumbnail of the block's view, shown if the block is collapsed
+    pub thumbnail: Option<egui::TextureId>,
 }
 
 fn draw_line_numbers(
@@ -470,11 +727,11 @@ fn draw_line_numbers(
     let width = max_indent as f32
         * ui.text_style_height(&egui::TextStyle::Monospace)
         * 0.5;
-    let err_line = match block.data.as_ref().and_then(|e| e.error.as_ref()) {
-        Some(BlockError::Eval(e)) => Some(e.position()),
-        Some(BlockError::Parse(e)) => Some(e.position()),
-        _ => None,
-    };
+    let err_line = block
+        .data
+        .as_ref()
+        .and_then(|e| e.error.as_ref())
+        .and_then(|e| e.position());
 
     // cached LayoutJob computation for line numbers
     #[derive(Default)]
@@ -563,12 +820,15 @@ fn draw_line_numbers(
 /// Draws a draggable block within a [`egui_dnd`] context
 ///
 /// Returns a [`BlockResponse`] based on button presses
+#[allow(clippy::too_many_arguments)]
 pub fn draggable_block(
     ui: &mut egui::Ui,
     index: BlockIndex,
     block: &mut Block,
     flags: BlockUiFlags,
     mat: nalgebra::Matrix4<f32>,
+    names: &HashSet<String>,
+    library: &ShapeLibrary,
     handle: egui_dnd::Handle,
 ) -> BlockResponse {
     let mut response = BlockResponse::empty();
@@ -581,27 +841,38 @@ pub fn draggable_block(
                 .is_some_and(|s| !s.io_values.is_empty() || s.export.is_some())
             {
                 use egui::collapsing_header::CollapsingState;
-                CollapsingState::load_with_default_open(
+                let state = CollapsingState::load_with_default_open(
                     ui.ctx(),
                     index.id(),
                     true,
-                )
-                .show_header(ui, |ui| {
-                    response = draggable_script_block_header(
-                        ui, index, block, flags, handle,
-                    )
-                })
-                .body_unindented(|ui| {
-                    response |= script_block_body(ui, index, block, mat);
-                    if !flags.is_last {
-                        ui.separator();
-                    }
-                });
+                );
+                let mut header_flags = flags;
+                if state.is_open() {
+                    header_flags.thumbnail = None;
+                }
+                state
+                    .show_header(ui, |ui| {
+                        response = draggable_script_block_header(
+                            ui,
+                            index,
+                            block,
+                            header_flags,
+                            names,
+                            library,
+                            handle,
+                        )
+                    })
+                    .body_unindented(|ui| {
+                        response |= script_block_body(ui, index, block, mat);
+                        if !flags.is_last {
+                            ui.separator();
+                        }
+                    });
             } else {
                 ui.horizontal(|ui| {
                     ui.add_space(padding);
                     response = draggable_script_block_header(
-                        ui, index, block, flags, handle,
+                        ui, index, block, flags, names, library, handle,
                     )
                 });
             }
@@ -610,11 +881,33 @@ pub fn draggable_block(
         Block::Value(block) => {
             ui.horizontal(|ui| {
                 ui.add_space(padding);
-                response =
-                    draggable_value_block(ui, index, block, flags, handle, mat);
+                response = draggable_value_block(
+                    ui, index, block, flags, names, library, handle, mat,
+                );
             });
             response
         }
+        Block::Note(block) => {
+            use egui::collapsing_header::CollapsingState;
+            CollapsingState::load_with_default_open(ui.ctx(), index.id(), true)
+                .show_header(ui, |ui| {
+                    response = draggable_note_block_header(
+                        ui, index, block, flags, names, library, handle,
+                    )
+                })
+                .body_unindented(|ui| {
+                    ui.horizontal_top(|ui| {
+                        ui.add_space(padding);
+                        ui.vertical(|ui| {
+                            crate::markdown::show(ui, &block.text);
+                        });
+                    });
+                    if !flags.is_last {
+                        ui.separator();
+                    }
+                });
+            response
+        }
     }
 }
 
@@ -640,6 +933,7 @@ fn script_block_body(
             min,
             max,
             feature_size,
+            format,
             ..
         }) => {
             // Calculate settings here in the UI, so that we can disable the
@@ -647,8 +941,20 @@ fn script_block_body(
             // also check them in the actual mesh export function.
             let s = export::mesh_settings(*min, *max, *feature_size);
             let enabled = block_data.error.is_none() && s.is_ok();
+            let mut selected = export_format(ui.ctx(), index, *format);
             let r = ui.horizontal(|ui| {
                 ui.add_space(padding);
+                egui::ComboBox::from_id_salt(index.id().with("format_combo"))
+                    .selected_text(selected.description())
+                    .show_ui(ui, |ui| {
+                        for f in MeshFormat::ALL {
+                            ui.selectable_value(
+                                &mut selected,
+                                f,
+                                f.description(),
+                            );
+                        }
+                    });
                 ui.add_enabled_ui(enabled, |ui| {
                     ui.add_sized(
                         [ui.available_width(), 25.0],
@@ -657,6 +963,12 @@ fn script_block_body(
                 })
                 .inner
             });
+            ui.data_mut(|d| {
+                d.insert_temp(
+                    index.id().with("export_format"),
+                    (*format, selected),
+                )
+            });
             match s {
                 Ok(s) => {
                     ui.horizontal(|ui| {
@@ -744,15 +1056,9 @@ fn script_block_io(
 ) -> bool {
     ui.label(name);
     match value {
-        IoValue::Output { text, .. } => {
-            // XXX awkward cloning here, but passing a &mut &str discards most
-            // of the text box formatting, leaving just a label.
-            let mut text = text.clone();
-            ui.add_enabled(
-                false,
-                egui::TextEdit::singleline(&mut text)
-                    .desired_width(f32::INFINITY),
-            );
+        IoValue::Output { value, text, .. } => {
+            let id = index.id().with("inspect").with(name);
+            crate::inspector::show(ui, id, value, text);
             false
         }
         IoValue::Input { value, .. } => {
@@ -790,6 +1096,11 @@ fn try_parse_vec3(s: &str) -> Option<Vec3> {
 
 /// Draws an editable input field for a block input
 #[must_use]
+/// Returns the id of the text edit for a block's input (or value)
+fn input_edit_id(index: BlockIndex, name: &str) -> egui::Id {
+    index.id().with("input_edit").with(name)
+}
+
 fn block_io_input(
     ui: &mut egui::Ui,
     index: BlockIndex,
@@ -798,7 +1109,7 @@ fn block_io_input(
     err: Option<&str>,
     mat: nalgebra::Matrix4<f32>,
 ) -> bool {
-    let input_id = index.id().with("input_edit").with(name);
+    let input_id = input_edit_id(index, name);
     ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
         if let Some(err) = err {
             ui.colored_label(ui.style().visuals.error_fg_color, WARN)
@@ -837,6 +1148,8 @@ fn draggable_script_block_header(
     index: BlockIndex,
     block: &mut ScriptBlock,
     flags: BlockUiFlags,
+    names: &HashSet<String>,
+    library: &ShapeLibrary,
     handle: egui_dnd::Handle,
 ) -> BlockResponse {
     // Editable object name
@@ -850,6 +1163,15 @@ fn draggable_script_block_header(
         if ui.button(TRASH).clicked() {
             response |= BlockResponse::DELETE;
         }
+        let mut inputs = block.inputs.keys().collect::<Vec<_>>();
+        inputs.sort();
+        if disable_button(
+            ui,
+            &mut block.disabled,
+            Some((&mut block.passthrough, inputs.as_slice())),
+        ) {
+            response |= BlockResponse::CHANGED;
+        }
         if ui
             .add(egui::Button::new(PENCIL).selected(flags.is_open))
             .clicked()
@@ -861,12 +1183,26 @@ fn draggable_script_block_header(
         {
             response = BlockResponse::TOGGLE_VIEW;
         }
+        if let Some(t) = flags.thumbnail {
+            crate::thumbnail::show(ui, t);
+        }
         if let Some(block_data) = &block.data {
+            if block_data.stale {
+                stale_badge(ui);
+            }
             let e = match block_data.error.as_ref() {
+                Some(BlockError::Upstream { name, source, .. }) => {
+                    if upstream_badge(ui, name, source) {
+                        response |= BlockResponse::FOCUS_UPSTREAM;
+                    }
+                    None
+                }
                 Some(e) => {
                     let clickable = matches!(
                         e,
-                        BlockError::Parse(..) | BlockError::Eval(..)
+                        BlockError::Parse(..)
+                            | BlockError::Eval(..)
+                            | BlockError::Assertion { .. }
                     );
                     Some((e.print_chain(), clickable))
                 }
@@ -891,25 +1227,180 @@ fn draggable_script_block_header(
                 if r.clicked() {
                     response |= BlockResponse::FOCUS_ERR;
                 }
+            } else if !block_data.warnings.is_empty() {
+                warnings_badge(ui, &block_data.warnings);
             }
         }
         ui.with_layout(
             egui::Layout::left_to_right(egui::Align::Center),
             |ui| {
-                if block_name(ui, index, &mut block.name).changed {
+                if block.disabled {
+                    dim_text(ui);
+                }
+                let r = block_name(
+                    ui,
+                    index,
+                    &mut block.name,
+                    true,
+                    names,
+                    library,
+                );
+                if r.changed {
                     response |= BlockResponse::CHANGED;
                 }
+                if let Some(a) = r.action {
+                    response |= BlockResponse::from(a);
+                }
             },
         );
     });
     response
 }
 
+fn draggable_note_block_header(
+    ui: &mut egui::Ui,
+    index: BlockIndex,
+    block: &mut NoteBlock,
+    flags: BlockUiFlags,
+    names: &HashSet<String>,
+    library: &ShapeLibrary,
+    handle: egui_dnd::Handle,
+) -> BlockResponse {
+    let mut response = BlockResponse::empty();
+    ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+        ui.add_space(5.0);
+        handle.show_drag_cursor_on_hover(false).ui(ui, |ui| {
+            ui.add(egui::Button::new(DRAG_UP_DOWN).selected(flags.is_dragged));
+        });
+        if ui.button(TRASH).clicked() {
+            response |= BlockResponse::DELETE;
+        }
+        if ui
+            .add(egui::Button::new(PENCIL).selected(flags.is_open))
+            .clicked()
+        {
+            response = BlockResponse::TOGGLE_EDIT;
+        }
+        ui.with_layout(
+            egui::Layout::left_to_right(egui::Align::Center),
+            |ui| {
+                let r = block_name(
+                    ui,
+                    index,
+                    &mut block.name,
+                    false,
+                    names,
+                    library,
+                );
+                if r.changed {
+                    response |= BlockResponse::CHANGED;
+                }
+                if let Some(a) = r.action {
+                    response |= BlockResponse::from(a);
+                }
+            },
+        );
+    });
+    response
+}
+
+/// Draws a badge marking a block as blocked by an upstream failure
+///
+/// Returns `true` if the badge was clicked
+fn upstream_badge(
+    ui: &mut egui::Ui,
+    name: &str,
+    source: &rhai::EvalAltResult,
+) -> bool {
+    ui.add(
+        egui::Label::new(
+            egui::RichText::new(BLOCKED).color(ui.visuals().warn_fg_color),
+        )
+        .sense(egui::Sense::click()),
+    )
+    .on_hover_text(format!(
+        "blocked: upstream block `{name}` failed\n\
+         this block's error: {source}\n\
+         (click to show the upstream error)"
+    ))
+    .clicked()
+}
+
+/// Draws a badge listing a block's (non-fatal) warnings
+fn warnings_badge(ui: &mut egui::Ui, warnings: &[Warning]) {
+    ui.label(egui::RichText::new(WARN).color(crate::color::YELLOW))
+        .on_hover_ui(|ui| {
+            for w in warnings {
+                if let Some(line) = w.pos.line() {
+                    ui.label(format!("line {line}: {}", w.message));
+                } else {
+                    ui.label(&w.message);
+                }
+            }
+        });
+}
+
+/// Draws a badge marking a block as using its last good value
+fn stale_badge(ui: &mut egui::Ui) {
+    ui.label(egui::RichText::new(STALE).color(ui.visuals().warn_fg_color))
+        .on_hover_text("block failed; using its last good value");
+}
+
+/// Draws a toggle button to disable (bypass) a block
+///
+/// If `passthrough` is provided, then right-clicking the button opens a menu
+/// to pick which input is forwarded as the block's value while disabled.
+///
+/// Returns `true` if either setting has changed
+fn disable_button(
+    ui: &mut egui::Ui,
+    disabled: &mut bool,
+    passthrough: Option<(&mut Option<String>, &[&String])>,
+) -> bool {
+    let mut changed = false;
+    let r = ui
+        .add(egui::Button::new(POWER).selected(*disabled))
+        .on_hover_text(if *disabled {
+            "Enable block"
+        } else {
+            "Disable block"
+        });
+    if r.clicked() {
+        *disabled = !*disabled;
+        changed = true;
+    }
+    if let Some((passthrough, inputs)) = passthrough {
+        r.context_menu(|ui| {
+            ui.label("When disabled, pass through:");
+            changed |= ui.radio_value(passthrough, None, "nothing").changed();
+            for name in inputs {
+                changed |= ui
+                    .radio_value(
+                        passthrough,
+                        Some(name.to_string()),
+                        name.as_str(),
+                    )
+                    .changed();
+            }
+        });
+    }
+    changed
+}
+
+/// Draws subsequent text in this `Ui` with a dimmed color
+fn dim_text(ui: &mut egui::Ui) {
+    let c = ui.visuals().weak_text_color();
+    ui.visuals_mut().override_text_color = Some(c);
+}
+
+#[allow(clippy::too_many_arguments)]
 fn draggable_value_block(
     ui: &mut egui::Ui,
     index: BlockIndex,
     block: &mut ValueBlock,
     flags: BlockUiFlags,
+    names: &HashSet<String>,
+    library: &ShapeLibrary,
     handle: egui_dnd::Handle,
     mat: nalgebra::Matrix4<f32>,
 ) -> BlockResponse {
@@ -924,18 +1415,46 @@ fn draggable_value_block(
         if ui.button(TRASH).clicked() {
             response |= BlockResponse::DELETE;
         }
+        if disable_button(ui, &mut block.disabled, None) {
+            response |= BlockResponse::CHANGED;
+        }
         if let Some(view) = flags.is_view_open
             && ui.add(egui::Button::new(EYE).selected(view)).clicked()
         {
             response = BlockResponse::TOGGLE_VIEW;
         }
+        if let Some(t) = flags.thumbnail {
+            crate::thumbnail::show(ui, t);
+        }
         ui.with_layout(
             egui::Layout::left_to_right(egui::Align::Center),
             |ui| {
-                let r = block_name(ui, index, &mut block.name);
+                if let Some(BlockError::Upstream { name, source, .. }) =
+                    block.data.as_ref().and_then(|d| d.output.as_ref().err())
+                    && upstream_badge(ui, name, source)
+                {
+                    response |= BlockResponse::FOCUS_UPSTREAM;
+                }
+                if block.data.as_ref().is_some_and(|d| d.stale) {
+                    stale_badge(ui);
+                }
+                if block.disabled {
+                    dim_text(ui);
+                }
+                let r = block_name(
+                    ui,
+                    index,
+                    &mut block.name,
+                    true,
+                    names,
+                    library,
+                );
                 if r.changed {
                     response |= BlockResponse::CHANGED;
                 }
+                if let Some(a) = r.action {
+                    response |= BlockResponse::from(a);
+                }
                 // Only show input editor if the name isn't being edited
                 if !r.open
                     && block_io_input(
@@ -946,15 +1465,24 @@ fn draggable_value_block(
                         block
                             .data
                             .as_ref()
-                            .and_then(|d| {
-                                d.output.as_ref().err().map(|e| e.print_chain())
+                            .and_then(|d| d.output.as_ref().err())
+                            .filter(|e| {
+                                // Upstream errors get their own badge
+                                !matches!(e, BlockError::Upstream { .. })
                             })
+                            .map(|e| e.print_chain())
                             .as_deref(),
                         mat,
                     )
                 {
                     response |= BlockResponse::CHANGED;
                 }
+                if take_value_focus(ui.ctx(), index) {
+                    ui.memory_mut(|m| {
+                        m.request_focus(input_edit_id(index, ""))
+                    });
+                    ui.scroll_to_rect(ui.min_rect(), Some(egui::Align::Center));
+                }
             },
         );
     });
@@ -964,19 +1492,51 @@ fn draggable_value_block(
 struct NameResult {
     changed: bool,
     open: bool,
+    /// Action picked from the name's context menu, if any
+    action: Option<NameAction>,
+}
+
+/// Action picked from a block name's context menu
+#[derive(Copy, Clone)]
+enum NameAction {
+    /// A rename (updating references) was requested; see [`take_rename`]
+    Rename,
+    /// The reference list was requested
+    ShowRefs,
+    /// The expression graph was requested
+    ShowDag,
+    /// A new block was requested after this one; see [`take_insert`]
+    InsertAfter,
+}
+
+impl From<NameAction> for BlockResponse {
+    fn from(a: NameAction) -> Self {
+        match a {
+            NameAction::Rename => BlockResponse::RENAME,
+            NameAction::ShowRefs => BlockResponse::SHOW_REFS,
+            NameAction::ShowDag => BlockResponse::SHOW_DAG,
+            NameAction::InsertAfter => BlockResponse::INSERT_AFTER,
+        }
+    }
 }
 
 /// Draws the name of a block, editable with a double-click
 ///
-/// Returns `true` if the name has changed, `false` otherwise
+/// Returns whether the name has changed or is being edited, along with any
+/// action picked from its context menu.
 fn block_name(
     ui: &mut egui::Ui,
     index: BlockIndex,
     name: &mut String,
+    show_refs: bool,
+    names: &HashSet<String>,
+    library: &ShapeLibrary,
 ) -> NameResult {
     let id = index.id();
     let mut changed = false;
     let mut open = false;
+    let mut action = None;
+    let mut menu_open = false;
     match ui.memory(|mem| mem.data.get_temp(id)) {
         Some(NameEdit { needs_focus }) => {
             let text_edit_id = egui::Id::new(index).with("name_edit");
@@ -1031,9 +1591,143 @@ fn block_name(
                     mem.data.insert_temp(id, NameEdit { needs_focus: true })
                 });
             }
+            let menu = response.context_menu(|ui| {
+                if rename_menu(ui, index, name, names) {
+                    action = Some(NameAction::Rename);
+                }
+                if show_refs {
+                    ui.separator();
+                    if ui.button("Show references").clicked() {
+                        action = Some(NameAction::ShowRefs);
+                        ui.close();
+                    }
+                    if ui.button("Show expression graph").clicked() {
+                        action = Some(NameAction::ShowDag);
+                        ui.close();
+                    }
+                }
+                ui.separator();
+                if insert_menu(ui, index, library) {
+                    action = Some(NameAction::InsertAfter);
+                }
+            });
+            menu_open = menu.is_some();
+        }
+    }
+    // Discard any half-typed rename when the menu is closed, so that it starts
+    // from the current name when reopened
+    if !menu_open {
+        ui.data_mut(|d| d.remove_temp::<String>(index.id().with("rename")));
+    }
+    NameResult {
+        changed,
+        open,
+        action,
+    }
+}
+
+/// Draws a menu to rename a block, updating references to it
+///
+/// The new name must be a valid identifier and must not be used by any other
+/// block in `names`.
+///
+/// Returns `true` if the rename was confirmed, in which case the new name can
+/// be retrieved with [`take_rename`].
+fn rename_menu(
+    ui: &mut egui::Ui,
+    index: BlockIndex,
+    name: &str,
+    names: &HashSet<String>,
+) -> bool {
+    let id = index.id().with("rename");
+    let mut text = ui.data_mut(|d| {
+        d.get_temp_mut_or_insert_with(id, || name.to_owned())
+            .clone()
+    });
+    ui.label("Rename and update references:");
+    let r = ui.text_edit_singleline(&mut text);
+    let error = if text == name {
+        None
+    } else if !rhai::is_valid_identifier(&text) {
+        Some(NameError::InvalidIdentifier)
+    } else if names.contains(&text) {
+        Some(NameError::DuplicateName)
+    } else {
+        None
+    };
+    if let Some(e) = &error {
+        ui.label(
+            egui::RichText::new(e.to_string())
+                .color(ui.style().visuals.error_fg_color),
+        );
+    }
+    let valid = text != name && error.is_none();
+    let enter = r.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+    let clicked = ui.add_enabled(valid, egui::Button::new("Rename")).clicked();
+    ui.data_mut(|d| d.insert_temp(id, text));
+    if valid && (clicked || enter) {
+        ui.close();
+        true
+    } else {
+        false
+    }
+}
+
+/// Returns the mesh format selected next to a block's export button
+///
+/// The format passed to `export_mesh` in the script is the default; the user's
+/// selection is discarded if the script's format changes.
+pub fn export_format(
+    ctx: &egui::Context,
+    index: BlockIndex,
+    default: MeshFormat,
+) -> MeshFormat {
+    ctx.data(|d| {
+        d.get_temp::<(MeshFormat, MeshFormat)>(index.id().with("export_format"))
+    })
+    .filter(|(script, _)| *script == default)
+    .map_or(default, |(_, f)| f)
+}
+
+/// Takes the name from a rename requested with [`BlockResponse::RENAME`]
+pub fn take_rename(ctx: &egui::Context, index: BlockIndex) -> Option<String> {
+    ctx.data_mut(|d| d.remove_temp::<String>(index.id().with("rename")))
+}
+
+/// Draws a submenu to insert a new block after the given block
+///
+/// Returns `true` if a shape was selected, in which case its index in the
+/// library can be retrieved with [`take_insert`].
+fn insert_menu(
+    ui: &mut egui::Ui,
+    index: BlockIndex,
+    library: &ShapeLibrary,
+) -> bool {
+    let mut out = None;
+    ui.menu_button("Insert after", |ui| {
+        let mut prev_category = None;
+        for (i, s) in library.shapes.iter().enumerate() {
+            if prev_category.is_some_and(|c| c != s.category) {
+                ui.separator();
+            }
+            if ui.button(&s.name).clicked() {
+                out = Some(i);
+                ui.close();
+            }
+            prev_category = Some(s.category);
         }
+    });
+    if let Some(i) = out {
+        ui.data_mut(|d| d.insert_temp(index.id().with("insert"), i));
+        true
+    } else {
+        false
     }
-    NameResult { changed, open }
+}
+
+/// Takes the shape from a request with [`BlockResponse::INSERT_AFTER`]
+pub fn take_insert(ctx: &egui::Context, index: BlockIndex) -> Option<usize> {
+    ctx.data_mut(|d| d.remove_temp::<usize>(index.id().with("insert")))
 }
 
 enum DraggableInputValue {
@@ -1140,6 +1834,16 @@ impl<'a> DockStateEditor<'a> {
             self.update_view();
         }
     }
+    /// Opens the script tab (if needed) and makes it active
+    pub fn focus_script(&mut self) {
+        if let Some((surface, node, tab)) = self.script {
+            self.tree[surface].set_active_tab(node, tab);
+        } else {
+            self.tree.push_to_focused_leaf(self.script_index());
+            self.update_script();
+            self.update_view();
+        }
+    }
     fn update_script(&mut self) {
         self.script = self.tree.find_tab(&self.script_index());
     }
@@ -1172,14 +1876,47 @@ impl<'a> DockStateEditor<'a> {
     fn view_index(&self) -> Tab {
         Tab::view(self.index)
     }
+
+    /// Opens an auxiliary tab for this block (if needed) and makes it active
+    ///
+    /// This is used for tabs other than the script and view, which aren't
+    /// tracked by the editor.
+    pub fn focus_tab(&mut self, mode: TabMode) {
+        let tab = Tab {
+            index: self.index,
+            mode,
+        };
+        if let Some((surface, node, tab)) = self.tree.find_tab(&tab) {
+            self.tree[surface].set_active_tab(node, tab);
+        } else {
+            self.tree.push_to_focused_leaf(tab);
+            self.update_script();
+            self.update_view();
+        }
+    }
+    /// Closes an auxiliary tab for this block, if it's open
+    pub fn close_tab(&mut self, mode: TabMode) {
+        let tab = Tab {
+            index: self.index,
+            mode,
+        };
+        if let Some(loc) = self.tree.find_tab(&tab) {
+            self.tree.remove_tab(loc).unwrap();
+            self.update_script();
+            self.update_view();
+        }
+    }
 }
 
 // Unicode symbols from Nerd Fonts, see https://www.nerdfonts.com/cheat-sheet
+const BLOCKED: &str = "\u{f05e}";
 const DRAG_UP_DOWN: &str = "\u{f0e79}";
 const ERROR: &str = "\u{ea87}";
 const EYE: &str = "\u{f441}";
 const HOURGLASS: &str = "\u{f252}";
 const PENCIL: &str = "\u{f03eb}";
+const POWER: &str = "\u{f011}";
+const STALE: &str = "\u{f017}";
 const TRASH: &str = "\u{f48e}";
 
 pub const CAMERA: &str = "\u{f03d}";
@@ -0,0 +1,259 @@
+//! Structured display of script values
+//!
+//! Maps and arrays are drawn as collapsible trees (paginated if large); math
+//! trees, scenes, colors, and vectors have dedicated summaries.  Summaries of
+//! trees require walking the whole tree, so they're cached between frames.
+use crate::world::{Color, Drawable, Scene, TreeSummary};
+use fidget::{
+    context::Tree,
+    shapes::types::{Vec2, Vec3, Vec4},
+};
+
+/// Number of array or map items shown per page
+const PAGE_SIZE: usize = 25;
+
+/// Cached value, along with the trees it was computed from
+///
+/// Holding clones of the trees keeps their allocations alive, so comparing
+/// tree pointers is enough to check whether the cache is still valid.
+#[derive(Clone)]
+struct Cached<T>(Vec<Tree>, T);
+
+/// Returns a value computed from the given trees, using a cache if possible
+fn cached<T: Clone + Send + Sync + 'static>(
+    ui: &egui::Ui,
+    id: egui::Id,
+    trees: &[&Tree],
+    f: impl FnOnce() -> T,
+) -> T {
+    if let Some(Cached(ts, v)) = ui.data(|d| d.get_temp::<Cached<T>>(id))
+        && ts.len() == trees.len()
+        && ts.iter().zip(trees).all(|(a, b)| std::ptr::eq(&**a, &***b))
+    {
+        return v;
+    }
+    let v = f();
+    let ts = trees.iter().map(|t| (*t).clone()).collect();
+    ui.data_mut(|d| d.insert_temp(id, Cached(ts, v.clone())));
+    v
+}
+
+/// Draws a (cached) summary of a math tree
+fn tree_ui(ui: &mut egui::Ui, id: egui::Id, tree: &Tree) {
+    let s = cached(ui, id, &[tree], || TreeSummary::new(tree).to_string());
+    ui.monospace(s);
+}
+
+/// Draws a value
+///
+/// `text` is the value's printed form, which is used as a fallback for types
+/// without a dedicated display.
+pub fn show(
+    ui: &mut egui::Ui,
+    id: egui::Id,
+    value: &rhai::Dynamic,
+    text: &str,
+) {
+    ui.vertical(|ui| {
+        if !value_ui(ui, id, value) {
+            // XXX awkward cloning here, but passing a &mut &str discards most
+            // of the text box formatting, leaving just a label.
+            let mut text = text.to_owned();
+            ui.add_enabled(
+                false,
+                egui::TextEdit::singleline(&mut text)
+                    .desired_width(f32::INFINITY),
+            );
+        }
+    });
+}
+
+/// Draws a value, returning `false` if it has no dedicated display
+fn value_ui(ui: &mut egui::Ui, id: egui::Id, value: &rhai::Dynamic) -> bool {
+    if value.is_array() {
+        let array = value.read_lock::<rhai::Array>().unwrap();
+        collection(ui, id, "array", array.len(), |ui, i| {
+            item(ui, id.with(i), &format!("[{i}]"), &array[i]);
+        });
+    } else if value.is_map() {
+        let map = value.read_lock::<rhai::Map>().unwrap();
+        let entries = map.iter().collect::<Vec<_>>();
+        collection(ui, id, "map", entries.len(), |ui, i| {
+            let (k, v) = entries[i];
+            item(ui, id.with(k.as_str()), k, v);
+        });
+    } else if let Some(tree) = value.clone().try_cast::<Tree>() {
+        tree_ui(ui, id, &tree);
+    } else if let Some(scene) = value.clone().try_cast::<Scene>() {
+        scene_ui(ui, id, &scene);
+    } else if let Some(d) = value.clone().try_cast::<Drawable>() {
+        drawable_ui(ui, id, &d);
+    } else if let Some(c) = value.clone().try_cast::<Color>() {
+        color_ui(ui, id, &c);
+    } else if let Some(v) = value.clone().try_cast::<Vec2>() {
+        components(ui, &[("x", v.x), ("y", v.y)]);
+    } else if let Some(v) = value.clone().try_cast::<Vec3>() {
+        components(ui, &[("x", v.x), ("y", v.y), ("z", v.z)]);
+    } else if let Some(v) = value.clone().try_cast::<Vec4>() {
+        components(ui, &[("x", v.x), ("y", v.y), ("z", v.z), ("w", v.w)]);
+    } else if value.is_unit() {
+        ui.weak("()");
+    } else if value.is_string() {
+        ui.monospace(format!("{value:?}"));
+    } else if value.is_int()
+        || value.is_float()
+        || value.is_bool()
+        || value.is_char()
+    {
+        ui.monospace(value.to_string());
+    } else {
+        return false;
+    }
+    true
+}
+
+/// Draws a single array item or map entry
+fn item(ui: &mut egui::Ui, id: egui::Id, label: &str, value: &rhai::Dynamic) {
+    ui.horizontal_top(|ui| {
+        ui.label(egui::RichText::new(label).monospace().weak());
+        if !value_ui(ui, id, value) {
+            ui.monospace(format!("<{}>", value.type_name()));
+        }
+    });
+}
+
+/// Draws a collapsible, paginated collection of items
+fn collection(
+    ui: &mut egui::Ui,
+    id: egui::Id,
+    kind: &str,
+    len: usize,
+    mut item: impl FnMut(&mut egui::Ui, usize),
+) {
+    egui::CollapsingHeader::new(format!("{kind} ({len})"))
+        .id_salt(id)
+        .show(ui, |ui| {
+            if len == 0 {
+                ui.weak("(empty)");
+                return;
+            }
+            let page_id = id.with("page");
+            let pages = len.div_ceil(PAGE_SIZE);
+            let mut page = ui
+                .data(|d| d.get_temp::<usize>(page_id))
+                .unwrap_or(0)
+                .min(pages - 1);
+            if pages > 1 {
+                ui.horizontal(|ui| {
+                    if ui
+                        .add_enabled(page > 0, egui::Button::new("◀"))
+                        .clicked()
+                    {
+                        page -= 1;
+                    }
+                    ui.label(format!(
+                        "{}–{} of {len}",
+                        page * PAGE_SIZE + 1,
+                        ((page + 1) * PAGE_SIZE).min(len)
+                    ));
+                    if ui
+                        .add_enabled(page + 1 < pages, egui::Button::new("▶"))
+                        .clicked()
+                    {
+                        page += 1;
+                    }
+                });
+                ui.data_mut(|d| d.insert_temp(page_id, page));
+            }
+            for i in page * PAGE_SIZE..((page + 1) * PAGE_SIZE).min(len) {
+                item(ui, i);
+            }
+        });
+}
+
+/// Draws vector components as (disabled) numeric fields
+fn components(ui: &mut egui::Ui, values: &[(&str, f64)]) {
+    ui.horizontal(|ui| {
+        for (name, v) in values {
+            let mut v = *v;
+            ui.add_enabled(
+                false,
+                egui::DragValue::new(&mut v).prefix(format!("{name}: ")),
+            );
+        }
+    });
+}
+
+fn scene_ui(ui: &mut egui::Ui, id: egui::Id, scene: &Scene) {
+    egui::CollapsingHeader::new(format!("scene ({})", scene.shapes.len()))
+        .id_salt(id)
+        .default_open(true)
+        .show(ui, |ui| {
+            for (i, d) in scene.shapes.iter().enumerate() {
+                drawable_ui(ui, id.with(i), d);
+            }
+        });
+}
+
+fn drawable_ui(ui: &mut egui::Ui, id: egui::Id, d: &Drawable) {
+    ui.horizontal(|ui| {
+        match &d.color {
+            Some(c) => color_ui(ui, id.with("color"), c),
+            None => {
+                ui.weak("(no color)");
+            }
+        }
+        tree_ui(ui, id.with("tree"), &d.tree);
+    });
+}
+
+/// Draws a color swatch, using the color's value at the origin
+///
+/// Colors may vary per-pixel; the hover text explains whether they do.
+fn color_ui(ui: &mut egui::Ui, id: egui::Id, c: &Color) {
+    let (channels, is_hsl) = match c {
+        Color::Rgb(rgb) => (rgb, false),
+        Color::Hsl(hsl) => (hsl, true),
+    };
+    let trees = channels.each_ref();
+    let ([r, g, b, _], constant) = cached(ui, id, &trees, || {
+        let mut ctx = fidget::Context::new();
+        let mut constant = true;
+        let mut values = [0u8; 4];
+        for (i, (t, v)) in channels.iter().zip(&mut values).enumerate() {
+            let node = ctx.import(t);
+            // HSL hue is always a tree, because it's wrapped into the 0-1 range
+            constant &= is_hsl && i == 0
+                || matches!(&**t, fidget::context::TreeOp::Const(..));
+            let f = ctx.eval_xyz(node, 0.0, 0.0, 0.0).unwrap_or(0.0);
+            *v = (f.clamp(0.0, 1.0) * 255.0).round() as u8;
+        }
+        values[3] = u8::MAX;
+        let rgb = if is_hsl {
+            crate::render::hsl_to_rgb(values)
+        } else {
+            values
+        };
+        (rgb, constant)
+    });
+    let (rect, response) = ui.allocate_exact_size(
+        egui::Vec2::splat(ui.spacing().interact_size.y * 0.8),
+        egui::Sense::hover(),
+    );
+    ui.painter()
+        .rect_filled(rect, 2.0, egui::Color32::from_rgb(r, g, b));
+    ui.painter().rect_stroke(
+        rect,
+        2.0,
+        ui.visuals().widgets.noninteractive.bg_stroke,
+        egui::StrokeKind::Inside,
+    );
+    let kind = if is_hsl { "hsl" } else { "rgb" };
+    if constant {
+        response.on_hover_text(format!("{kind} color"));
+    } else {
+        response.on_hover_text(format!(
+            "{kind} color, evaluated per-pixel (shown at the origin)"
+        ));
+    }
+}
@@ -4,11 +4,22 @@ use log::{debug, error, info, warn};
 use std::collections::{HashMap, HashSet};
 use web_time::Instant;
 
+mod analysis;
+mod completion;
+mod dag;
+mod debugger;
+mod docs;
 mod export;
+mod formats;
+mod graph;
 mod gui;
+mod inspector;
+mod markdown;
 mod painters;
 mod render;
+mod search;
 mod state;
+mod thumbnail;
 mod view;
 mod world;
 
@@ -225,6 +236,25 @@ enum Message {
     },
     CancelLoad,
     ExportComplete(Result<Vec<u8>, export::ExportError>),
+    Debug {
+        id: u64,
+        status: world::DebugStatus,
+    },
+    Thumbnail {
+        block: BlockIndex,
+        id: u64,
+        image: Option<egui::ColorImage>,
+    },
+    ZoomToFit {
+        block: BlockIndex,
+        lower: fidget::shapes::types::Vec3,
+        upper: fidget::shapes::types::Vec3,
+    },
+    MassProperties {
+        id: u64,
+        result: Result<world::MassProperties, String>,
+    },
+    FillBounds(world::BoundsFill),
 }
 
 /// Message sender for worker tasks
@@ -344,6 +374,7 @@ pub(crate) struct App<P: Platform> {
     data: World,
     generation: std::sync::Arc<std::sync::atomic::AtomicU64>,
     library: world::ShapeLibrary,
+    reference: docs::Reference,
     examples: Vec<Example>,
     undo: state::Undo,
 
@@ -351,6 +382,15 @@ pub(crate) struct App<P: Platform> {
     tree: egui_dock::DockState<gui::Tab>,
     syntax: egui_extras::syntax_highlighting::SyntectSettings,
     views: HashMap<BlockIndex, view::ViewData>,
+    thumbnails: thumbnail::Thumbnails,
+    graph: state::GraphState,
+
+    /// Shows the node graph instead of the block list
+    show_graph: bool,
+    search: search::SearchState,
+    analysis: analysis::AnalysisState,
+    reference_window: docs::ReferenceState,
+    debugger: debugger::DebugState,
 
     rx: MessageReceiver<P::Notify>,
     script_state: ScriptState,
@@ -373,7 +413,11 @@ pub(crate) struct App<P: Platform> {
 
 enum ScriptState {
     Done,
-    Running { changed: bool },
+    Running {
+        /// The world has changed since this rebuild started
+        changed: bool,
+        cancel: fidget::render::CancelToken,
+    },
 }
 
 #[derive(Clone)]
@@ -429,6 +473,17 @@ enum Modal<E: std::fmt::Debug> {
     },
     WaitForLoad,
     About,
+    /// A drag-and-drop reorder would break blocks which depend on each other
+    Reorder {
+        /// Names of blocks which would be broken
+        broken: Vec<String>,
+        /// Order requested by the drag-and-drop
+        order: Vec<BlockIndex>,
+        /// Alternate order, with dependencies moved as well
+        fixed: Vec<BlockIndex>,
+        /// The block was dragged upwards
+        up: bool,
+    },
 }
 
 impl<E: std::fmt::Debug> std::fmt::Debug for Modal<E> {
@@ -462,6 +517,11 @@ impl<E: std::fmt::Debug> std::fmt::Debug for Modal<E> {
                 .field("target", &format!("{target:?}"))
                 .finish(),
             Modal::About => f.debug_struct("About").finish(),
+            Modal::Reorder { broken, up, .. } => f
+                .debug_struct("Reorder")
+                .field("broken", broken)
+                .field("up", up)
+                .finish(),
         }
     }
 }
@@ -544,16 +604,27 @@ impl<P: Platform> App<P> {
 
         let rx = platform.take_rx_channel();
         let data = World::new();
-        let undo = state::Undo::new(&data);
+        let graph = state::GraphState::default();
+        let undo = state::Undo::new(&data, &graph);
+        let library = world::ShapeLibrary::build();
+        let reference = docs::Reference::new(&library);
         Self {
             data,
-            library: world::ShapeLibrary::build(),
+            library,
+            reference,
             examples,
             tree: egui_dock::DockState::new(vec![]),
             script_state: ScriptState::Done,
             undo,
             syntax,
             views: HashMap::new(),
+            thumbnails: thumbnail::Thumbnails::default(),
+            graph,
+            show_graph: false,
+            search: search::SearchState::default(),
+            analysis: analysis::AnalysisState::default(),
+            reference_window: docs::ReferenceState::default(),
+            debugger: debugger::DebugState::default(),
             meta: state::Metadata::default(),
             generation: std::sync::Arc::new(0.into()),
             platform,
@@ -569,7 +640,13 @@ impl<P: Platform> App<P> {
 
     /// Gets our current `AppState`
     fn get_state(&self) -> AppState {
-        AppState::new(&self.data, &self.views, &self.tree, &self.meta)
+        AppState::new(
+            &self.data,
+            &self.views,
+            &self.tree,
+            &self.meta,
+            &self.graph,
+        )
     }
 
     /// Loads an example by name, returning `false` if not found
@@ -587,6 +664,7 @@ impl<P: Platform> App<P> {
         self.data = state.world.into();
         self.tree = state.dock;
         self.meta = state.meta;
+        self.graph = state.graph;
         self.views = state
             .views
             .into_iter()
@@ -594,27 +672,84 @@ impl<P: Platform> App<P> {
             .collect();
         self.generation
             .store(0, std::sync::atomic::Ordering::Relaxed);
-        self.undo = state::Undo::new(&self.data);
+        self.undo = state::Undo::new(&self.data, &self.graph);
+        self.debugger = debugger::DebugState::default();
+        self.thumbnails = thumbnail::Thumbnails::default();
+        self.analysis.reset();
+        if let ScriptState::Running { cancel, .. } = &self.script_state {
+            cancel.cancel();
+        }
+        self.script_state = ScriptState::Done;
         self.rx.increment_gen(); // orphan previous tasks
     }
 
     pub fn start_world_rebuild(&mut self) {
-        if let ScriptState::Running { changed } = &mut self.script_state {
+        if let ScriptState::Running { changed, cancel } = &mut self.script_state
+        {
+            // The running rebuild is out of date, so cut short any slow
+            // functions; we'll start a new rebuild when it finishes.
             *changed = true;
+            cancel.cancel();
             return;
         }
 
         // Send the world to a worker thread for re-evaluation
         let world = WorldState::from(&self.data);
+        let last_good = self.data.last_good().clone();
         let tx = self.rx.sender_with_gen();
+        let cancel = fidget::render::CancelToken::new();
+        let cancel_ = cancel.clone();
         rayon::spawn(move || {
-            let world = World::from(world);
+            let world = World::build(world, last_good, cancel_);
             tx.send(Message::RebuildWorld { world })
         });
-        self.script_state = ScriptState::Running { changed: false };
+        self.script_state = ScriptState::Running {
+            changed: false,
+            cancel,
+        };
+    }
+
+    /// Computes the bounds of a block's scene on a worker thread, then fits
+    /// the block's view to them
+    fn start_zoom_to_fit(&self, block: BlockIndex) {
+        let Some(view) =
+            self.data.blocks.get(&block).and_then(|b| b.get_view())
+        else {
+            return;
+        };
+        let scene = view.scene.clone();
+        let tx = self.rx.sender_with_gen();
+        rayon::spawn(move || match world::scene_bounds(&scene) {
+            Ok(Some((lower, upper))) => tx.send(Message::ZoomToFit {
+                block,
+                lower,
+                upper,
+            }),
+            Ok(None) => (),
+            Err(e) => warn!("could not compute scene bounds: {e}"),
+        });
     }
 
-    fn draw_menu(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+    /// Finds bounds for a newly inserted block on a worker thread, then fills
+    /// them into the block's inputs
+    fn start_fill_bounds(&mut self) {
+        let Some(request) = self.data.take_bounds_request() else {
+            return;
+        };
+        let tx = self.rx.sender_with_gen();
+        rayon::spawn(move || {
+            if let Some(fill) = request.run() {
+                tx.send(Message::FillBounds(fill))
+            }
+        });
+    }
+
+    /// Draws the menu bar
+    ///
+    /// Returns `true` if anything in the world has changed
+    #[must_use]
+    fn draw_menu(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
         egui::MenuBar::new().ui(ui, |ui| {
             ui.menu_button("File", |ui| {
                 if ui.button("\u{ea7b} New").clicked() {
@@ -686,6 +821,24 @@ impl<P: Platform> App<P> {
                         self.on_redo();
                     }
                 });
+                ui.separator();
+                if ui.button("Find and replace").clicked() {
+                    self.search.show();
+                }
+                if ui.button("Mass properties").clicked() {
+                    self.analysis.open = true;
+                }
+                ui.separator();
+                changed |= ui
+                    .checkbox(
+                        &mut self.data.keep_stale,
+                        "Keep last good values",
+                    )
+                    .on_hover_text(
+                        "When a block fails, downstream blocks \
+                         use its last successful value",
+                    )
+                    .changed();
             });
             ui.menu_button("Examples", |ui| {
                 let mut load_state = None;
@@ -711,6 +864,9 @@ impl<P: Platform> App<P> {
                 }
             });
             ui.menu_button("Help", |ui| {
+                if ui.button("Function reference").clicked() {
+                    self.reference_window.open = true;
+                }
                 if ui.button("\u{eb32} About").clicked() {
                     self.on_about();
                 }
@@ -737,6 +893,7 @@ impl<P: Platform> App<P> {
                 );
             }
         });
+        changed
     }
 
     /// Draws a list of blocks into a caller-provided left panel
@@ -770,11 +927,20 @@ impl<P: Platform> App<P> {
                 });
             ui.separator();
             ui.with_layout(egui::Layout::top_down(egui::Align::LEFT), |ui| {
-                egui::ScrollArea::vertical().show(ui, |ui| {
-                    if self.block_list(ui) {
-                        changed = true;
-                    }
+                ui.horizontal(|ui| {
+                    ui.selectable_value(&mut self.show_graph, false, "List");
+                    ui.selectable_value(&mut self.show_graph, true, "Graph");
                 });
+                ui.separator();
+                if self.show_graph {
+                    changed |= self.block_graph(ui);
+                } else {
+                    egui::ScrollArea::vertical().show(ui, |ui| {
+                        if self.block_list(ui) {
+                            changed = true;
+                        }
+                    });
+                }
             });
         });
         changed
@@ -801,6 +967,7 @@ impl<P: Platform> App<P> {
                 | Modal::SaveLocal { .. }
                 | Modal::OpenLocal { .. }
                 | Modal::About
+                | Modal::Reorder { .. }
         ) && escape_pressed)
             || (matches!(modal, Modal::Error { .. } | Modal::About)
                 && enter_pressed)
@@ -1014,6 +1181,63 @@ impl<P: Platform> App<P> {
                     }
                 }
             }
+            Modal::Reorder {
+                broken,
+                order,
+                fixed,
+                up,
+            } => {
+                let msg = if broken.len() == 1 {
+                    "This move would break 1 block, which would come before \
+                     a block that it uses:"
+                        .to_owned()
+                } else {
+                    format!(
+                        "This move would break {} blocks, which would come \
+                         before blocks that they use:",
+                        broken.len()
+                    )
+                };
+                let fix = if *up {
+                    "Move dependencies too"
+                } else {
+                    "Move dependent blocks too"
+                };
+                let mut next = None;
+                let r = draw_modal_window(ctx, "Reorder", dialog_size, |ui| {
+                    ui.label(msg);
+                    ui.add_space(5.0);
+                    for b in broken.iter() {
+                        ui.horizontal(|ui| {
+                            ui.colored_label(
+                                ui.style().visuals.warn_fg_color,
+                                gui::WARN,
+                            );
+                            ui.label(b);
+                        });
+                    }
+                    ui.add_space(5.0);
+                    ui.horizontal(|ui| {
+                        if ui.button(fix).clicked() {
+                            next = Some(std::mem::take(fixed));
+                        } else if ui.button("Move anyway").clicked() {
+                            next = Some(std::mem::take(order));
+                        }
+                        ui.button("Cancel").clicked()
+                    })
+                    .inner
+                });
+                if let Some(order) = next {
+                    // The reorder is recorded as a single undo step
+                    self.undo.checkpoint(&self.data, &self.graph);
+                    self.data.order = order;
+                    self.undo.checkpoint(&self.data, &self.graph);
+                    self.modal = None;
+                    self.start_world_rebuild();
+                } else if r {
+                    self.modal = None;
+                }
+            }
             Modal::Error { title, message } => {
                 let r = draw_modal_window(ctx, title, dialog_size, |ui| {
                     ui.add(
@@ -1079,7 +1303,7 @@ impl<P: Platform> App<P> {
                         state.meta.name = Some(name.clone());
                         self.platform
                             .save_to_local_storage(&name, &state.serialize());
-                        self.undo.mark_saved(state.world);
+                        self.undo.mark_saved(state.world, state.graph);
                         self.meta.name = Some(name);
                         self.local_name_confirmed = true;
                     }
@@ -1207,6 +1431,8 @@ impl<P: Platform> App<P> {
             views: &mut self.views,
             tx: &self.rx.sender_with_gen(),
             out: &mut io_out,
+            reference: &self.reference,
+            debug: &mut self.debugger,
         };
         egui_dock::DockArea::new(&mut self.tree)
             .style(egui_dock::Style::from_egui(ctx.style().as_ref()))
@@ -1217,7 +1443,10 @@ impl<P: Platform> App<P> {
         for (block, flags) in io_out {
             for f in flags.iter() {
                 match f {
-                    ViewResponse::FOCUS_ERR => {
+                    ViewResponse::FOCUS_ERR | ViewResponse::FOCUS_SCRIPT => {
+                        if f == ViewResponse::FOCUS_ERR {
+                            gui::request_error_focus(ctx, block);
+                        }
                         let tab = gui::Tab::script(block);
                         let tab_location = self.tree.find_tab(&tab);
                         if let Some(tab_location) = tab_location {
@@ -1232,6 +1461,7 @@ impl<P: Platform> App<P> {
                     ViewResponse::REDRAW => {
                         self.request_repaint = true;
                     }
+                    ViewResponse::ZOOM_TO_FIT => self.start_zoom_to_fit(block),
                     _ => panic!("invalid flag"),
                 }
             }
@@ -1242,11 +1472,14 @@ impl<P: Platform> App<P> {
     #[must_use]
     fn draw_ui(&mut self, ctx: &egui::Context) -> bool {
         let mut changed = false;
-        egui::Panel::top("menu").show(ctx, |ui| {
-            ui.add_space(2.0);
-            self.draw_menu(ctx, ui);
-            ui.add_space(2.0);
-        });
+        changed |= egui::Panel::top("menu")
+            .show(ctx, |ui| {
+                ui.add_space(2.0);
+                let changed = self.draw_menu(ctx, ui);
+                ui.add_space(2.0);
+                changed
+            })
+            .inner;
 
         changed |= egui::Panel::left("left_panel")
             .min_size(250.0)
@@ -1266,6 +1499,21 @@ impl<P: Platform> App<P> {
             })
             .inner;
 
+        if self.search.open {
+            changed |= self.draw_search(ctx);
+        }
+        if self.reference_window.open {
+            docs::show(ctx, &mut self.reference_window, &self.reference);
+        }
+        if self.analysis.open {
+            analysis::show(
+                ctx,
+                &mut self.analysis,
+                &self.data,
+                &self.rx.sender_with_gen(),
+            );
+        }
+
         // Draw optional modals
         self.draw_modal(ctx, size);
 
@@ -1298,7 +1546,7 @@ impl<P: Platform> App<P> {
     fn download_file(&mut self, f: &str, state: AppState) {
         let json_str = state.serialize();
         match self.platform.download_file(f, json_str.as_bytes()) {
-            None => self.undo.mark_saved(state.world),
+            None => self.undo.mark_saved(state.world, state.graph),
             Some(d) => self.modal = Some(d),
         }
     }
@@ -1314,9 +1562,10 @@ impl<P: Platform> App<P> {
     fn on_redo(&mut self) {
         if self.modal.is_some() {
             warn!("ignoring redo while modal is active");
-        } else if let Some(prev) = self.undo.redo(&self.data) {
+        } else if let Some((prev, graph)) = self.undo.redo(&self.data) {
             debug!("got redo state");
-            let prev = prev.clone();
+            let (prev, graph) = (prev.clone(), graph.clone());
+            self.graph = graph;
             self.restore_world_state(prev);
         } else {
             // XXX show a dialog or something?
@@ -1356,7 +1605,7 @@ impl<P: Platform> App<P> {
         if self.platform.can_save() {
             let state = self.get_state();
             if self.platform.save_as(&state).unwrap() {
-                self.undo.mark_saved(state.world);
+                self.undo.mark_saved(state.world, state.graph);
             }
         } else {
             self.on_save_local()
@@ -1387,7 +1636,7 @@ impl<P: Platform> App<P> {
             if self.local_name_confirmed {
                 self.platform
                     .save_to_local_storage(name, &state.serialize());
-                self.undo.mark_saved(state.world);
+                self.undo.mark_saved(state.world, state.graph);
             } else {
                 let files = self.platform.list_local_storage();
                 if files.contains(name) {
@@ -1399,7 +1648,7 @@ impl<P: Platform> App<P> {
                 } else {
                     self.platform
                         .save_to_local_storage(name, &state.serialize());
-                    self.undo.mark_saved(state.world);
+                    self.undo.mark_saved(state.world, state.graph);
                 }
             }
         } else {
@@ -1429,7 +1678,7 @@ impl<P: Platform> App<P> {
         } else if self.platform.can_save() {
             let state = self.get_state();
             if self.platform.save(&state).unwrap() {
-                self.undo.mark_saved(state.world);
+                self.undo.mark_saved(state.world, state.graph);
             }
         } else {
             self.on_save_local()
@@ -1447,9 +1696,12 @@ impl<P: Platform> App<P> {
     fn on_undo(&mut self) {
         if self.modal.is_some() {
             warn!("ignoring undo while modal is active");
-        } else if let Some(prev) = self.undo.undo(&self.data) {
+        } else if let Some((prev, graph)) =
+            self.undo.undo(&self.data, &self.graph)
+        {
             debug!("got undo state");
-            let prev = prev.clone();
+            let (prev, graph) = (prev.clone(), graph.clone());
+            self.graph = graph;
             self.restore_world_state(prev);
         } else {
             // XXX show a dialog or something?
@@ -1484,6 +1736,7 @@ impl<P: Platform> eframe::App for App<P> {
         self.intercept_quit(ctx);
 
         if self.draw_ui(ctx) {
+            self.start_fill_bounds();
             self.start_world_rebuild();
         }
 
@@ -1538,9 +1791,25 @@ impl<P: Platform> App<P> {
         let mut to_delete = HashSet::new();
         let mut changed = false;
         let mut to_export = None;
+        let mut to_focus = None;
+        let mut to_rename = None;
+        let mut to_insert = None;
         let last = self.data.order.last().cloned();
 
         let block_mats = self.characteristic_matrices();
+        let names = self
+            .data
+            .blocks
+            .values()
+            .map(|b| b.name().to_owned())
+            .collect::<HashSet<_>>();
+        let prev_order = self.data.order.clone();
+        self.thumbnails.update(
+            ui.ctx(),
+            &self.data,
+            &self.views,
+            &self.rx.sender_with_gen(),
+        );
 
         // XXX there is a drag-and-drop implementation that's built into egui,
         // see `egui_demo_lib/src/demo/drag_and_drop.rs`
@@ -1554,9 +1823,14 @@ impl<P: Platform> App<P> {
                 // If we have an open view but block is (1) valid and (2) no
                 // longer defines a view, then close the view.  We'll leave the
                 // view open if the block isn't valid, to prevent views from
-                // flicking in and out as a script is edited.
+                // flicking in and out as a script is edited, or if the block is
+                // disabled, so that its view comes back when it's re-enabled.
                 let block_defines_view = block.has_view();
-                if tree.has_view() && block.is_valid() && !block_defines_view {
+                if tree.has_view()
+                    && block.is_valid()
+                    && !block.is_disabled()
+                    && !block_defines_view
+                {
                     tree.close_view();
                 }
 
@@ -1571,14 +1845,31 @@ impl<P: Platform> App<P> {
                     } else {
                         None
                     },
+                    thumbnail: self.thumbnails.get(*index),
                 };
                 let mat = block_mats[index];
-                let r =
-                    gui::draggable_block(ui, *index, block, flags, mat, handle);
+                let r = gui::draggable_block(
+                    ui,
+                    *index,
+                    block,
+                    flags,
+                    mat,
+                    &names,
+                    &self.library,
+                    handle,
+                );
                 if r.contains(BlockResponse::DELETE) {
                     to_delete.insert(*index);
                     tree.close_view();
                     tree.close_script();
+                    tree.close_tab(gui::TabMode::Refs);
+                    tree.close_tab(gui::TabMode::Dag);
+                }
+                if r.contains(BlockResponse::SHOW_REFS) {
+                    tree.focus_tab(gui::TabMode::Refs);
+                }
+                if r.contains(BlockResponse::SHOW_DAG) {
+                    tree.focus_tab(gui::TabMode::Dag);
                 }
                 if r.contains(BlockResponse::TOGGLE_EDIT) {
                     tree.toggle_script();
@@ -1587,7 +1878,24 @@ impl<P: Platform> App<P> {
                     tree.toggle_view();
                 }
                 if r.contains(BlockResponse::FOCUS_ERR) {
-                    tree.toggle_script();
+                    tree.focus_script();
+                    gui::request_error_focus(ui.ctx(), *index);
+                }
+                if r.contains(BlockResponse::FOCUS_UPSTREAM)
+                    && let Some(world::BlockError::Upstream { root, .. }) =
+                        block.error()
+                {
+                    to_focus = Some(*root);
+                }
+                if r.contains(BlockResponse::RENAME)
+                    && let Some(name) = gui::take_rename(ui.ctx(), *index)
+                {
+                    to_rename = Some((*index, name));
+                }
+                if r.contains(BlockResponse::INSERT_AFTER)
+                    && let Some(shape) = gui::take_insert(ui.ctx(), *index)
+                {
+                    to_insert = Some((*index, shape));
                 }
                 if r.contains(BlockResponse::EXPORT) {
                     let world::Block::Script(s) = block else {
@@ -1602,43 +1910,99 @@ impl<P: Platform> App<P> {
                     if to_export.is_some() {
                         error!("multiple export requests found");
                     } else {
-                        to_export = Some(e.clone());
+                        // The user may pick a different format in the UI
+                        let mut e = e.clone();
+                        if let world::ExportRequest::Mesh { format, .. } =
+                            &mut e
+                        {
+                            *format =
+                                gui::export_format(ui.ctx(), *index, *format);
+                        }
+                        to_export = Some(e);
                     }
                 }
                 changed |= r.contains(BlockResponse::CHANGED);
             },
         );
-        if r.final_update().is_some() {
-            changed = true;
+        if let Some(u) = r.final_update() {
+            // Check whether the move breaks any blocks.  We revert the order
+            // here, then ask the user what to do.
+            let order = std::mem::replace(&mut self.data.order, prev_order);
+            let (broken, fixed) = self.data.check_order(&order, u.to < u.from);
+            if broken.is_empty() || self.modal.is_some() {
+                self.data.order = order;
+                changed = true;
+            } else {
+                self.modal = Some(Modal::Reorder {
+                    broken,
+                    order,
+                    fixed,
+                    up: u.to < u.from,
+                });
+            }
         }
 
         // Post-processing: edit blocks based on button presses
+        if let Some(root) = to_focus
+            && self.data.blocks.contains_key(&root)
+        {
+            gui::DockStateEditor::new(root, &mut self.tree).focus_script();
+            gui::request_error_focus(ui.ctx(), root);
+        }
+        if let Some((index, name)) = to_rename {
+            // The rename is recorded as a single undo step
+            self.undo.checkpoint(&self.data, &self.graph);
+            let skipped = self.data.rename_block(index, &name);
+            self.undo.checkpoint(&self.data, &self.graph);
+            changed = true;
+            if !skipped.is_empty() && self.modal.is_none() {
+                self.modal = Some(Modal::Error {
+                    title: "Rename incomplete".to_owned(),
+                    message: format!(
+                        "These blocks define a local variable with the old \
+                         name, so their references were not updated: {}",
+                        skipped.join(", ")
+                    ),
+                });
+            }
+        }
+        if let Some((index, shape)) = to_insert {
+            // Insertion and rewiring are recorded as a single undo step
+            self.undo.checkpoint(&self.data, &self.graph);
+            let b = &self.library.shapes[shape];
+            if self.data.new_block_after(b, Some(index)) {
+                self.undo.checkpoint(&self.data, &self.graph);
+                changed = true;
+            }
+        }
         changed |= self.data.retain(|index| !to_delete.contains(index));
         self.views.retain(|index, _| !to_delete.contains(index));
 
         match to_export {
             Some(world::ExportRequest::Mesh {
-                tree,
+                scene,
                 min,
                 max,
                 feature_size,
+                format,
             }) => {
                 if self.modal.is_none()
                     && let Some(target) = self.platform.export_name(
                         self.meta.name.as_deref(),
-                        "mesh",
-                        "stl",
+                        format.description(),
+                        format.extension(),
                     )
                 {
                     let cancel = fidget::render::CancelToken::new();
                     let cancel_ = cancel.clone();
                     let tx = self.rx.sender();
                     rayon::spawn(move || {
-                        let r = export::build_stl(
-                            tree,
+                        let r = export::build_mesh_file(
+                            scene,
                             min,
                             max,
                             feature_size,
+                            format,
                             cancel_,
                         );
                         tx.send(Message::ExportComplete(r))
@@ -1679,6 +2043,55 @@ impl<P: Platform> App<P> {
         changed
     }
 
+    /// Draws the find-and-replace window
+    ///
+    /// Returns `true` if anything in the world has changed
+    #[must_use]
+    fn draw_search(&mut self, ctx: &egui::Context) -> bool {
+        match search::show(ctx, &mut self.search, &self.data) {
+            search::SearchResponse::None => false,
+            search::SearchResponse::ReplaceAll => {
+                // Replacements are recorded as a single undo step
+                self.undo.checkpoint(&self.data, &self.graph);
+                let n = search::replace_all(&mut self.data, &self.search);
+                self.undo.checkpoint(&self.data, &self.graph);
+                n > 0
+            }
+            search::SearchResponse::Focus(index) => {
+                match self.data.blocks.get(&index) {
+                    Some(world::Block::Script(..)) => {
+                        gui::DockStateEditor::new(index, &mut self.tree)
+                            .focus_script();
+                    }
+                    Some(world::Block::Value(..)) => {
+                        gui::request_value_focus(ctx, index);
+                    }
+                    Some(world::Block::Note(..)) | None => (),
+                }
+                false
+            }
+        }
+    }
+
+    /// Draws blocks as a node graph
+    ///
+    /// Returns `true` if anything in the world has changed
+    #[must_use]
+    fn block_graph(&mut self, ui: &mut egui::Ui) -> bool {
+        let mut changed = false;
+        for (index, r) in graph::show(ui, &mut self.data, &mut self.graph) {
+            if r.contains(BlockResponse::TOGGLE_EDIT) {
+                gui::DockStateEditor::new(index, &mut self.tree)
+                    .toggle_script();
+            }
+            if r.contains(BlockResponse::MOVED) {
+                self.undo.checkpoint_layout(&self.data, &self.graph);
+            }
+            changed |= r.contains(BlockResponse::CHANGED);
+        }
+        changed
+    }
+
     pub fn restore_world_state(&mut self, state: WorldState) {
         self.data = state.into();
         self.tree
@@ -1706,9 +2119,9 @@ impl<P: Platform> App<P> {
         let (is_dragging, drag_released) =
             ctx.input(|i| (i.pointer.any_down(), i.pointer.any_released()));
         if drag_released {
-            self.undo.checkpoint(&self.data);
+            self.undo.checkpoint(&self.data, &self.graph);
         } else if !is_dragging {
-            self.undo.feed_state(&self.data);
+            self.undo.feed_state(&self.data, &self.graph);
         }
     }
 
@@ -1726,7 +2139,8 @@ impl<P: Platform> App<P> {
         match m {
             Message::RebuildWorld { world } => {
                 self.data.import_data(world);
-                let ScriptState::Running { changed } = std::mem::replace(
+                self.thumbnails.invalidate();
+                let ScriptState::Running { changed, .. } = std::mem::replace(
                     &mut self.script_state,
                     ScriptState::Done,
                 ) else {
@@ -1802,6 +2216,29 @@ impl<P: Platform> App<P> {
                     self.modal,
                 ),
             },
+            Message::Debug { id, status } => self.debugger.update(id, status),
+            Message::Thumbnail { block, id, image } => {
+                self.thumbnails.receive(block, id, image)
+            }
+            Message::ZoomToFit {
+                block,
+                lower,
+                upper,
+            } => {
+                if let Some(e) = self.views.get_mut(&block) {
+                    e.canvas.zoom_to_fit(lower, upper);
+                    self.request_repaint = true;
+                }
+            }
+            Message::MassProperties { id, result } => {
+                self.analysis.receive(id, result)
+            }
+            Message::FillBounds(fill) => {
+                if self.data.fill_bounds(fill) {
+                    self.start_world_rebuild();
+                    self.request_repaint = true;
+                }
+            }
         }
     }
 
@@ -1840,6 +2277,12 @@ impl<P: Platform> App<P> {
                 )) {
                     self.on_open();
                 }
+                if i.consume_shortcut(&egui::KeyboardShortcut::new(
+                    egui::Modifiers::MAC_CMD | egui::Modifiers::SHIFT,
+                    egui::Key::F,
+                )) {
+                    self.search.show();
+                }
                 if i.consume_shortcut(&egui::KeyboardShortcut::new(
                     egui::Modifiers::MAC_CMD | egui::Modifiers::SHIFT,
                     egui::Key::Z,
@@ -1886,12 +2329,24 @@ bitflags::bitflags! {
         const TOGGLE_EDIT   = (1 << 1);
         /// Request to toggle the view window
         const TOGGLE_VIEW   = (1 << 2);
-        /// Request to focus the edit window
+        /// Request to focus the edit window on the error location
         const FOCUS_ERR     = (1 << 3);
         /// The block has changed
         const CHANGED       = (1 << 4);
         /// Export this block
         const EXPORT        = (1 << 5);
+        /// Request to focus the root cause of an upstream failure
+        const FOCUS_UPSTREAM = (1 << 6);
+        /// Request to rename the block, updating references
+        const RENAME        = (1 << 7);
+        /// Request to show the block's references
+        const SHOW_REFS     = (1 << 8);
+        /// Request to insert a new block after this one
+        const INSERT_AFTER  = (1 << 9);
+        /// Request to show the block's expression graph
+        const SHOW_DAG      = (1 << 10);
+        /// The block's node was moved in the graph view
+        const MOVED         = (1 << 11);
     }
 }
 
@@ -1900,12 +2355,16 @@ bitflags::bitflags! {
     #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
     #[must_use]
     struct ViewResponse: u32 {
-        /// Request to focus the edit window
+        /// Request to focus the edit window on the error location
         const FOCUS_ERR     = (1 << 0);
         /// The block has changed
         const CHANGED       = (1 << 1);
         /// The UI should be repainted
         const REDRAW        = (1 << 2);
+        /// Request to focus the edit window
+        const FOCUS_SCRIPT  = (1 << 3);
+        /// Request to fit the view's camera to its scene
+        const ZOOM_TO_FIT   = (1 << 4);
     }
 }
 
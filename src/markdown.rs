@@ -0,0 +1,219 @@
+//! Minimal Markdown renderer for note blocks
+//!
+//! This supports a small subset of Markdown: headings, paragraphs, bullet and
+//! numbered lists, block quotes, horizontal rules, fenced code blocks, and
+//! inline `**bold**`, `*italic*`, `` `code` `` and `[links](url)`.  Anything
+//! else is drawn as plain text.
+
+/// Draws Markdown-formatted text into the given `Ui`
+pub fn show(ui: &mut egui::Ui, text: &str) {
+    let mut paragraph = String::new();
+    let mut code: Option<String> = None;
+    for line in text.lines() {
+        if let Some(c) = code.as_mut() {
+            if line.trim_start().starts_with("```") {
+                code_block(ui, c);
+                code = None;
+            } else {
+                if !c.is_empty() {
+                    c.push('\n');
+                }
+                c.push_str(line);
+            }
+            continue;
+        }
+
+        let trimmed = line.trim();
+        if trimmed.is_empty() {
+            flush_paragraph(ui, &mut paragraph);
+            continue;
+        } else if trimmed.starts_with("```") {
+            flush_paragraph(ui, &mut paragraph);
+            code = Some(String::new());
+            continue;
+        }
+
+        if let Some((level, title)) = heading(trimmed) {
+            flush_paragraph(ui, &mut paragraph);
+            let size = ui.text_style_height(&egui::TextStyle::Heading)
+                * [1.0, 0.85, 0.75][(level - 1).min(2)];
+            ui.label(egui::RichText::new(title).strong().size(size));
+        } else if trimmed.chars().all(|c| c == '-' || c == '*')
+            && trimmed.len() >= 3
+        {
+            flush_paragraph(ui, &mut paragraph);
+            ui.separator();
+        } else if let Some(item) = trimmed
+            .strip_prefix("- ")
+            .or_else(|| trimmed.strip_prefix("* "))
+        {
+            flush_paragraph(ui, &mut paragraph);
+            list_item(ui, "•", item);
+        } else if let Some((n, item)) = numbered(trimmed) {
+            flush_paragraph(ui, &mut paragraph);
+            list_item(ui, &format!("{n}."), item);
+        } else if let Some(quote) = trimmed.strip_prefix('>') {
+            flush_paragraph(ui, &mut paragraph);
+            ui.horizontal_top(|ui| {
+                let c = ui.visuals().weak_text_color();
+                ui.label(egui::RichText::new("│").color(c));
+                let mut job = inline(ui, quote.trim_start());
+                for s in job.sections.iter_mut() {
+                    s.format.italics = true;
+                    s.format.color = c;
+                }
+                ui.label(job);
+            });
+        } else {
+            if !paragraph.is_empty() {
+                paragraph.push(' ');
+            }
+            paragraph.push_str(trimmed);
+        }
+    }
+    flush_paragraph(ui, &mut paragraph);
+    if let Some(c) = code {
+        // Unterminated code block
+        code_block(ui, &c);
+    }
+}
+
+/// Draws an accumulated paragraph (if non-empty), then clears it
+fn flush_paragraph(ui: &mut egui::Ui, paragraph: &mut String) {
+    if !paragraph.is_empty() {
+        let job = inline(ui, paragraph);
+        ui.label(job);
+        paragraph.clear();
+    }
+}
+
+fn code_block(ui: &mut egui::Ui, code: &str) {
+    egui::Frame::new()
+        .fill(ui.visuals().code_bg_color)
+        .inner_margin(4.0)
+        .corner_radius(2.0)
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            ui.monospace(code);
+        });
+}
+
+fn list_item(ui: &mut egui::Ui, bullet: &str, text: &str) {
+    ui.horizontal_top(|ui| {
+        ui.add_space(ui.spacing().indent / 2.0);
+        ui.label(bullet);
+        let job = inline(ui, text);
+        ui.label(job);
+    });
+}
+
+/// Parses a heading line, returning its level (1-6) and text
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if (1..=6).contains(&level) {
+        line[level..]
+            .strip_prefix(' ')
+            .map(|t| (level, t.trim_end_matches('#').trim()))
+    } else {
+        None
+    }
+}
+
+/// Parses a numbered list item (e.g. `1. foo`), returning the number and text
+fn numbered(line: &str) -> Option<(&str, &str)> {
+    let digits = line.chars().take_while(|c| c.is_ascii_digit()).count();
+    if digits == 0 {
+        return None;
+    }
+    line[digits..]
+        .strip_prefix(". ")
+        .map(|item| (&line[..digits], item))
+}
+
+/// Builds a layout job for a single run of inline Markdown
+fn inline(ui: &egui::Ui, text: &str) -> egui::text::LayoutJob {
+    let style = ui.style();
+    let body = egui::TextStyle::Body.resolve(style);
+    let mono = egui::TextStyle::Monospace.resolve(style);
+    let text_color = style.visuals.text_color();
+    let strong_color = style.visuals.strong_text_color();
+
+    let mut job = egui::text::LayoutJob::default();
+    let mut bold = false;
+    let mut italics = false;
+    let mut buf = String::new();
+    let flush = |job: &mut egui::text::LayoutJob,
+                 buf: &mut String,
+                 bold: bool,
+                 italics: bool| {
+        if !buf.is_empty() {
+            job.append(
+                buf,
+                0.0,
+                egui::TextFormat {
+                    font_id: body.clone(),
+                    color: if bold { strong_color } else { text_color },
+                    italics,
+                    ..Default::default()
+                },
+            );
+            buf.clear();
+        }
+    };
+
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if let Some(r) = rest.strip_prefix("**") {
+            flush(&mut job, &mut buf, bold, italics);
+            bold = !bold;
+            rest = r;
+        } else if c == '*' {
+            flush(&mut job, &mut buf, bold, italics);
+            italics = !italics;
+            rest = &rest[1..];
+        } else if c == '`'
+            && let Some(end) = rest[1..].find('`')
+        {
+            flush(&mut job, &mut buf, bold, italics);
+            job.append(
+                &rest[1..=end],
+                0.0,
+                egui::TextFormat {
+                    font_id: mono.clone(),
+                    color: text_color,
+                    background: style.visuals.code_bg_color,
+                    ..Default::default()
+                },
+            );
+            rest = &rest[end + 2..];
+        } else if c == '['
+            && let Some(mid) = rest.find("](")
+            && let Some(end) = rest[mid..].find(')')
+        {
+            flush(&mut job, &mut buf, bold, italics);
+            let color = style.visuals.hyperlink_color;
+            job.append(
+                &rest[1..mid],
+                0.0,
+                egui::TextFormat {
+                    font_id: body.clone(),
+                    color,
+                    italics,
+                    underline: egui::Stroke::new(1.0, color),
+                    ..Default::default()
+                },
+            );
+            rest = &rest[mid + end + 1..];
+        } else if c == '\\'
+            && let Some(e) = rest[1..].chars().next()
+        {
+            buf.push(e);
+            rest = &rest[1 + e.len_utf8()..];
+        } else {
+            buf.push(c);
+            rest = &rest[c.len_utf8()..];
+        }
+    }
+    flush(&mut job, &mut buf, bold, italics);
+    job
+}
@@ -30,7 +30,10 @@ pub struct WgpuShadedPainter {
 struct Uniforms {
     transform: [[f32; 4]; 4],
     max_depth: f32,
-    _padding: [u8; 12],
+    ambient: f32,
+    _padding: [u8; 8],
+    /// Light positions and intensities, packed as `[x, y, z, intensity]`
+    lights: [[f32; 4]; 3],
 }
 
 impl WgpuShadedPainter {
@@ -148,7 +151,10 @@ impl egui_wgpu::CallbackTrait for WgpuShadedPainter {
         let uniforms = Uniforms {
             transform: transform.into(),
             max_depth: max_depth as f32,
+            ambient: crate::render::AMBIENT,
             _padding: Default::default(),
+            lights: crate::render::LIGHTS
+                .map(|([x, y, z], intensity)| [x, y, z, intensity]),
         };
         {
             let mut writer = queue
@@ -2,6 +2,7 @@ use crate::{
     App, AppState, Message, MessageReceiver, MessageSender, Modal,
     platform::{self, Platform},
     state, wgpu_setup,
+    world::{Block, World},
 };
 use log::{info, warn};
 use std::io::{Read, Write};
@@ -207,6 +208,10 @@ struct Args {
     #[clap(long, conflicts_with = "target")]
     example: Option<String>,
 
+    /// Check the target file for errors and warnings, then exit
+    #[clap(long, requires = "target")]
+    check: bool,
+
     /// File to edit (created if not present)
     target: Option<std::path::PathBuf>,
 }
@@ -233,6 +238,10 @@ pub fn run() -> anyhow::Result<()> {
     )
     .init();
 
+    if args.check {
+        return check_file(args.target.as_deref().unwrap());
+    }
+
     let mut native_options = eframe::NativeOptions::default();
     native_options.wgpu_options.wgpu_setup =
         pollster::block_on(wgpu_setup())?.into();
@@ -296,6 +305,45 @@ fn load_from_file(
     AppState::deserialize(s)
 }
 
+/// Evaluates a file without opening a window
+///
+/// Errors and warnings are printed to stdout; returns an error if any block
+/// failed to evaluate.
+fn check_file(filename: &std::path::Path) -> anyhow::Result<()> {
+    let state = load_from_file(filename)?;
+    let world = World::from(state.world);
+    let mut failed = 0;
+    for i in &world.order {
+        let block = &world.blocks[i];
+        let name = block.name();
+        if let Some(e) = block.error() {
+            failed += 1;
+            match e.position().and_then(|p| p.line()) {
+                Some(line) => {
+                    println!("{name}:{line}: error: {}", e.print_chain())
+                }
+                None => println!("{name}: error: {}", e.print_chain()),
+            }
+        }
+        if let Block::Script(s) = block
+            && let Some(data) = &s.data
+        {
+            for w in &data.warnings {
+                match w.pos.line() {
+                    Some(line) => {
+                        println!("{name}:{line}: warning: {}", w.message)
+                    }
+                    None => println!("{name}: warning: {}", w.message),
+                }
+            }
+        }
+    }
+    if failed > 0 {
+        anyhow::bail!("{failed} block(s) failed to evaluate");
+    }
+    Ok(())
+}
+
 /// Writes to the given file
 fn write_to_file(
     state: &AppState,
@@ -339,12 +339,7 @@ impl std::cmp::PartialEq for RenderSettings {
                 mode_a == mode_b
                     && view_a == view_b
                     && size_a == size_b
-                    && scene_a.shapes.len() == scene_b.shapes.len()
-                    && scene_a
-                        .shapes
-                        .iter()
-                        .zip(&scene_b.shapes)
-                        .all(|(a, b)| a.color == b.color && a.tree == b.tree)
+                    && scene_a == scene_b
             }
             (
                 Self::Render3 {
@@ -366,12 +361,7 @@ impl std::cmp::PartialEq for RenderSettings {
                     && view_a == view_b
                     && size_a == size_b
                     && perspective_a == perspective_b
-                    && scene_a.shapes.len() == scene_b.shapes.len()
-                    && scene_a
-                        .shapes
-                        .iter()
-                        .zip(&scene_b.shapes)
-                        .all(|(a, b)| a.color == b.color && a.tree == b.tree)
+                    && scene_a == scene_b
             }
             _ => false,
         }
@@ -435,19 +425,26 @@ fn image_to_heightmap(
     view: fidget::gui::View3,
     color: Option<Color>,
 ) -> HeightmapImageData {
-    let color = color.map(|c| {
-        match c {
-            Color::Rgb(rgb) => render_colors_3d(&image, view, rgb),
-            Color::Hsl(hsl) => render_hsl_3d(&image, view, hsl),
-        }
-        .take()
-        .0
-        .into()
-    });
+    let color = color.map(|c| image_to_colors_3d(&image, view, c));
     let depth = image.map(|v| v.depth).take().0.into();
     HeightmapImageData { depth, color }
 }
 
+/// Evaluates a shape's color at each filled pixel of a 3D image
+pub(crate) fn image_to_colors_3d(
+    image: &fidget::raster::GeometryBuffer,
+    view: fidget::gui::View3,
+    color: Color,
+) -> std::sync::Arc<[[u8; 4]]> {
+    match color {
+        Color::Rgb(rgb) => render_colors_3d(image, view, rgb),
+        Color::Hsl(hsl) => render_hsl_3d(image, view, hsl),
+    }
+    .take()
+    .0
+    .into()
+}
+
 fn merged_ssao(
     images: &[(fidget::raster::GeometryBuffer, Option<Color>)],
 ) -> std::sync::Arc<[f32]> {
@@ -471,6 +468,50 @@ fn merged_ssao(
     ssao.take().0.into()
 }
 
+/// Lights used to shade 3D views, as `(position, intensity)` pairs
+///
+/// Positions are in normalized image coordinates (±1 on each axis).  These are
+/// passed to the GPU shader for shaded views, and used directly when shading
+/// on the CPU with [`shade_pixel`].
+pub(crate) const LIGHTS: [([f32; 3], f32); 3] = [
+    ([5.0, -5.0, 10.0], 0.5),
+    ([-5.0, 0.0, 10.0], 0.15),
+    ([0.0, -5.0, 10.0], 0.15),
+];
+
+/// Ambient light added to every shaded pixel
+pub(crate) const AMBIENT: f32 = 0.2;
+
+/// Returns the brightness of a pixel in a 3D image
+///
+/// This matches the lighting in `shaded.wgsl`, but without ambient occlusion.
+/// Pixels at the near clipping plane are drawn fully lit, and the result is
+/// not clamped.
+pub(crate) fn shade_pixel(
+    p: GeometryPixel,
+    (x, y): (usize, usize),
+    size: fidget::render::VoxelSize,
+) -> f32 {
+    let max_depth = size.depth() as f32;
+    if p.depth >= max_depth {
+        return 1.0;
+    }
+    let pos = nalgebra::Vector3::new(
+        ((x as f32 + 0.5) / size.width() as f32 - 0.5) * 2.0,
+        ((y as f32 + 0.5) / size.height() as f32 - 0.5) * 2.0,
+        2.0 * (p.depth / max_depth - 0.5),
+    );
+    let n = nalgebra::Vector3::from(p.normal).normalize();
+    LIGHTS
+        .iter()
+        .map(|(light, intensity)| {
+            let dir = (nalgebra::Vector3::from(*light) - pos).normalize();
+            dir.dot(&n).max(0.0) * intensity
+        })
+        .sum::<f32>()
+        + AMBIENT
+}
+
 fn image_to_shaded(
     image: fidget::raster::GeometryBuffer,
     view: fidget::gui::View3,
@@ -479,15 +520,7 @@ fn image_to_shaded(
     let threads = Some(&fidget::render::ThreadPool::Global);
 
     let color = color
-        .map(|c| {
-            match c {
-                Color::Rgb(rgb) => render_colors_3d(&image, view, rgb),
-                Color::Hsl(hsl) => render_hsl_3d(&image, view, hsl),
-            }
-            .take()
-            .0
-            .into()
-        })
+        .map(|c| image_to_colors_3d(&image, view, c))
         .unwrap_or_else(|| {
             let pixel_count =
                 image.size().width() as usize * image.size().height() as usize;
@@ -543,11 +576,6 @@ pub(crate) fn render_colors_2d(
     let mat = view.world_to_model() * image.size().screen_to_world();
 
     let image_size = image.size();
-    let mut ctx = fidget::Context::new();
-    let colors = colors.map(|x| ctx.import(&x));
-
-    let f = RenderFunction::new(&ctx, &colors).unwrap();
-    let vars = f.vars();
 
     let mut tiles = vec![];
     const TILE_SIZE: u32 = 8;
@@ -578,57 +606,27 @@ pub(crate) fn render_colors_2d(
         }
     }
 
-    let tape = f.float_slice_tape(Default::default());
-
-    let tiles = tiles
-        .into_par_iter()
-        .map_init(
-            || {
-                (
-                    RenderFunction::new_float_slice_eval(),
-                    vec![0f32; (TILE_SIZE * TILE_SIZE) as usize],
-                    vec![0f32; (TILE_SIZE * TILE_SIZE) as usize],
-                    vec![0f32; (TILE_SIZE * TILE_SIZE) as usize],
-                )
-            },
-            |(eval, xs, ys, zs), (px, py)| {
-                let mut i = 0;
-                for dy in 0..TILE_SIZE {
-                    for dx in 0..TILE_SIZE {
-                        let pos = mat.transform_point(&nalgebra::Point2::new(
-                            (px + dx) as f32,
-                            (py + dy) as f32,
-                        ));
-                        xs[i] = pos.x;
-                        ys[i] = pos.y;
-                        i += 1;
-                    }
-                }
-                // Dummy values, which we have to shuffle around
-                let mut vs = [xs.as_slice(), ys.as_slice(), zs.as_slice()];
-                if let Some(ix) = vars.get(&fidget::var::Var::X) {
-                    vs[ix] = xs;
-                }
-                if let Some(iy) = vars.get(&fidget::var::Var::Y) {
-                    vs[iy] = ys;
-                }
-                if let Some(iz) = vars.get(&fidget::var::Var::Z) {
-                    vs[iz] = zs;
-                }
-                let out = eval.eval(&tape, &vs).unwrap();
-                let r = &out[0];
-                let g = &out[1];
-                let b = &out[2];
-                let image = (0..(TILE_SIZE as usize).pow(2))
-                    .map(|i| [r[i], g[i], b[i], 1.0])
-                    .collect::<Vec<_>>();
-                (px, py, image)
-            },
-        )
+    let points = tiles
+        .iter()
+        .flat_map(|&(px, py)| {
+            (0..TILE_SIZE).flat_map(move |dy| {
+                (0..TILE_SIZE).map(move |dx| {
+                    let pos = mat.transform_point(&nalgebra::Point2::new(
+                        (px + dx) as f32,
+                        (py + dy) as f32,
+                    ));
+                    nalgebra::Vector3::new(pos.x, pos.y, 0.0)
+                })
+            })
+        })
         .collect::<Vec<_>>();
+    let colors = eval_colors(&colors, &points);
 
     let mut out = fidget::raster::Image::new(image_size);
-    for (x, y, data) in tiles {
+    for (&(x, y), data) in tiles
+        .iter()
+        .zip(colors.chunks((TILE_SIZE * TILE_SIZE) as usize))
+    {
         let mut iter = data.iter();
         for dy in 0..TILE_SIZE {
             for dx in 0..TILE_SIZE {
@@ -636,8 +634,7 @@ pub(crate) fn render_colors_2d(
                 let x = x + dx;
                 let y = y + dy;
                 if x < image_size.width() && y < image_size.height() {
-                    out[(y as usize, x as usize)] =
-                        p.map(|p| (p.clamp(0.0, 1.0) * 255.0) as u8);
+                    out[(y as usize, x as usize)] = *p;
                 }
             }
         }
@@ -676,11 +673,6 @@ fn render_colors_3d(
     let mat = view.world_to_model() * image.size().screen_to_world();
 
     let image_size = image.size();
-    let mut ctx = fidget::Context::new();
-    let colors = colors.map(|x| ctx.import(&x));
-
-    let f = RenderFunction::new(&ctx, &colors).unwrap();
-    let vars = f.vars();
 
     let mut tiles = vec![];
     const TILE_SIZE: u32 = 8;
@@ -711,64 +703,33 @@ fn render_colors_3d(
         }
     }
 
-    let tape = f.float_slice_tape(Default::default());
-
-    let tiles = tiles
-        .into_par_iter()
-        .map_init(
-            || {
-                (
-                    RenderFunction::new_float_slice_eval(),
-                    vec![0f32; (TILE_SIZE * TILE_SIZE) as usize],
-                    vec![0f32; (TILE_SIZE * TILE_SIZE) as usize],
-                    vec![0f32; (TILE_SIZE * TILE_SIZE) as usize],
-                )
-            },
-            |(eval, xs, ys, zs), (px, py)| {
-                let mut i = 0;
-                for dy in 0..TILE_SIZE {
-                    for dx in 0..TILE_SIZE {
-                        let px = (px + dx) as usize;
-                        let py = (py + dy) as usize;
-                        let pz = if py < image.height() && px < image.width() {
-                            image[(py, px)].depth
-                        } else {
-                            0.0
-                        };
-                        let pos = mat.transform_point(&nalgebra::Point3::new(
-                            px as f32, py as f32, pz,
-                        ));
-                        xs[i] = pos.x;
-                        ys[i] = pos.y;
-                        zs[i] = pos.z;
-                        i += 1;
-                    }
-                }
-                // Dummy values, which we have to shuffle around
-                let mut vs = [xs.as_slice(), ys.as_slice(), zs.as_slice()];
-                if let Some(ix) = vars.get(&fidget::var::Var::X) {
-                    vs[ix] = xs;
-                }
-                if let Some(iy) = vars.get(&fidget::var::Var::Y) {
-                    vs[iy] = ys;
-                }
-                if let Some(iz) = vars.get(&fidget::var::Var::Z) {
-                    vs[iz] = zs;
-                }
-                let out = eval.eval(&tape, &vs).unwrap();
-                let r = &out[0];
-                let g = &out[1];
-                let b = &out[2];
-                let image = (0..(TILE_SIZE as usize).pow(2))
-                    .map(|i| [r[i], g[i], b[i], 1.0])
-                    .collect::<Vec<_>>();
-                (px, py, image)
-            },
-        )
+    let points = tiles
+        .iter()
+        .flat_map(|&(px, py)| {
+            (0..TILE_SIZE).flat_map(move |dy| {
+                (0..TILE_SIZE).map(move |dx| {
+                    let px = (px + dx) as usize;
+                    let py = (py + dy) as usize;
+                    let pz = if py < image.height() && px < image.width() {
+                        image[(py, px)].depth
+                    } else {
+                        0.0
+                    };
+                    mat.transform_point(&nalgebra::Point3::new(
+                        px as f32, py as f32, pz,
+                    ))
+                    .coords
+                })
+            })
+        })
         .collect::<Vec<_>>();
+    let colors = eval_colors(&colors, &points);
 
     let mut out = fidget::raster::Image::new(image_size);
-    for (x, y, data) in tiles {
+    for (&(x, y), data) in tiles
+        .iter()
+        .zip(colors.chunks((TILE_SIZE * TILE_SIZE) as usize))
+    {
         let mut iter = data.iter();
         for dy in 0..TILE_SIZE {
             for dx in 0..TILE_SIZE {
@@ -776,11 +737,75 @@ fn render_colors_3d(
                 let x = x + dx;
                 let y = y + dy;
                 if x < image_size.width() && y < image_size.height() {
-                    out[(y as usize, x as usize)] =
-                        p.map(|p| (p.clamp(0.0, 1.0) * 255.0) as u8);
+                    out[(y as usize, x as usize)] = *p;
                 }
             }
         }
     }
     out
 }
+
+/// Evaluates a color at each of the given points, returning sRGB values
+///
+/// This is used when exporting colored meshes; HSL colors are converted to RGB.
+pub(crate) fn vertex_colors(
+    points: &[nalgebra::Vector3<f32>],
+    color: &Color,
+) -> Vec<[u8; 3]> {
+    let (colors, hsl) = match color {
+        Color::Rgb(rgb) => (rgb, false),
+        Color::Hsl(hsl) => (hsl, true),
+    };
+    eval_colors(colors, points)
+        .into_iter()
+        .map(|c| {
+            let [r, g, b, _] = if hsl { hsl_to_rgb(c) } else { c };
+            [r, g, b]
+        })
+        .collect()
+}
+
+/// Evaluates a set of color channels at each of the given points
+///
+/// Channels are clamped to `[0, 1]` and scaled to bytes; alpha is always 255.
+fn eval_colors(
+    colors: &[fidget::context::Tree; 3],
+    points: &[nalgebra::Vector3<f32>],
+) -> Vec<[u8; 4]> {
+    let mut ctx = fidget::Context::new();
+    let colors = colors.clone().map(|x| ctx.import(&x));
+
+    let f = RenderFunction::new(&ctx, &colors).unwrap();
+    let vars = f.vars();
+    let tape = f.float_slice_tape(Default::default());
+
+    const CHUNK_SIZE: usize = 256;
+    points
+        .par_chunks(CHUNK_SIZE)
+        .map_init(RenderFunction::new_float_slice_eval, |eval, chunk| {
+            let xs = chunk.iter().map(|p| p.x).collect::<Vec<_>>();
+            let ys = chunk.iter().map(|p| p.y).collect::<Vec<_>>();
+            let zs = chunk.iter().map(|p| p.z).collect::<Vec<_>>();
+
+            // Dummy values, which we have to shuffle around
+            let mut vs = [xs.as_slice(), ys.as_slice(), zs.as_slice()];
+            if let Some(ix) = vars.get(&fidget::var::Var::X) {
+                vs[ix] = &xs;
+            }
+            if let Some(iy) = vars.get(&fidget::var::Var::Y) {
+                vs[iy] = &ys;
+            }
+            if let Some(iz) = vars.get(&fidget::var::Var::Z) {
+                vs[iz] = &zs;
+            }
+            let out = eval.eval(&tape, &vs).unwrap();
+            (0..chunk.len())
+                .map(|i| {
+                    [out[0][i], out[1][i], out[2][i], 1.0]
+                        .map(|p| (p.clamp(0.0, 1.0) * 255.0) as u8)
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>()
+        .concat()
+}
@@ -0,0 +1,361 @@
+//! Project-wide find and replace
+//!
+//! Searches cover script bodies, script input expressions, and value block
+//! inputs.  Notes are not searched.
+use crate::world::{Block, BlockIndex, World};
+
+/// Persistent state for the find-and-replace window
+#[derive(Default)]
+pub struct SearchState {
+    pub open: bool,
+    query: String,
+    replace: String,
+    regex: bool,
+    case_sensitive: bool,
+    /// The query box should take focus on the next frame
+    needs_focus: bool,
+}
+
+impl SearchState {
+    /// Opens the window and focuses the query box
+    pub fn show(&mut self) {
+        self.open = true;
+        self.needs_focus = true;
+    }
+
+    /// Builds a regex from the current query
+    ///
+    /// Returns `Ok(None)` if the query is empty
+    fn matcher(&self) -> Result<Option<regex::Regex>, regex::Error> {
+        if self.query.is_empty() {
+            return Ok(None);
+        }
+        let pattern = if self.regex {
+            self.query.clone()
+        } else {
+            regex::escape(&self.query)
+        };
+        regex::RegexBuilder::new(&pattern)
+            .case_insensitive(!self.case_sensitive)
+            .build()
+            .map(Some)
+    }
+}
+
+/// Action requested from the find-and-replace window
+pub enum SearchResponse {
+    None,
+    /// Replace every match (as a single undo step)
+    ReplaceAll,
+    /// Open the given block's script
+    Focus(BlockIndex),
+}
+
+/// A searchable piece of text within a block
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Field {
+    Script,
+    Input(String),
+    Value,
+}
+
+impl std::fmt::Display for Field {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Field::Script => write!(f, "script"),
+            Field::Input(name) => write!(f, "input `{name}`"),
+            Field::Value => write!(f, "value"),
+        }
+    }
+}
+
+/// Returns every searchable field in a block, in display order
+fn fields(block: &Block) -> Vec<(Field, &str)> {
+    match block {
+        Block::Script(s) => {
+            let mut inputs = s.inputs.iter().collect::<Vec<_>>();
+            inputs.sort();
+            std::iter::once((Field::Script, s.script.as_str()))
+                .chain(inputs.into_iter().map(|(name, text)| {
+                    (Field::Input(name.clone()), text.as_str())
+                }))
+                .collect()
+        }
+        Block::Value(v) => vec![(Field::Value, v.input.as_str())],
+        Block::Note(..) => vec![],
+    }
+}
+
+/// Replaces every match in the world, returning the number of replacements
+pub fn replace_all(world: &mut World, state: &SearchState) -> usize {
+    let Ok(Some(re)) = state.matcher() else {
+        return 0;
+    };
+    let replace = |text: &mut String| {
+        let count = re.find_iter(text).count();
+        if count > 0 {
+            let out = if state.regex {
+                re.replace_all(text, state.replace.as_str())
+            } else {
+                re.replace_all(text, regex::NoExpand(&state.replace))
+            };
+            *text = out.into_owned();
+        }
+        count
+    };
+    let mut count = 0;
+    for block in world.blocks.values_mut() {
+        match block {
+            Block::Script(s) => {
+                count += replace(&mut s.script);
+                count += s.inputs.values_mut().map(replace).sum::<usize>();
+            }
+            Block::Value(v) => count += replace(&mut v.input),
+            Block::Note(..) => (),
+        }
+    }
+    count
+}
+
+/// Draws the find-and-replace window
+pub fn show(
+    ctx: &egui::Context,
+    state: &mut SearchState,
+    world: &World,
+) -> SearchResponse {
+    let mut out = SearchResponse::None;
+    let mut open = state.open;
+    egui::Window::new("Find and replace")
+        .open(&mut open)
+        .default_width(400.0)
+        .show(ctx, |ui| {
+            egui::Grid::new("search_grid")
+                .num_columns(2)
+                .show(ui, |ui| {
+                    ui.label("Find");
+                    let r = ui.add(
+                        egui::TextEdit::singleline(&mut state.query)
+                            .desired_width(f32::INFINITY),
+                    );
+                    if std::mem::take(&mut state.needs_focus) {
+                        r.request_focus();
+                    }
+                    ui.end_row();
+                    ui.label("Replace");
+                    ui.add(
+                        egui::TextEdit::singleline(&mut state.replace)
+                            .desired_width(f32::INFINITY),
+                    );
+                    ui.end_row();
+                });
+            ui.horizontal(|ui| {
+                ui.checkbox(&mut state.regex, "Regex");
+                ui.checkbox(&mut state.case_sensitive, "Match case");
+            });
+
+            let re = match state.matcher() {
+                Ok(Some(re)) => re,
+                Ok(None) => return,
+                Err(e) => {
+                    ui.colored_label(
+                        ui.visuals().error_fg_color,
+                        e.to_string(),
+                    );
+                    return;
+                }
+            };
+
+            // Collect matches, grouped by block
+            let mut total = 0;
+            let mut groups = vec![];
+            for index in &world.order {
+                let block = &world.blocks[index];
+                let mut matches = vec![];
+                for (field, text) in fields(block) {
+                    for m in re.find_iter(text) {
+                        matches.push((field.clone(), text, m.range()));
+                    }
+                }
+                if !matches.is_empty() {
+                    total += matches.len();
+                    groups.push((*index, block.name(), matches));
+                }
+            }
+
+            ui.horizontal(|ui| {
+                ui.label(format!(
+                    "{total} match{} in {} block{}",
+                    if total == 1 { "" } else { "es" },
+                    groups.len(),
+                    if groups.len() == 1 { "" } else { "s" },
+                ));
+                if ui
+                    .add_enabled(total > 0, egui::Button::new("Replace all"))
+                    .clicked()
+                {
+                    out = SearchResponse::ReplaceAll;
+                }
+            });
+            ui.separator();
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for (index, name, matches) in groups {
+                    egui::CollapsingHeader::new(format!(
+                        "{name} ({})",
+                        matches.len()
+                    ))
+                    .id_salt(index.id().with("search"))
+                    .default_open(true)
+                    .show(ui, |ui| {
+                        for (field, text, range) in matches {
+                            let job = match_line(ui, &field, text, range);
+                            if ui
+                                .add(
+                                    egui::Label::new(job)
+                                        .sense(egui::Sense::click())
+                                        .truncate(),
+                                )
+                                .on_hover_text("Open block")
+                                .clicked()
+                            {
+                                out = SearchResponse::Focus(index);
+                            }
+                        }
+                    });
+                }
+            });
+        });
+    state.open = open;
+    out
+}
+
+/// Builds a one-line summary of a match, with the match highlighted
+fn match_line(
+    ui: &egui::Ui,
+    field: &Field,
+    text: &str,
+    range: std::ops::Range<usize>,
+) -> egui::text::LayoutJob {
+    let line_start = text[..range.start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = text[range.start..]
+        .find('\n')
+        .map_or(text.len(), |i| range.start + i);
+    let line = text[..range.start].matches('\n').count() + 1;
+    let end = range.end.min(line_end);
+
+    let font = egui::TextStyle::Monospace.resolve(ui.style());
+    let color = ui.visuals().text_color();
+    let weak =
+        egui::TextFormat::simple(font.clone(), ui.visuals().weak_text_color());
+    let normal = egui::TextFormat::simple(font.clone(), color);
+    let highlight = egui::TextFormat {
+        background: ui.visuals().selection.bg_fill,
+        ..egui::TextFormat::simple(font, ui.visuals().strong_text_color())
+    };
+
+    let mut job = egui::text::LayoutJob::default();
+    let prefix = match field {
+        Field::Script => format!("{line}: "),
+        _ => format!("{field}: "),
+    };
+    job.append(&prefix, 0.0, weak);
+    job.append(
+        text[line_start..range.start].trim_start(),
+        0.0,
+        normal.clone(),
+    );
+    job.append(&text[range.start..end], 0.0, highlight);
+    job.append(&text[end..line_end], 0.0, normal);
+    job
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::state::{
+        BlockState, NoteState, ScriptState, ValueState, WorldState,
+    };
+
+    fn world() -> World {
+        let blocks = vec![
+            BlockState::Script(ScriptState {
+                name: "a".to_owned(),
+                script: "output(\"v\", input(\"x\") * 1.5);".to_owned(),
+                inputs: [("x".to_owned(), "1.5".to_owned())].into(),
+                disabled: false,
+                passthrough: None,
+            }),
+            BlockState::Value(ValueState {
+                name: "b".to_owned(),
+                input: "a + 1.5".to_owned(),
+                disabled: false,
+            }),
+            BlockState::Note(NoteState {
+                name: "c".to_owned(),
+                text: "Scale by 1.5".to_owned(),
+            }),
+        ];
+        let order = (0..blocks.len() as u64)
+            .map(BlockIndex::new)
+            .collect::<Vec<_>>();
+        World::from(WorldState {
+            next_index: blocks.len() as u64,
+            blocks: order.iter().cloned().zip(blocks).collect(),
+            order,
+            keep_stale: false,
+        })
+    }
+
+    fn search(query: &str, replace: &str, regex: bool) -> SearchState {
+        SearchState {
+            query: query.to_owned(),
+            replace: replace.to_owned(),
+            regex,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn replace_all_fields() {
+        let mut w = world();
+        assert_eq!(replace_all(&mut w, &search("1.5", "2", false)), 3);
+        let Block::Script(a) = &w.blocks[&BlockIndex::new(0)] else {
+            panic!()
+        };
+        assert_eq!(a.script, "output(\"v\", input(\"x\") * 2);");
+        assert_eq!(a.inputs["x"], "2");
+        let Block::Value(b) = &w.blocks[&BlockIndex::new(1)] else {
+            panic!()
+        };
+        assert_eq!(b.input, "a + 2");
+
+        // Notes are not searched
+        let Block::Note(c) = &w.blocks[&BlockIndex::new(2)] else {
+            panic!()
+        };
+        assert_eq!(c.text, "Scale by 1.5");
+    }
+
+    #[test]
+    fn replace_all_regex() {
+        // Plain-text queries match literally and don't expand `$` groups
+        let mut w = world();
+        assert_eq!(replace_all(&mut w, &search("1.5", "$1", false)), 3);
+        let Block::Value(b) = &w.blocks[&BlockIndex::new(1)] else {
+            panic!()
+        };
+        assert_eq!(b.input, "a + $1");
+
+        let mut w = world();
+        let s = search(r"(\d)\.5", "${1}.25", true);
+        assert_eq!(replace_all(&mut w, &s), 3);
+        let Block::Value(b) = &w.blocks[&BlockIndex::new(1)] else {
+            panic!()
+        };
+        assert_eq!(b.input, "a + 1.25");
+
+        // Invalid patterns replace nothing
+        let mut w = world();
+        assert_eq!(replace_all(&mut w, &search("(", "", true)), 0);
+    }
+}
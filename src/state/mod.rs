@@ -97,6 +97,8 @@ pub struct AppState {
     pub world: WorldState,
     pub views: HashMap<BlockIndex, ViewState>,
     pub dock: egui_dock::DockState<Tab>,
+    #[serde(default)]
+    pub graph: GraphState,
 }
 
 const TAG: &str = "halfspace";
@@ -111,6 +113,7 @@ impl Default for AppState {
             world: WorldState::default(),
             views: HashMap::new(),
             dock: egui_dock::DockState::new(vec![]),
+            graph: GraphState::default(),
         }
     }
 }
@@ -119,6 +122,7 @@ trait Reader {
     type WorldState: serde::de::DeserializeOwned;
     type Metadata: serde::de::DeserializeOwned + Default;
     type ViewState: serde::de::DeserializeOwned;
+    type GraphState: serde::de::DeserializeOwned + Default;
     type Tab: serde::de::DeserializeOwned;
     const MAJOR_VERSION: usize;
     const MINOR_VERSION: usize;
@@ -133,6 +137,7 @@ struct ReadData<R: Reader> {
     views: HashMap<BlockIndex, R::ViewState>,
     world: R::WorldState,
     dock: egui_dock::DockState<R::Tab>,
+    graph: R::GraphState,
 }
 
 impl AppState {
@@ -141,6 +146,7 @@ impl AppState {
         views: &HashMap<BlockIndex, ViewData>,
         dock: &egui_dock::DockState<Tab>,
         meta: &Metadata,
+        graph: &GraphState,
     ) -> Self {
         let world = world.into();
         let dock = dock.clone();
@@ -156,6 +162,7 @@ impl AppState {
             world,
             views,
             dock,
+            graph: graph.clone(),
         }
     }
 
@@ -191,6 +198,7 @@ impl AppState {
             views: data.views,
             world: data.world,
             dock: data.dock,
+            graph: data.graph,
         })
     }
 
@@ -225,6 +233,19 @@ impl AppState {
             })
             .transpose()?
             .unwrap_or_default();
+        let graph: R::GraphState = raw
+            .graph
+            .map(|r| {
+                serde_json::from_value(r).map_err(|e| {
+                    if perhaps_too_new {
+                        too_new()
+                    } else {
+                        ReadError::from(e)
+                    }
+                })
+            })
+            .transpose()?
+            .unwrap_or_default();
         let mut views: HashMap<BlockIndex, R::ViewState> =
             serde_json::from_value(raw.views).map_err(|e| {
                 if perhaps_too_new {
@@ -246,6 +267,7 @@ impl AppState {
             views,
             world,
             dock,
+            graph,
         })
     }
 
@@ -263,4 +285,155 @@ struct RawAppState {
     world: serde_json::Value,
     views: serde_json::Value,
     dock: serde_json::Value,
+    graph: Option<serde_json::Value>,
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    /// Builds a serialized file with the given version and world
+    fn file(minor: usize, world: serde_json::Value) -> String {
+        let dock = egui_dock::DockState::new(vec![Tab {
+            index: BlockIndex::new(0),
+            mode: TabMode::Script,
+        }]);
+        serde_json::json!({
+            "tag": TAG,
+            "major": MAJOR_VERSION,
+            "minor": minor,
+            "world": world,
+            "views": {},
+            "dock": serde_json::to_value(&dock).unwrap(),
+        })
+        .to_string()
+    }
+
+    #[test]
+    fn load_old_minor_version() {
+        // Files from version 2.2 have no disabled blocks, notes, stale values,
+        // or graph layout
+        let s = file(
+            2,
+            serde_json::json!({
+                "next_index": 2,
+                "order": [0, 1],
+                "blocks": {
+                    "0": { "Script": {
+                        "name": "a",
+                        "script": "output(\"v\", input(\"x\"));",
+                        "inputs": { "x": "1" },
+                    }},
+                    "1": { "Value": { "name": "b", "input": "a + 1" }},
+                },
+            }),
+        );
+        let state = AppState::deserialize(&s).unwrap();
+        assert_eq!((state.major, state.minor), (MAJOR_VERSION, MINOR_VERSION));
+        let BlockState::Script(a) = &state.world.blocks[&BlockIndex::new(0)]
+        else {
+            panic!("expected script block");
+        };
+        assert!(!a.disabled);
+        assert_eq!(a.passthrough, None);
+        let BlockState::Value(b) = &state.world.blocks[&BlockIndex::new(1)]
+        else {
+            panic!("expected value block");
+        };
+        assert!(!b.disabled);
+        assert!(!state.world.keep_stale);
+        assert_eq!(state.graph, GraphState::default());
+        let tab = Tab {
+            index: BlockIndex::new(0),
+            mode: TabMode::Script,
+        };
+        assert!(state.dock.find_tab(&tab).is_some());
+    }
+
+    #[test]
+    fn round_trip() {
+        let world = WorldState {
+            next_index: 3,
+            order: (0..3).map(BlockIndex::new).collect(),
+            blocks: [
+                BlockState::Script(ScriptState {
+                    name: "a".to_owned(),
+                    script: "output(\"v\", input(\"x\"));".to_owned(),
+                    inputs: [("x".to_owned(), "1".to_owned())].into(),
+                    disabled: true,
+                    passthrough: Some("x".to_owned()),
+                }),
+                BlockState::Value(ValueState {
+                    name: "b".to_owned(),
+                    input: "a + 1".to_owned(),
+                    disabled: true,
+                }),
+                BlockState::Note(NoteState {
+                    name: "c".to_owned(),
+                    text: "# Notes".to_owned(),
+                }),
+            ]
+            .into_iter()
+            .enumerate()
+            .map(|(i, b)| (BlockIndex::new(i as u64), b))
+            .collect(),
+            keep_stale: true,
+        };
+        let graph = GraphState {
+            nodes: [(BlockIndex::new(0), [10.0, 20.0])].into(),
+        };
+        let mut dock = egui_dock::DockState::new(vec![Tab {
+            index: BlockIndex::new(0),
+            mode: TabMode::Refs,
+        }]);
+        dock.push_to_focused_leaf(Tab {
+            index: BlockIndex::new(0),
+            mode: TabMode::Dag,
+        });
+        let state = AppState {
+            world: world.clone(),
+            graph: graph.clone(),
+            dock,
+            ..Default::default()
+        };
+        let out = AppState::deserialize(&state.serialize()).unwrap();
+        assert_eq!(out.world, world);
+        assert_eq!(out.graph, graph);
+        for mode in [TabMode::Refs, TabMode::Dag] {
+            let tab = Tab {
+                index: BlockIndex::new(0),
+                mode,
+            };
+            assert!(out.dock.find_tab(&tab).is_some());
+        }
+    }
+
+    #[test]
+    fn too_new() {
+        // Newer files load if they don't use new types
+        let world = serde_json::json!({
+            "next_index": 1,
+            "order": [0],
+            "blocks": {
+                "0": { "Value": { "name": "a", "input": "1" }},
+            },
+        });
+        assert!(AppState::deserialize(&file(MINOR_VERSION + 1, world)).is_ok());
+
+        let world = serde_json::json!({
+            "next_index": 1,
+            "order": [0],
+            "blocks": {
+                "0": { "Widget": { "name": "a" }},
+            },
+        });
+        assert!(matches!(
+            AppState::deserialize(&file(MINOR_VERSION + 1, world.clone())),
+            Err(ReadError::TooNew { .. })
+        ));
+        assert!(matches!(
+            AppState::deserialize(&file(MINOR_VERSION, world)),
+            Err(ReadError::ParseError(..))
+        ));
+    }
 }
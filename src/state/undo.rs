@@ -2,13 +2,22 @@
 //!
 //! The implementation is inspired by [`egui::util::undoer::Undoer`], but is
 //! specialized to our use case.
-use crate::{state::WorldState, world::World};
+use crate::{
+    state::{GraphState, WorldState},
+    world::World,
+};
 use log::debug;
 use web_time::{Duration, Instant};
 
 #[derive(Clone)]
 struct UndoState {
     state: WorldState,
+    /// Node graph layout, which is restored along with the world
+    ///
+    /// Changes to the layout only create undo points when requested with
+    /// [`Undo::checkpoint_layout`], so that automatic layout of new nodes
+    /// doesn't count as an edit.
+    graph: GraphState,
     saved: bool,
 }
 
@@ -30,10 +39,11 @@ impl Undo {
     /// Builds a new undo state
     ///
     /// The initial world is assumed to be saved
-    pub fn new(world: &World) -> Self {
+    pub fn new(world: &World, graph: &GraphState) -> Self {
         Undo {
             undo: nonempty::NonEmpty::new(UndoState {
                 state: world.into(),
+                graph: graph.clone(),
                 saved: true,
             }),
             redo: vec![],
@@ -58,7 +68,11 @@ impl Undo {
         !self.redo.is_empty() && world == &self.undo.last().state
     }
 
-    pub fn undo(&mut self, world: &World) -> Option<&WorldState> {
+    pub fn undo(
+        &mut self,
+        world: &World,
+        graph: &GraphState,
+    ) -> Option<(&WorldState, &GraphState)> {
         if self.has_undo(world) {
             let last = self.undo.last();
             if world == &last.state {
@@ -66,15 +80,20 @@ impl Undo {
             }
             self.redo.push(UndoState {
                 state: world.into(),
+                graph: graph.clone(),
                 saved: false,
             });
-            Some(&self.undo.last().state)
+            let last = self.undo.last();
+            Some((&last.state, &last.graph))
         } else {
             None
         }
     }
 
-    pub fn redo(&mut self, world: &World) -> Option<&WorldState> {
+    pub fn redo(
+        &mut self,
+        world: &World,
+    ) -> Option<(&WorldState, &GraphState)> {
         // If the current state of the world differs from the value on top of
         // the undo stack, then we've changed and the redo stack is no longer
         // valid.
@@ -83,14 +102,15 @@ impl Undo {
             None
         } else if let Some(state) = self.redo.pop() {
             self.undo.push(state);
-            Some(&self.undo.last().state)
+            let last = self.undo.last();
+            Some((&last.state, &last.graph))
         } else {
             None
         }
     }
 
     /// Update the state, creating a checkpoint when things are stable
-    pub fn feed_state(&mut self, world: &World) {
+    pub fn feed_state(&mut self, world: &World, graph: &GraphState) {
         let prev = self.undo.last();
         if world != &prev.state {
             match &mut self.last_changed {
@@ -112,6 +132,7 @@ impl Undo {
                         let t = self.last_changed.take().unwrap();
                         self.undo.push(UndoState {
                             state: t.state,
+                            graph: graph.clone(),
                             saved: false,
                         });
                         self.redo.clear();
@@ -123,12 +144,30 @@ impl Undo {
     }
 
     /// Forcibly create an undo point if the state has changed
-    pub fn checkpoint(&mut self, world: &World) {
+    pub fn checkpoint(&mut self, world: &World, graph: &GraphState) {
         let prev = self.undo.last();
         if world != &prev.state {
             debug!("creating undo point due to checkpoint");
             self.undo.push(UndoState {
                 state: WorldState::from(world),
+                graph: graph.clone(),
+                saved: false,
+            });
+            self.redo.clear();
+        }
+        self.last_changed = None;
+    }
+
+    /// Forcibly create an undo point if the world or graph layout has changed
+    ///
+    /// This should be called when the user finishes moving a graph node.
+    pub fn checkpoint_layout(&mut self, world: &World, graph: &GraphState) {
+        let prev = self.undo.last();
+        if world != &prev.state || graph != &prev.graph {
+            debug!("creating undo point due to layout change");
+            self.undo.push(UndoState {
+                state: WorldState::from(world),
+                graph: graph.clone(),
                 saved: false,
             });
             self.redo.clear();
@@ -140,15 +179,19 @@ impl Undo {
     ///
     /// Note that this takes a [`WorldState`] instead of a `&World`; we have to
     /// make a `WorldState` when saving the file to disk, so we might as well
-    /// reuse it.
-    pub fn mark_saved(&mut self, state: WorldState) {
+    /// reuse it (along with its [`GraphState`]).
+    pub fn mark_saved(&mut self, state: WorldState, graph: GraphState) {
         let prev = self.undo.last_mut();
-        if state == prev.state {
+        if state == prev.state && graph == prev.graph {
             debug!("marking previous undo point as saved");
             prev.saved = true;
         } else {
             debug!("pushing a new saved undo point");
-            self.undo.push(UndoState { state, saved: true });
+            self.undo.push(UndoState {
+                state,
+                graph,
+                saved: true,
+            });
             self.redo.clear();
             self.last_changed = None;
         }
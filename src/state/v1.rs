@@ -14,6 +14,7 @@ impl super::Reader for Reader {
     type WorldState = WorldState;
     type Metadata = Metadata;
     type ViewState = ViewState;
+    type GraphState = ();
     const MAJOR_VERSION: usize = MAJOR_VERSION;
     const MINOR_VERSION: usize = MINOR_VERSION;
 }
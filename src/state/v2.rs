@@ -6,7 +6,7 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 
 pub const MAJOR_VERSION: usize = 2;
-pub const MINOR_VERSION: usize = 2;
+pub const MINOR_VERSION: usize = 3;
 
 pub struct Reader;
 impl super::Reader for Reader {
@@ -14,6 +14,7 @@ impl super::Reader for Reader {
     type WorldState = WorldState;
     type Metadata = Metadata;
     type ViewState = ViewState;
+    type GraphState = GraphState;
     const MAJOR_VERSION: usize = MAJOR_VERSION;
     const MINOR_VERSION: usize = MINOR_VERSION;
 }
@@ -28,6 +29,7 @@ impl MigrateFrom<v1::Reader> for Reader {
             meta: r.meta.into(),
             views: r.views.into_iter().map(|(i, b)| (i, b.into())).collect(),
             dock: r.dock.map_tabs(|t| t.into()),
+            graph: GraphState::default(),
         }
     }
 }
@@ -38,6 +40,7 @@ impl From<v1::WorldState> for WorldState {
             next_index: v.next_index,
             order: v.order,
             blocks: v.blocks.into_iter().map(|(i, b)| (i, b.into())).collect(),
+            keep_stale: false,
         }
     }
 }
@@ -48,6 +51,8 @@ impl From<v1::BlockState> for BlockState {
             name: b.name,
             script: b.script,
             inputs: b.inputs,
+            disabled: false,
+            passthrough: None,
         })
     }
 }
@@ -147,12 +152,22 @@ pub struct Metadata {
     pub name: Option<String>,
 }
 
+/// Layout of the node-graph view
+#[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
+pub struct GraphState {
+    /// Position of each block's node
+    pub nodes: HashMap<BlockIndex, [f32; 2]>,
+}
+
 /// Serialization-friendly subset of world state
 #[derive(Clone, Default, Debug, PartialEq, Serialize, Deserialize)]
 pub struct WorldState {
     pub next_index: u64,
     pub order: Vec<BlockIndex>,
     pub blocks: HashMap<BlockIndex, BlockState>,
+    /// When a block fails, use its last good value for downstream blocks
+    #[serde(default)]
+    pub keep_stale: bool,
 }
 
 /// Serialization-friendly subset of block state
@@ -160,6 +175,7 @@ pub struct WorldState {
 pub enum BlockState {
     Script(ScriptState),
     Value(ValueState),
+    Note(NoteState),
 }
 
 /// Serialization-friendly subset of block script state
@@ -168,6 +184,12 @@ pub struct ScriptState {
     pub name: String,
     pub script: String,
     pub inputs: HashMap<String, String>,
+    /// Disabled blocks are skipped during evaluation
+    #[serde(default)]
+    pub disabled: bool,
+    /// Input to forward as the block's value when it is disabled
+    #[serde(default)]
+    pub passthrough: Option<String>,
 }
 
 /// Serialization-friendly subset of block value state
@@ -175,6 +197,17 @@ pub struct ScriptState {
 pub struct ValueState {
     pub name: String,
     pub input: String,
+    /// Disabled blocks are skipped during evaluation
+    #[serde(default)]
+    pub disabled: bool,
+}
+
+/// Serialization-friendly subset of block note state
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct NoteState {
+    pub name: String,
+    /// Markdown-formatted text
+    pub text: String,
 }
 
 /// Serialization-friendly state associated with a view in the GUI
@@ -221,12 +254,16 @@ pub enum ViewMode3 {
 pub enum TabMode {
     Script,
     View,
+    /// List of references to and from the block
+    Refs,
+    /// Expression graph of the block's tree outputs
+    Dag,
 }
 
 /// Identifier for a tab in the GUI
 ///
 /// Each block may have one tab for each [`TabMode`]; right now, this is one
-/// editor and one viewer.
+/// editor, one viewer, one reference list, and one expression graph.
 #[derive(Copy, Clone, Debug, Hash, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Tab {
     pub index: BlockIndex,
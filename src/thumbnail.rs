@@ -0,0 +1,351 @@
+//! Thumbnail previews of block views, drawn in the block list
+//!
+//! Thumbnails are small renders of each block's view.  2D views are rendered as
+//! a slice at z = 0, and 3D views are shaded from the view's camera (including
+//! its yaw and pitch).  They use the block's view position if it has one, and
+//! are otherwise centered on the origin; however, they're only re-rendered when
+//! the world is rebuilt, not when the block's view is moved.
+//!
+//! Thumbnails are rendered at low priority: one at a time, and single-threaded,
+//! so that they don't compete with views for the worker pool.
+use std::collections::HashMap;
+
+use crate::{
+    Message, MessageGenSender,
+    platform::Notify,
+    render::{RenderShape, image_to_bitfield, image_to_colors_3d, shade_pixel},
+    view::{ViewCanvas, ViewData},
+    world::{BlockIndex, Scene, World},
+};
+
+/// Thumbnail size, in pixels
+pub const SIZE: u32 = 64;
+
+/// Color of shapes without an associated color
+const DEFAULT_COLOR: egui::Color32 = egui::Color32::from_gray(160);
+
+struct Thumbnail {
+    texture: Option<egui::TextureHandle>,
+    /// Rendered image, which has not yet been uploaded to the texture
+    image: Option<egui::ColorImage>,
+    /// Rebuild generation of the most recent render request
+    generation: Option<u64>,
+}
+
+/// In-progress thumbnail render
+struct Pending {
+    block: BlockIndex,
+    id: u64,
+    cancel: fidget::render::CancelToken,
+}
+
+impl Drop for Pending {
+    fn drop(&mut self) {
+        self.cancel.cancel()
+    }
+}
+
+/// Cache of thumbnails for each block with a view
+#[derive(Default)]
+pub struct Thumbnails {
+    thumbnails: HashMap<BlockIndex, Thumbnail>,
+    pending: Option<Pending>,
+    next_id: u64,
+    /// Incremented each time the world is rebuilt
+    generation: u64,
+}
+
+impl Thumbnails {
+    /// Marks every thumbnail as out of date
+    ///
+    /// This should be called when a world rebuild completes.
+    pub fn invalidate(&mut self) {
+        self.generation += 1;
+    }
+
+    /// Uploads new thumbnails and checks for out-of-date thumbnails, kicking
+    /// off a new render if needed
+    ///
+    /// This should be called in the main GUI loop.
+    pub(crate) fn update<N: Notify>(
+        &mut self,
+        ctx: &egui::Context,
+        world: &World,
+        views: &HashMap<BlockIndex, ViewData>,
+        tx: &MessageGenSender<N>,
+    ) {
+        self.thumbnails.retain(|i, _| {
+            world.blocks.get(i).is_some_and(|b| b.get_view().is_some())
+        });
+        for index in &world.order {
+            if world.blocks[index].get_view().is_some() {
+                self.thumbnails.entry(*index).or_insert(Thumbnail {
+                    texture: None,
+                    image: None,
+                    generation: None,
+                });
+            }
+        }
+
+        for (block, t) in &mut self.thumbnails {
+            let Some(image) = t.image.take() else {
+                continue;
+            };
+            let options = egui::TextureOptions::LINEAR;
+            match &mut t.texture {
+                Some(tex) => tex.set(image, options),
+                None => {
+                    t.texture = Some(ctx.load_texture(
+                        format!("thumbnail_{block:?}"),
+                        image,
+                        options,
+                    ))
+                }
+            }
+        }
+
+        // Cancel the pending render if its scene is out of date
+        let generation = Some(self.generation);
+        if let Some(p) = &self.pending
+            && self
+                .thumbnails
+                .get(&p.block)
+                .is_none_or(|t| t.generation != generation)
+        {
+            self.pending = None;
+        }
+        if self.pending.is_some() {
+            return;
+        }
+        let Some(block) = world
+            .order
+            .iter()
+            .find(|i| {
+                self.thumbnails
+                    .get(i)
+                    .is_some_and(|t| t.generation != generation)
+            })
+            .cloned()
+        else {
+            return;
+        };
+        let t = self.thumbnails.get_mut(&block).unwrap();
+        t.generation = generation;
+
+        let id = self.next_id;
+        self.next_id += 1;
+        let cancel = fidget::render::CancelToken::new();
+        let cancel_ = cancel.clone();
+        let scene = world.blocks[&block].get_view().unwrap().scene.clone();
+        let view = thumbnail_view(views.get(&block));
+        let tx = tx.clone();
+        rayon::spawn(move || {
+            let image = render(&scene, view, cancel_);
+            tx.send(Message::Thumbnail { block, id, image })
+        });
+        self.pending = Some(Pending { block, id, cancel });
+    }
+
+    /// Callback when a thumbnail render is complete
+    ///
+    /// `image` is `None` if the render was cancelled.  The image is uploaded
+    /// to a texture on the next call to [`Thumbnails::update`].
+    pub fn receive(
+        &mut self,
+        block: BlockIndex,
+        id: u64,
+        image: Option<egui::ColorImage>,
+    ) {
+        if self.pending.as_ref().is_none_or(|p| p.id != id) {
+            return;
+        }
+        self.pending = None;
+        if let Some(image) = image
+            && let Some(t) = self.thumbnails.get_mut(&block)
+        {
+            t.image = Some(image);
+        }
+    }
+
+    /// Returns the most recent thumbnail for a block, if available
+    pub fn get(&self, block: BlockIndex) -> Option<egui::TextureId> {
+        self.thumbnails
+            .get(&block)
+            .and_then(|t| t.texture.as_ref())
+            .map(|t| t.id())
+    }
+}
+
+/// Camera used to render a thumbnail
+#[derive(Copy, Clone)]
+enum ThumbnailView {
+    View2(fidget::gui::View2),
+    View3 {
+        view: fidget::gui::View3,
+        perspective: bool,
+    },
+}
+
+/// Picks a view for a thumbnail, based on the block's view (if present)
+fn thumbnail_view(view: Option<&ViewData>) -> ThumbnailView {
+    match view.map(|v| v.canvas) {
+        Some(ViewCanvas::Canvas2 { canvas, .. }) => {
+            ThumbnailView::View2(canvas.view())
+        }
+        Some(ViewCanvas::Canvas3 {
+            canvas,
+            perspective,
+            ..
+        }) => ThumbnailView::View3 {
+            view: canvas.view(),
+            perspective,
+        },
+        None => ThumbnailView::View2(fidget::gui::View2::from_components(
+            nalgebra::Vector2::zeros(),
+            1.0,
+        )),
+    }
+}
+
+/// Renders a thumbnail (off-thread)
+///
+/// Returns `None` if the render is cancelled
+fn render(
+    scene: &Scene,
+    view: ThumbnailView,
+    cancel: fidget::render::CancelToken,
+) -> Option<egui::ColorImage> {
+    match view {
+        ThumbnailView::View2(view) => render_2d(scene, view, cancel),
+        ThumbnailView::View3 { view, perspective } => {
+            render_3d(scene, view, perspective, cancel)
+        }
+    }
+}
+
+/// Renders a 2D slice of the scene at z = 0
+fn render_2d(
+    scene: &Scene,
+    view: fidget::gui::View2,
+    cancel: fidget::render::CancelToken,
+) -> Option<egui::ColorImage> {
+    let image_size = fidget::render::ImageSize::new(SIZE, SIZE);
+    let cfg = fidget::raster::ImageRenderConfig {
+        image_size,
+        world_to_model: view.world_to_model(),
+        cancel,
+        threads: None,
+        ..Default::default()
+    };
+    let images = scene
+        .shapes
+        .iter()
+        .map(|shape| {
+            let rs = RenderShape::from(shape.tree.clone());
+            let data = cfg.run(rs)?;
+            Some(image_to_bitfield(data, view, shape.color.clone()))
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    // Later shapes are drawn on top of earlier shapes
+    let mut pixels = vec![egui::Color32::TRANSPARENT; (SIZE * SIZE) as usize];
+    for (pos, p) in pixels.iter_mut().enumerate() {
+        for i in images.iter().rev() {
+            if i.distance[pos] < 0.0 {
+                *p = match &i.color {
+                    Some(c) if c[pos][3] == 0 => continue,
+                    Some(c) => {
+                        let [r, g, b, _] = c[pos];
+                        egui::Color32::from_rgb(r, g, b)
+                    }
+                    None => DEFAULT_COLOR,
+                };
+                break;
+            }
+        }
+    }
+    Some(egui::ColorImage::new([SIZE as usize; 2], pixels))
+}
+
+/// Renders a shaded 3D view of the scene
+///
+/// This uses the same lights as the shaded view, but skips ambient occlusion.
+fn render_3d(
+    scene: &Scene,
+    view: fidget::gui::View3,
+    perspective: bool,
+    cancel: fidget::render::CancelToken,
+) -> Option<egui::ColorImage> {
+    // Match the scaling used when rendering 3D views (see `render.rs`)
+    let z_scale = 2.0;
+    let scale = nalgebra::Scale3::new(1.0, 1.0, z_scale);
+    let mut world_to_model = view.world_to_model() * scale.to_homogeneous();
+    if perspective {
+        *world_to_model.get_mut((3, 2)).unwrap() = 0.3;
+    }
+    let image_size = fidget::render::VoxelSize::new(SIZE, SIZE, SIZE);
+    let cfg = fidget::raster::VoxelRenderConfig {
+        image_size,
+        world_to_model,
+        cancel,
+        threads: None,
+        ..Default::default()
+    };
+    let images = scene
+        .shapes
+        .iter()
+        .map(|shape| {
+            let rs = RenderShape::from(shape.tree.clone());
+            let data = cfg.run(rs)?.map(|p| fidget::raster::GeometryPixel {
+                depth: p.depth,
+                normal: [p.normal[0], p.normal[1], p.normal[2] / z_scale],
+            });
+            let color = shape
+                .color
+                .clone()
+                .map(|c| image_to_colors_3d(&data, view, c));
+            Some((data, color))
+        })
+        .collect::<Option<Vec<_>>>()?;
+
+    let mut pixels = vec![egui::Color32::TRANSPARENT; (SIZE * SIZE) as usize];
+    for (pos, p) in pixels.iter_mut().enumerate() {
+        let (y, x) = (pos / SIZE as usize, pos % SIZE as usize);
+
+        // Pick the nearest shape; later shapes win ties
+        let mut best: Option<(fidget::raster::GeometryPixel, egui::Color32)> =
+            None;
+        for (image, color) in &images {
+            let px = image[(y, x)];
+            if px.depth == 0.0 || best.is_some_and(|(b, _)| b.depth > px.depth)
+            {
+                continue;
+            }
+            let c = match color {
+                Some(c) if c[pos][3] == 0 => continue,
+                Some(c) => {
+                    let [r, g, b, _] = c[pos];
+                    egui::Color32::from_rgb(r, g, b)
+                }
+                None => DEFAULT_COLOR,
+            };
+            best = Some((px, c));
+        }
+        let Some((px, c)) = best else {
+            continue;
+        };
+
+        let brightness = shade_pixel(px, (x, y), image_size);
+        let shade = |v: u8| (v as f32 * brightness.clamp(0.0, 1.0)) as u8;
+        *p = egui::Color32::from_rgb(shade(c.r()), shade(c.g()), shade(c.b()));
+    }
+    Some(egui::ColorImage::new([SIZE as usize; 2], pixels))
+}
+
+/// Draws a thumbnail in a block header, with a larger version on hover
+pub fn show(ui: &mut egui::Ui, texture: egui::TextureId) {
+    let size = egui::Vec2::splat(ui.spacing().interact_size.y);
+    ui.add(egui::Image::new((texture, size))).on_hover_ui(|ui| {
+        ui.add(egui::Image::new((texture, egui::Vec2::splat(SIZE as f32))));
+    });
+}
@@ -89,6 +89,69 @@ pub enum ViewCanvas {
     },
 }
 
+/// Fraction of the view left empty around the scene by "Zoom to fit"
+const FIT_MARGIN: f32 = 0.1;
+
+impl ViewCanvas {
+    /// Moves the camera so that the given bounds fill the view, with a margin
+    ///
+    /// Infinite axes (e.g. the Z axis of a 2D shape) are ignored.  If the
+    /// bounds have zero size, then only the camera's center is moved.
+    pub fn zoom_to_fit(
+        &mut self,
+        lower: fidget::shapes::types::Vec3,
+        upper: fidget::shapes::types::Vec3,
+    ) {
+        // Center and half-size on each axis
+        let axis = |lo: f64, hi: f64| {
+            if lo.is_finite() && hi.is_finite() {
+                (((lo + hi) / 2.0) as f32, ((hi - lo) / 2.0) as f32)
+            } else {
+                (0.0, 0.0)
+            }
+        };
+        let (cx, hx) = axis(lower.x, upper.x);
+        let (cy, hy) = axis(lower.y, upper.y);
+        let (cz, hz) = axis(lower.z, upper.z);
+        match self {
+            ViewCanvas::Canvas2 { canvas, .. } => {
+                let (view, size) = canvas.components();
+                let (_center, scale) = view.components();
+                // The view spans ±scale along the image's longer axis
+                let w = size.width().max(1) as f32;
+                let h = size.height().max(1) as f32;
+                let m = w.max(h);
+                let fit = (hx * m / w).max(hy * m / h) * (1.0 + FIT_MARGIN);
+                *canvas = fidget::gui::Canvas2::from_components(
+                    fidget::gui::View2::from_components(
+                        nalgebra::Vector2::new(cx, cy),
+                        if fit > 0.0 { fit } else { scale },
+                    ),
+                    size,
+                );
+            }
+            ViewCanvas::Canvas3 { canvas, .. } => {
+                let (view, size) = canvas.components();
+                let (_center, scale, yaw, pitch) = view.components();
+                // The camera may be rotated, so we fit the bounding sphere
+                let r = (hx * hx + hy * hy + hz * hz).sqrt();
+                let w = size.width().max(1) as f32;
+                let h = size.height().max(1) as f32;
+                let fit = r * w.max(h) / w.min(h) * (1.0 + FIT_MARGIN);
+                *canvas = fidget::gui::Canvas3::from_components(
+                    fidget::gui::View3::from_components(
+                        nalgebra::Vector3::new(cx, cy, cz),
+                        if fit > 0.0 { fit } else { scale },
+                        yaw,
+                        pitch,
+                    ),
+                    size,
+                );
+            }
+        }
+    }
+}
+
 impl From<&ViewCanvas> for state::ViewState {
     fn from(v: &ViewCanvas) -> state::ViewState {
         match v {
@@ -516,6 +579,9 @@ pub fn edit_button(
             if ui.button("Reset camera").clicked() {
                 reset_camera = true;
             }
+            if ui.button("Zoom to fit").clicked() {
+                out |= ViewResponse::ZOOM_TO_FIT;
+            }
         });
     // If we've edited the canvas tag, then update it in the entry
     if tag != initial_tag {
@@ -0,0 +1,271 @@
+//! Interactive debugging of script blocks
+//!
+//! A debug run evaluates a single script block on its own thread, using the
+//! last good value of each upstream block as its scope.  The run pauses at
+//! breakpoints (by line) or after a step, reporting local variables, then
+//! blocks until it receives the next [`DebugCommand`].  While running, it
+//! checks for [`DebugCommand::Stop`] (or a dropped sender) at every node.
+//!
+//! Debug runs don't modify the world; they only report their status.
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, Mutex, RwLock, mpsc},
+};
+
+use super::{
+    Block, BlockEvalData, BlockIndex, TreeSummary, World, eval_engine,
+};
+
+/// Command sent to a paused debug run
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DebugCommand {
+    /// Run until the next breakpoint
+    Continue,
+    /// Stop at the next line, entering function calls
+    StepInto,
+    /// Stop at the next line in the current function
+    StepOver,
+    /// Stop after returning from the current function
+    StepOut,
+    /// Abort the run
+    Stop,
+}
+
+/// A local variable, summarized for display
+#[derive(Clone, Debug)]
+pub struct Local {
+    pub name: String,
+    pub ty: String,
+    pub value: String,
+}
+
+/// Status of a debug run
+#[derive(Clone, Debug)]
+pub enum DebugStatus {
+    Running,
+    Paused {
+        /// Line number (1-indexed)
+        line: usize,
+        locals: Vec<Local>,
+        /// Names of functions in the call stack, outermost first
+        stack: Vec<String>,
+    },
+    /// The run has finished, with an error message if it failed
+    Finished(Option<String>),
+}
+
+/// Lines at which a debug run should pause
+///
+/// This is shared with the running script, so breakpoints may be changed while
+/// the run is in progress.
+pub type Breakpoints = Arc<RwLock<BTreeSet<usize>>>;
+
+/// A debug run, which should be executed with [`DebugTask::spawn`]
+pub struct DebugTask {
+    script: String,
+    inputs: HashMap<String, String>,
+    scope: rhai::Scope<'static>,
+    breakpoints: Breakpoints,
+    start: DebugCommand,
+    commands: mpsc::Receiver<DebugCommand>,
+}
+
+/// Stepping state, shared with the debugger callback
+struct Stepper {
+    mode: DebugCommand,
+    /// Line and call depth of the previous statement
+    prev: Option<(usize, usize)>,
+    /// Call depth when the run last paused
+    paused_depth: usize,
+    commands: mpsc::Receiver<DebugCommand>,
+}
+
+impl World {
+    /// Prepares a debug run for the given script block
+    ///
+    /// `start` is the initial command, e.g. [`DebugCommand::StepInto`] to
+    /// pause at the first statement.
+    ///
+    /// Returns a sender for debugger commands and the task to be run, or
+    /// `None` if the block isn't a script.  Dropping the sender stops the run.
+    pub fn debug_block(
+        &self,
+        index: BlockIndex,
+        breakpoints: Breakpoints,
+        start: DebugCommand,
+    ) -> Option<(mpsc::Sender<DebugCommand>, DebugTask)> {
+        let Some(Block::Script(s)) = self.blocks.get(&index) else {
+            return None;
+        };
+        let mut scope = rhai::Scope::new();
+        for i in self.order.iter().take_while(|i| **i != index) {
+            if let Some(v) = self.last_good.get(i) {
+                scope.push(self.blocks[i].name().to_owned(), v.clone());
+            }
+        }
+        let (tx, rx) = mpsc::channel();
+        let task = DebugTask {
+            script: s.script.clone(),
+            inputs: s.inputs.clone(),
+            scope,
+            breakpoints,
+            start,
+            commands: rx,
+        };
+        Some((tx, task))
+    }
+}
+
+impl DebugTask {
+    /// Runs the script on a dedicated thread
+    ///
+    /// A paused run blocks its thread, so we don't want to tie up a worker in
+    /// the global pool.  The web build can't spawn threads directly, so it
+    /// falls back to the pool.
+    pub fn spawn(self, on_status: impl Fn(DebugStatus) + Send + 'static) {
+        #[cfg(not(target_arch = "wasm32"))]
+        std::thread::Builder::new()
+            .name("debugger".to_owned())
+            .spawn(move || self.run(on_status))
+            .expect("could not spawn debugger thread");
+
+        #[cfg(target_arch = "wasm32")]
+        rayon::spawn(move || self.run(on_status));
+    }
+
+    /// Runs the script, calling `on_status` whenever it pauses or finishes
+    fn run(self, on_status: impl Fn(DebugStatus) + Send + 'static) {
+        let mut engine = eval_engine(fidget::render::CancelToken::new());
+        let ast = match engine.compile(&self.script) {
+            Ok(ast) => ast,
+            Err(e) => {
+                on_status(DebugStatus::Finished(Some(e.to_string())));
+                return;
+            }
+        };
+        let eval_data =
+            Arc::new(RwLock::new(BlockEvalData::new(self.inputs, self.scope)));
+        BlockEvalData::bind(&eval_data, &mut engine);
+
+        // The debugger callback must be `Sync`, so we wrap the (possibly
+        // non-`Sync`) status callback in a mutex.
+        let on_status = Arc::new(Mutex::new(on_status));
+        let on_status_ = on_status.clone();
+        let breakpoints = self.breakpoints;
+        let stepper = Mutex::new(Stepper {
+            mode: self.start,
+            prev: None,
+            paused_depth: 0,
+            commands: self.commands,
+        });
+        engine.register_debugger(
+            |_engine, debugger| debugger,
+            move |ctx, _event, node, _source, pos| {
+                let stopped = || {
+                    Err(rhai::EvalAltResult::ErrorTerminated(
+                        "stopped by debugger".into(),
+                        pos,
+                    )
+                    .into())
+                };
+                // We ask to be called on every node, so that a running script
+                // can be stopped at any point.
+                let next = Ok(rhai::debugger::DebuggerCommand::StepInto);
+                let mut s = stepper.lock().unwrap();
+                match s.commands.try_recv() {
+                    Ok(DebugCommand::Stop)
+                    | Err(mpsc::TryRecvError::Disconnected) => {
+                        return stopped();
+                    }
+                    // Other commands are only meaningful while paused
+                    Ok(..) | Err(mpsc::TryRecvError::Empty) => (),
+                }
+
+                // Decide whether to pause based on statement lines and call
+                // depth.
+                let (rhai::ASTNode::Stmt(..), Some(line)) = (node, pos.line())
+                else {
+                    return next;
+                };
+                let depth =
+                    ctx.global_runtime_state().debugger().call_stack().len();
+                let moved = s.prev != Some((line, depth));
+                s.prev = Some((line, depth));
+                let pause = moved
+                    && match s.mode {
+                        DebugCommand::Continue => {
+                            breakpoints.read().unwrap().contains(&line)
+                        }
+                        DebugCommand::StepInto => true,
+                        DebugCommand::StepOver => depth <= s.paused_depth,
+                        DebugCommand::StepOut => depth < s.paused_depth,
+                        DebugCommand::Stop => true,
+                    };
+                if !pause {
+                    return next;
+                }
+
+                let locals = ctx
+                    .scope()
+                    .iter()
+                    .map(|(name, _constant, value)| Local {
+                        name: name.to_owned(),
+                        ty: ctx
+                            .engine()
+                            .map_type_name(value.type_name())
+                            .to_owned(),
+                        value: summarize(&value),
+                    })
+                    .collect();
+                let stack = ctx
+                    .global_runtime_state()
+                    .debugger()
+                    .call_stack()
+                    .iter()
+                    .map(|f| f.fn_name.to_string())
+                    .collect();
+                (on_status_.lock().unwrap())(DebugStatus::Paused {
+                    line,
+                    locals,
+                    stack,
+                });
+                match s.commands.recv() {
+                    Ok(DebugCommand::Stop) | Err(..) => stopped(),
+                    Ok(cmd) => {
+                        s.mode = cmd;
+                        s.paused_depth = depth;
+                        next
+                    }
+                }
+            },
+        );
+
+        let r = engine.eval_ast::<rhai::Dynamic>(&ast);
+        let err = match r {
+            Ok(..) => None,
+            Err(e)
+                if matches!(*e, rhai::EvalAltResult::ErrorTerminated(..)) =>
+            {
+                Some("stopped by debugger".to_owned())
+            }
+            Err(e) => Some(e.to_string()),
+        };
+        (on_status.lock().unwrap())(DebugStatus::Finished(err));
+    }
+}
+
+/// Returns a short, human-readable summary of a value
+fn summarize(value: &rhai::Dynamic) -> String {
+    const MAX_LEN: usize = 80;
+    if let Some(tree) = value.clone().try_cast::<fidget::context::Tree>() {
+        return TreeSummary::new(&tree).to_string();
+    }
+    let s = value.to_string();
+    if s.chars().count() > MAX_LEN {
+        let mut s = s.chars().take(MAX_LEN).collect::<String>();
+        s += "…";
+        s
+    } else {
+        s
+    }
+}
@@ -0,0 +1,537 @@
+//! Static analysis of dependencies between blocks
+use std::collections::{HashMap, HashSet};
+
+use super::{Block, BlockIndex, World, scene};
+
+/// Builds an engine which is suitable for parsing (but not evaluating) blocks
+///
+/// Optimization is disabled, so that the AST matches the source text; the same
+/// ASTs are then evaluated (unoptimized) by the engine for each block.
+pub(crate) fn parse_engine() -> rhai::Engine {
+    let mut engine = fidget::rhai::engine();
+    scene::register_types(&mut engine);
+    engine.set_optimization_level(rhai::OptimizationLevel::None);
+    engine
+}
+
+/// Walks the AST's top-level statements, skipping function bodies
+///
+/// Functions can't see variables from the enclosing scope, so names within
+/// their bodies never refer to other blocks.
+fn walk_statements(
+    ast: &rhai::AST,
+    on_node: &mut impl FnMut(&[rhai::ASTNode]) -> bool,
+) {
+    let mut path = vec![];
+    for stmt in ast.statements() {
+        if !stmt.walk(&mut path, on_node) {
+            break;
+        }
+    }
+}
+
+/// Returns the names of every variable referenced in the given AST
+///
+/// This only includes variables without a namespace qualifier, and does not
+/// distinguish between local variables and names defined by other blocks.
+/// Function bodies are skipped.
+pub(crate) fn referenced_names(ast: &rhai::AST) -> HashSet<String> {
+    let mut out = HashSet::new();
+    walk_statements(ast, &mut |path: &[rhai::ASTNode]| {
+        if let Some(rhai::ASTNode::Expr(rhai::Expr::Variable(x, ..))) =
+            path.last()
+            && x.2.is_empty()
+        {
+            out.insert(x.1.to_string());
+        }
+        true
+    });
+    out
+}
+
+/// Identity of an AST node, used to compare paths between walk callbacks
+fn node_id(node: &rhai::ASTNode) -> *const () {
+    match node {
+        rhai::ASTNode::Stmt(s) => std::ptr::from_ref(*s).cast(),
+        rhai::ASTNode::Expr(e) => std::ptr::from_ref(*e).cast(),
+        _ => std::ptr::null(),
+    }
+}
+
+/// Returns which arm of `parent` contains `child`
+///
+/// Statements with more than one body (`if` / `else`, `try` / `catch`) have a
+/// separate scope for each; `for` loop variables aren't visible in the
+/// expression being iterated over.
+fn arm(parent: Option<&rhai::ASTNode>, child: &rhai::ASTNode) -> usize {
+    match (parent, child) {
+        (
+            Some(rhai::ASTNode::Stmt(
+                rhai::Stmt::If(x, ..) | rhai::Stmt::TryCatch(x, ..),
+            )),
+            rhai::ASTNode::Stmt(s),
+        ) if x.branch.iter().any(|b| std::ptr::eq(b, *s)) => 1,
+        (
+            Some(rhai::ASTNode::Stmt(rhai::Stmt::For(..))),
+            rhai::ASTNode::Expr(..),
+        ) => 1,
+        _ => 0,
+    }
+}
+
+/// A local definition, which shadows block names within its scope
+struct Local {
+    name: String,
+    /// Ancestors of the definition, which must also be ancestors of each use
+    scope: Vec<*const ()>,
+    /// Arm of the innermost ancestor which contains the definition
+    arm: usize,
+    /// For `let` / `const` statements, the statement itself; the name isn't
+    /// defined until we're done walking its initial value.
+    pending: Option<*const ()>,
+}
+
+impl Local {
+    fn covers(&self, path: &[rhai::ASTNode]) -> bool {
+        let n = self.scope.len();
+        self.pending.is_none()
+            && path.len() > n
+            && path.iter().zip(&self.scope).all(|(a, b)| node_id(a) == *b)
+            && arm(n.checked_sub(1).map(|i| &path[i]), &path[n]) == self.arm
+    }
+}
+
+/// Returns the names of free variables in the given AST
+///
+/// This is [`referenced_names`], minus uses of names which are defined
+/// locally (with `let` / `const`, as loop variables, or as `catch` variables)
+/// at that point in the script.
+pub(crate) fn free_names(ast: &rhai::AST) -> HashSet<String> {
+    let mut locals: Vec<Local> = vec![];
+    let mut out = HashSet::new();
+    walk_statements(ast, &mut |path: &[rhai::ASTNode]| {
+        let n = path.len();
+        for local in &mut locals {
+            if let Some(p) = local.pending
+                && path.get(local.scope.len()).map(node_id) != Some(p)
+            {
+                local.pending = None;
+            }
+        }
+        let scope = |n| path[..n].iter().map(node_id).collect::<Vec<_>>();
+        match path.last() {
+            Some(rhai::ASTNode::Expr(rhai::Expr::Variable(x, ..)))
+                if x.2.is_empty() =>
+            {
+                let name = x.1.as_str();
+                if !locals.iter().any(|l| l.name == name && l.covers(path)) {
+                    out.insert(name.to_owned());
+                }
+            }
+            Some(node @ rhai::ASTNode::Stmt(rhai::Stmt::Var(x, ..))) => {
+                locals.push(Local {
+                    name: x.0.name.to_string(),
+                    scope: scope(n - 1),
+                    arm: arm(n.checked_sub(2).map(|i| &path[i]), node),
+                    pending: Some(node_id(node)),
+                });
+            }
+            Some(rhai::ASTNode::Stmt(rhai::Stmt::For(x, ..))) => {
+                for i in std::iter::once(&x.0).chain(&x.1) {
+                    locals.push(Local {
+                        name: i.name.to_string(),
+                        scope: scope(n),
+                        arm: 0,
+                        pending: None,
+                    });
+                }
+            }
+            Some(rhai::ASTNode::Stmt(rhai::Stmt::TryCatch(x, ..))) => {
+                if let rhai::Expr::Variable(v, ..) = &x.expr {
+                    locals.push(Local {
+                        name: v.1.to_string(),
+                        scope: scope(n),
+                        arm: 1,
+                        pending: None,
+                    });
+                }
+            }
+            _ => (),
+        }
+        true
+    });
+    out
+}
+
+/// A block's script and input expressions, parsed once per rebuild
+///
+/// The same ASTs are used for evaluation and static analysis.
+#[derive(Default)]
+pub(crate) struct ParsedBlock {
+    /// Script (or value expression); notes have nothing to parse
+    pub script: Option<Result<rhai::AST, rhai::ParseError>>,
+    /// Input expressions which parsed successfully, sorted by input name
+    pub inputs: Vec<(String, rhai::AST)>,
+}
+
+impl ParsedBlock {
+    /// Iterates over every successfully parsed AST, script first
+    pub fn asts(&self) -> impl Iterator<Item = &rhai::AST> {
+        let script = self.script.iter().flatten();
+        script.chain(self.inputs.iter().map(|(_, ast)| ast))
+    }
+}
+
+/// Parses a block's script and input expressions
+///
+/// Input expressions which fail to parse are skipped.
+pub(crate) fn parse_block(engine: &rhai::Engine, block: &Block) -> ParsedBlock {
+    match block {
+        Block::Script(s) => {
+            let mut inputs = s
+                .inputs
+                .iter()
+                .filter_map(|(name, txt)| {
+                    let ast = engine.compile_expression(txt).ok()?;
+                    Some((name.clone(), ast))
+                })
+                .collect::<Vec<_>>();
+            inputs.sort_by(|a, b| a.0.cmp(&b.0));
+            ParsedBlock {
+                script: Some(engine.compile(&s.script)),
+                inputs,
+            }
+        }
+        Block::Value(v) => ParsedBlock {
+            script: Some(engine.compile(&v.input)),
+            inputs: vec![],
+        },
+        Block::Note(..) => ParsedBlock::default(),
+    }
+}
+
+/// A reference from one block to another block's name
+#[derive(Clone, Debug)]
+pub struct Reference {
+    /// Block containing the reference
+    pub from: BlockIndex,
+    /// Block whose name is referenced
+    pub to: BlockIndex,
+    /// Input expression containing the reference, or `None` for the script
+    pub input: Option<String>,
+}
+
+/// Finds every reference from a block to an upstream block
+///
+/// References are returned in block order; within a block, script references
+/// come before input references (which are sorted by input name).
+pub(crate) fn world_references(
+    world: &World,
+    parsed: &HashMap<BlockIndex, ParsedBlock>,
+) -> Vec<Reference> {
+    let mut names: HashMap<&str, BlockIndex> = HashMap::new();
+    let mut out = vec![];
+    for i in &world.order {
+        let block = &world.blocks[i];
+        if matches!(block, Block::Note(..)) {
+            continue;
+        }
+        let parsed = &parsed[i];
+        let script = parsed.script.iter().flatten().map(|ast| (ast, None));
+        let asts = script
+            .chain(parsed.inputs.iter().map(|(name, ast)| (ast, Some(name))));
+        for (ast, input) in asts {
+            let mut refs = free_names(ast)
+                .into_iter()
+                .filter_map(|n| names.get(n.as_str()).cloned())
+                .collect::<Vec<_>>();
+            refs.sort_by_key(|r| world.order.iter().position(|i| i == r));
+            out.extend(refs.into_iter().map(|to| Reference {
+                from: *i,
+                to,
+                input: input.cloned(),
+            }));
+        }
+        names.insert(block.name(), *i);
+    }
+    out
+}
+
+/// Returns the free names in a block's script and input expressions
+///
+/// Names which are shadowed by local definitions aren't included.
+pub(crate) fn block_references(parsed: &ParsedBlock) -> HashSet<String> {
+    parsed.asts().flat_map(free_names).collect()
+}
+
+/// Returns the blocks which each block refers to by name, regardless of order
+///
+/// Unlike [`world_references`], this also finds references to blocks which
+/// come later in the world's order (which would fail during evaluation).
+pub(crate) fn block_dependencies(
+    world: &World,
+) -> HashMap<BlockIndex, HashSet<BlockIndex>> {
+    let engine = parse_engine();
+    let names: HashMap<&str, BlockIndex> = world
+        .order
+        .iter()
+        .map(|i| (world.blocks[i].name(), *i))
+        .collect();
+    world
+        .order
+        .iter()
+        .map(|i| {
+            let parsed = parse_block(&engine, &world.blocks[i]);
+            let deps = block_references(&parsed)
+                .iter()
+                .filter_map(|n| names.get(n.as_str()).cloned())
+                .filter(|j| j != i)
+                .collect();
+            (*i, deps)
+        })
+        .collect()
+}
+
+/// Returns blocks which come before one of their dependencies in `order`
+pub(crate) fn misordered(
+    order: &[BlockIndex],
+    deps: &HashMap<BlockIndex, HashSet<BlockIndex>>,
+) -> HashSet<BlockIndex> {
+    let pos: HashMap<BlockIndex, usize> =
+        order.iter().enumerate().map(|(p, i)| (*i, p)).collect();
+    order
+        .iter()
+        .filter(|i| {
+            deps.get(i)
+                .is_some_and(|d| d.iter().any(|j| pos[j] > pos[i]))
+        })
+        .cloned()
+        .collect()
+}
+
+/// Fixes up an order so that blocks come after their dependencies
+///
+/// If `up` is true, then dependencies are moved up to sit above the blocks
+/// which need them; otherwise, dependent blocks are moved down below their
+/// dependencies.  Other blocks keep their relative order.
+pub(crate) fn sort_dependencies(
+    order: &[BlockIndex],
+    deps: &HashMap<BlockIndex, HashSet<BlockIndex>>,
+    up: bool,
+) -> Vec<BlockIndex> {
+    // Edges from each block to the blocks which must be placed first
+    let mut edges: HashMap<BlockIndex, Vec<BlockIndex>> = HashMap::new();
+    for i in order {
+        for j in deps.get(i).into_iter().flatten() {
+            let (a, b) = if up { (*i, *j) } else { (*j, *i) };
+            edges.entry(a).or_default().push(b);
+        }
+    }
+    let rank: HashMap<BlockIndex, usize> =
+        order.iter().enumerate().map(|(p, i)| (*i, p)).collect();
+    for v in edges.values_mut() {
+        v.sort_by_key(|i| rank[i]);
+        if !up {
+            v.reverse();
+        }
+    }
+
+    fn visit(
+        i: BlockIndex,
+        edges: &HashMap<BlockIndex, Vec<BlockIndex>>,
+        seen: &mut HashSet<BlockIndex>,
+        out: &mut Vec<BlockIndex>,
+    ) {
+        if seen.insert(i) {
+            for j in edges.get(&i).into_iter().flatten() {
+                visit(*j, edges, seen, out);
+            }
+            out.push(i);
+        }
+    }
+    let mut seen = HashSet::new();
+    let mut out = vec![];
+    if up {
+        for i in order {
+            visit(*i, &edges, &mut seen, &mut out);
+        }
+    } else {
+        for i in order.iter().rev() {
+            visit(*i, &edges, &mut seen, &mut out);
+        }
+        out.reverse();
+    }
+    out
+}
+
+/// Result of rewriting references within a piece of text
+pub(crate) enum Rewrite {
+    /// The text does not refer to the name (or could not be parsed)
+    Unchanged,
+    /// The text has been rewritten
+    Changed(String),
+    /// The name is shadowed by a local definition, so references to the block
+    /// can't be told apart from local references.
+    Shadowed,
+}
+
+/// Rewrites references to the variable `old` so that they refer to `new`
+///
+/// If `expr` is true, then the text is parsed as a single expression (as used
+/// by script inputs); otherwise, it's parsed as a full script.
+pub(crate) fn rename_references(
+    engine: &rhai::Engine,
+    text: &str,
+    expr: bool,
+    old: &str,
+    new: &str,
+) -> Rewrite {
+    let ast = if expr {
+        engine.compile_expression(text)
+    } else {
+        engine.compile(text)
+    };
+    let Ok(ast) = ast else {
+        return Rewrite::Unchanged;
+    };
+
+    let mut shadowed = ast.iter_functions().any(|f| f.params.contains(&old));
+    let mut positions = vec![];
+    ast.walk(&mut |path: &[rhai::ASTNode]| {
+        match path.last() {
+            Some(rhai::ASTNode::Expr(rhai::Expr::Variable(x, _, pos)))
+                if x.1.as_str() == old && x.2.is_empty() =>
+            {
+                positions.push(*pos);
+            }
+            Some(rhai::ASTNode::Stmt(rhai::Stmt::Var(x, ..)))
+                if x.0.name.as_str() == old =>
+            {
+                shadowed = true;
+            }
+            Some(rhai::ASTNode::Stmt(rhai::Stmt::For(x, ..)))
+                if x.0.name.as_str() == old
+                    || x.1.as_ref().is_some_and(|i| i.name.as_str() == old) =>
+            {
+                shadowed = true;
+            }
+            _ => (),
+        }
+        true
+    });
+    if shadowed {
+        return Rewrite::Shadowed;
+    }
+
+    // Convert positions to byte offsets, then replace from back to front
+    let mut offsets = positions
+        .into_iter()
+        .filter_map(|pos| byte_offset(text, pos))
+        .filter(|i| text[*i..].starts_with(old))
+        .collect::<Vec<_>>();
+    if offsets.is_empty() {
+        return Rewrite::Unchanged;
+    }
+    offsets.sort_unstable();
+    offsets.dedup();
+    let mut out = text.to_owned();
+    for i in offsets.into_iter().rev() {
+        out.replace_range(i..i + old.len(), new);
+    }
+    Rewrite::Changed(out)
+}
+
+/// Converts a (line, column) position into a byte offset within the text
+pub(crate) fn byte_offset(text: &str, pos: rhai::Position) -> Option<usize> {
+    let line = pos.line()?;
+    let col = pos.position()?;
+    let mut start = 0;
+    for (i, t) in text.split('\n').enumerate() {
+        if i + 1 == line {
+            return t
+                .char_indices()
+                .map(|(j, _)| j)
+                .chain(std::iter::once(t.len()))
+                .nth(col - 1)
+                .map(|j| start + j);
+        }
+        start += t.len() + 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn rename(text: &str, expr: bool) -> Option<String> {
+        let engine = parse_engine();
+        match rename_references(&engine, text, expr, "foo", "bar") {
+            Rewrite::Unchanged => Some(text.to_owned()),
+            Rewrite::Changed(s) => Some(s),
+            Rewrite::Shadowed => None,
+        }
+    }
+
+    #[test]
+    fn sort_deps() {
+        let [a, b, c, d] = [0, 1, 2, 3].map(BlockIndex::new);
+        let deps = HashMap::from([
+            (a, HashSet::new()),
+            (b, HashSet::from([a])),
+            (c, HashSet::new()),
+            (d, HashSet::from([b])),
+        ]);
+
+        // `b` was dragged above `a`
+        let order = [b, c, a, d];
+        assert_eq!(misordered(&order, &deps), HashSet::from([b]));
+        assert_eq!(sort_dependencies(&order, &deps, true), [a, b, c, d]);
+
+        // `b` was dragged below `d`
+        let order = [a, c, d, b];
+        assert_eq!(misordered(&order, &deps), HashSet::from([d]));
+        assert_eq!(sort_dependencies(&order, &deps, false), [a, c, b, d]);
+    }
+
+    #[test]
+    fn free() {
+        let engine = parse_engine();
+        let free = |text: &str| {
+            let mut out = free_names(&engine.compile(text).unwrap())
+                .into_iter()
+                .collect::<Vec<_>>();
+            out.sort();
+            out
+        };
+        assert_eq!(free("a + b"), ["a", "b"]);
+        assert_eq!(free("let a = 1;\na + b"), ["b"]);
+
+        // A local only shadows later uses
+        assert_eq!(free("let x = a;\nlet a = 2;\na + x"), ["a"]);
+        assert_eq!(free("let a = a + 1;\na"), ["a"]);
+
+        // ...within its own block
+        assert_eq!(free("if c { let a = 1; a } else { a }"), ["a", "c"]);
+        assert_eq!(free("{ let a = 1; }\na"), ["a"]);
+        assert_eq!(free("for a in a { a; }"), ["a"]);
+        assert_eq!(free("try { a } catch (a) { a }"), ["a"]);
+        assert_eq!(free("try { 1 } catch (e) { e }"), Vec::<String>::new());
+
+        // Function bodies can't see block names
+        assert_eq!(free("fn f(x) { x + a }\nf(b)"), ["b"]);
+    }
+
+    #[test]
+    fn rename_refs() {
+        assert_eq!(rename("foo.x + 1", true).unwrap(), "bar.x + 1");
+        assert_eq!(rename("[foo, food]", true).unwrap(), "[bar, food]");
+        assert_eq!(
+            rename("let x = foo;\noutput(\"y\", foo * 2);", false).unwrap(),
+            "let x = bar;\noutput(\"y\", bar * 2);"
+        );
+        assert_eq!(rename("\"foo\"", true).unwrap(), "\"foo\"");
+        assert!(rename("let foo = 1;\nfoo + 2", false).is_none());
+        assert!(rename("for foo in 0..3 { }", false).is_none());
+    }
+}
@@ -0,0 +1,267 @@
+//! Static analysis of block scripts, producing non-fatal warnings
+use std::collections::{HashMap, HashSet};
+
+use super::{Block, BlockIndex, IoValue, World, deps};
+
+/// A non-fatal problem in a block's script
+///
+/// Warnings are produced by static analysis or by `warn(..)` calls.
+#[derive(Clone, Debug)]
+pub struct Warning {
+    pub message: String,
+    /// Position within the script, if known
+    pub pos: rhai::Position,
+}
+
+impl Warning {
+    pub(crate) fn new(message: String, pos: rhai::Position) -> Self {
+        Self { message, pos }
+    }
+}
+
+/// An access to a variable, optionally reading a single field
+type Access = (String, Option<String>);
+
+/// Returns every variable access in the given AST
+///
+/// Field accesses (`foo.bar` or `foo["bar"]`) record the field name; other
+/// uses record `None`, meaning that the whole value may be used.
+fn accesses(ast: &rhai::AST) -> Vec<Access> {
+    let mut out = vec![];
+    ast.walk(&mut |path: &[rhai::ASTNode]| {
+        let Some(rhai::ASTNode::Expr(rhai::Expr::Variable(x, ..))) =
+            path.last()
+        else {
+            return true;
+        };
+        if !x.2.is_empty() {
+            return true;
+        }
+        let parent = path.len().checked_sub(2).map(|i| &path[i]);
+        let field = match parent {
+            Some(rhai::ASTNode::Expr(
+                rhai::Expr::Dot(b, ..) | rhai::Expr::Index(b, ..),
+            )) if matches!(
+                &b.lhs,
+                rhai::Expr::Variable(v, ..) if v.1 == x.1
+            ) =>
+            {
+                match &b.rhs {
+                    rhai::Expr::Property(p, ..) => Some(p.2.to_string()),
+                    rhai::Expr::StringConstant(s, ..) => Some(s.to_string()),
+                    rhai::Expr::Dot(c, ..) | rhai::Expr::Index(c, ..) => {
+                        match &c.lhs {
+                            rhai::Expr::Property(p, ..) => {
+                                Some(p.2.to_string())
+                            }
+                            _ => None,
+                        }
+                    }
+                    _ => None,
+                }
+            }
+            _ => None,
+        };
+        out.push((x.1.to_string(), field));
+        true
+    });
+    out
+}
+
+/// Returns the input name if this expression is an `input("name")` call
+fn input_call(expr: &rhai::Expr) -> Option<String> {
+    match expr {
+        rhai::Expr::FnCall(f, ..) => input_call_name(f),
+        _ => None,
+    }
+}
+
+fn input_call_name(f: &rhai::FnCallExpr) -> Option<String> {
+    if f.name.as_str() == "input" && f.namespace.is_empty() {
+        match f.args.first() {
+            Some(rhai::Expr::StringConstant(s, ..)) => Some(s.to_string()),
+            _ => Some(String::new()),
+        }
+    } else {
+        None
+    }
+}
+
+/// Lints every enabled script block in the world
+///
+/// Warnings cover unused `let` bindings, unused inputs, outputs which aren't
+/// used by any downstream block, and local variables which shadow upstream
+/// block names.
+pub(crate) fn lint_world(
+    world: &World,
+    parsed: &HashMap<BlockIndex, deps::ParsedBlock>,
+) -> HashMap<BlockIndex, Vec<Warning>> {
+    // Find every variable access in every block
+    let block_accesses = parsed
+        .iter()
+        .map(|(i, p)| (*i, p.asts().flat_map(accesses).collect::<Vec<_>>()))
+        .collect::<HashMap<_, _>>();
+
+    let mut upstream = HashSet::new();
+    let mut out = HashMap::new();
+    for (n, i) in world.order.iter().enumerate() {
+        let block = &world.blocks[i];
+        if let Block::Script(s) = block
+            && !s.disabled
+            && let Some(data) = &s.data
+            && let Some(Ok(ast)) = &parsed[i].script
+        {
+            let outputs = data
+                .io_values
+                .iter()
+                .filter_map(|(name, v)| match v {
+                    IoValue::Output { pos, .. } => Some((name, *pos)),
+                    IoValue::Input { .. } => None,
+                })
+                .collect::<Vec<_>>();
+            let mut warnings = lint_script(ast, &upstream, !outputs.is_empty());
+
+            // Outputs are dead if no downstream block reads them.  Blocks which
+            // are drawn or exported are exempt, since their outputs are used.
+            if data.view.is_none() && data.export.is_none() {
+                let downstream = world.order[n + 1..]
+                    .iter()
+                    .flat_map(|j| block_accesses[j].iter())
+                    .filter(|(name, _)| *name == s.name)
+                    .collect::<Vec<_>>();
+                let whole = downstream.iter().any(|(_, f)| f.is_none());
+                for (name, pos) in &outputs {
+                    let used = if outputs.len() == 1 {
+                        !downstream.is_empty()
+                    } else {
+                        whole
+                            || downstream
+                                .iter()
+                                .any(|(_, f)| f.as_ref() == Some(*name))
+                    };
+                    if !used {
+                        warnings.push(Warning::new(
+                            format!(
+                                "output `{name}` is not used by any \
+                                 downstream block"
+                            ),
+                            *pos,
+                        ));
+                    }
+                }
+            }
+            warnings.sort_by_key(|w| w.pos);
+            out.insert(*i, warnings);
+        }
+        if !matches!(block, Block::Note(..)) {
+            upstream.insert(block.name().to_owned());
+        }
+    }
+    out
+}
+
+/// Lints a single script
+///
+/// Unused inputs are only reported if the script has outputs; otherwise, its
+/// inputs are passed along as the block's value.
+fn lint_script(
+    ast: &rhai::AST,
+    upstream: &HashSet<String>,
+    has_outputs: bool,
+) -> Vec<Warning> {
+    let used = deps::referenced_names(ast);
+    let mut out = vec![];
+    let mut shadow = |name: &str, pos: rhai::Position| {
+        if upstream.contains(name) {
+            out.push(Warning::new(
+                format!("`{name}` shadows an upstream block"),
+                pos,
+            ));
+        }
+    };
+    let mut bindings = vec![];
+    let mut discarded = vec![];
+    ast.walk(&mut |path: &[rhai::ASTNode]| {
+        match path.last() {
+            Some(rhai::ASTNode::Stmt(rhai::Stmt::Var(x, ..))) => {
+                shadow(&x.0.name, x.0.pos);
+                bindings.push((
+                    x.0.name.to_string(),
+                    x.0.pos,
+                    input_call(&x.1),
+                ));
+            }
+            Some(rhai::ASTNode::Stmt(rhai::Stmt::For(x, ..))) => {
+                shadow(&x.0.name, x.0.pos);
+                if let Some(i) = &x.1 {
+                    shadow(&i.name, i.pos);
+                }
+            }
+            Some(rhai::ASTNode::Stmt(rhai::Stmt::FnCall(f, pos))) => {
+                if let Some(name) = input_call_name(f) {
+                    discarded.push((name, *pos));
+                }
+            }
+            _ => (),
+        }
+        true
+    });
+
+    for (name, pos, input) in bindings {
+        if used.contains(&name) || name.starts_with('_') {
+            continue;
+        }
+        match input {
+            Some(i) if has_outputs => out
+                .push(Warning::new(format!("input `{i}` is never used"), pos)),
+            Some(..) => (),
+            None => {
+                out.push(Warning::new(format!("unused variable `{name}`"), pos))
+            }
+        }
+    }
+    if has_outputs {
+        for (name, pos) in discarded {
+            out.push(Warning::new(
+                format!("input `{name}` is never used"),
+                pos,
+            ));
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn lint(script: &str, upstream: &[&str]) -> Vec<String> {
+        let engine = deps::parse_engine();
+        let ast = engine.compile(script).unwrap();
+        let upstream = upstream.iter().map(|s| s.to_string()).collect();
+        lint_script(&ast, &upstream, true)
+            .into_iter()
+            .map(|w| w.message)
+            .collect()
+    }
+
+    #[test]
+    fn lint_scripts() {
+        assert!(lint("let x = 1;\noutput(\"y\", x);", &[]).is_empty());
+        assert_eq!(
+            lint("let x = 1;\nlet _y = 2;\noutput(\"y\", 3);", &[]),
+            ["unused variable `x`"]
+        );
+        assert_eq!(
+            lint(
+                "let r = input(\"r\");\ninput(\"s\");\noutput(\"y\", 3);",
+                &[]
+            ),
+            ["input `r` is never used", "input `s` is never used"]
+        );
+        assert_eq!(
+            lint("let foo = 1;\noutput(\"y\", foo);", &["foo"]),
+            ["`foo` shadows an upstream block"]
+        );
+    }
+}
@@ -0,0 +1,232 @@
+//! Mass properties (volume, surface area, center of mass, and inertia)
+//!
+//! Properties are computed from a closed triangle mesh of the shape, built with
+//! the same octree as mesh export, so their accuracy depends on the minimum
+//! feature size.  Each triangle forms a signed tetrahedron with the origin;
+//! summing over tetrahedra gives integrals over the whole solid.
+use fidget::{context::Tree, rhai::FromDynamic, shapes::types::Vec3};
+
+use crate::export::{ExportError, build_mesh};
+
+#[derive(thiserror::Error, Debug)]
+pub enum MassError {
+    #[error(transparent)]
+    Mesh(#[from] ExportError),
+
+    #[error("shape touches the bounds; its mass properties would be wrong")]
+    TouchesBounds,
+}
+
+/// Mass properties of a solid, assuming uniform unit density
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MassProperties {
+    pub volume: f64,
+    pub area: f64,
+    /// Center of mass
+    pub centroid: Vec3,
+    /// Inertia tensor about the center of mass
+    pub inertia: [[f64; 3]; 3],
+}
+
+impl MassProperties {
+    /// Computes mass properties from a closed mesh
+    ///
+    /// The mesh may be wound in either direction.
+    pub fn from_mesh(mesh: &fidget::mesh::Mesh) -> Self {
+        let mut volume = 0.0;
+        let mut area = 0.0;
+        let mut first = nalgebra::Vector3::<f64>::zeros();
+        let mut second = nalgebra::Matrix3::<f64>::zeros();
+        for t in &mesh.triangles {
+            let [a, b, c] =
+                [t.x, t.y, t.z].map(|i| mesh.vertices[i].cast::<f64>());
+            area += (b - a).cross(&(c - a)).norm() / 2.0;
+
+            // Signed tetrahedron (0, a, b, c)
+            let v = a.dot(&b.cross(&c)) / 6.0;
+            let sum = a + b + c;
+            volume += v;
+            first += v * sum / 4.0;
+            second += v / 20.0
+                * (a * a.transpose()
+                    + b * b.transpose()
+                    + c * c.transpose()
+                    + sum * sum.transpose());
+        }
+
+        // Flip signs if the mesh is wound inside-out
+        if volume < 0.0 {
+            volume = -volume;
+            first = -first;
+            second = -second;
+        }
+        let centroid = if volume > 0.0 {
+            first / volume
+        } else {
+            nalgebra::Vector3::zeros()
+        };
+
+        // Shift the second moment to the center of mass, then convert it into
+        // an inertia tensor
+        let second = second - volume * centroid * centroid.transpose();
+        let inertia = nalgebra::Matrix3::identity() * second.trace() - second;
+        Self {
+            volume,
+            area,
+            centroid: Vec3 {
+                x: centroid.x,
+                y: centroid.y,
+                z: centroid.z,
+            },
+            inertia: std::array::from_fn(|i| {
+                std::array::from_fn(|j| inertia[(i, j)])
+            }),
+        }
+    }
+
+    /// Converts mass properties into a script value
+    ///
+    /// This is an object map `#{ volume, area, centroid, inertia }`, where
+    /// `inertia` is an array of rows.
+    fn to_dynamic(self) -> rhai::Dynamic {
+        let mut map = rhai::Map::new();
+        map.insert("volume".into(), self.volume.into());
+        map.insert("area".into(), self.area.into());
+        map.insert("centroid".into(), rhai::Dynamic::from(self.centroid));
+        let inertia = self
+            .inertia
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|v| rhai::Dynamic::from(*v))
+                    .collect::<rhai::Array>()
+                    .into()
+            })
+            .collect::<rhai::Array>();
+        map.insert("inertia".into(), inertia.into());
+        map.into()
+    }
+}
+
+/// Computes mass properties of a shape within the given bounds
+///
+/// The shape must fit within the bounds.  If it touches them, then the mesh is
+/// clipped and doesn't represent the whole solid, so we return
+/// [`MassError::TouchesBounds`] rather than a misleading result.
+pub fn mass_properties(
+    tree: Tree,
+    lower: Vec3,
+    upper: Vec3,
+    feature_size: f64,
+    cancel: fidget::render::CancelToken,
+) -> Result<MassProperties, MassError> {
+    let mesh = build_mesh(tree, lower, upper, feature_size, cancel)?;
+
+    // Vertices on a clipped face lie on the bounding box, while vertices of
+    // the shape's own surface are placed within octree cells
+    let eps = feature_size / 10.0;
+    let on_bounds =
+        |v: f64, lo: f64, hi: f64| (v - lo).abs() < eps || (v - hi).abs() < eps;
+    if mesh.vertices.iter().any(|v| {
+        let v = v.cast::<f64>();
+        on_bounds(v.x, lower.x, upper.x)
+            || on_bounds(v.y, lower.y, upper.y)
+            || on_bounds(v.z, lower.z, upper.z)
+    }) {
+        return Err(MassError::TouchesBounds);
+    }
+    Ok(MassProperties::from_mesh(&mesh))
+}
+
+/// Registers `mass_properties` with the engine
+///
+/// Meshing is abandoned (and the call fails) if `cancel` is cancelled.
+pub fn register(
+    engine: &mut rhai::Engine,
+    cancel: fidget::render::CancelToken,
+) {
+    engine.register_fn(
+        "mass_properties",
+        move |ctx: rhai::NativeCallContext,
+              tree: Tree,
+              lower: rhai::Dynamic,
+              upper: rhai::Dynamic,
+              resolution: rhai::Dynamic|
+              -> Result<rhai::Dynamic, Box<rhai::EvalAltResult>> {
+            let lower = Vec3::from_dynamic(&ctx, lower, None)?;
+            let upper = Vec3::from_dynamic(&ctx, upper, None)?;
+            let resolution = f64::from_dynamic(&ctx, resolution, None)?;
+            let m =
+                mass_properties(tree, lower, upper, resolution, cancel.clone())
+                    .map_err(|e| {
+                        rhai::EvalAltResult::ErrorRuntime(
+                            e.to_string().into(),
+                            ctx.call_position(),
+                        )
+                    })?;
+            Ok(m.to_dynamic())
+        },
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn sphere_mass() {
+        let (x, y, z) = (Tree::x(), Tree::y(), Tree::z());
+        let sphere = (x.square() + y.square() + z.square()).sqrt() - 1.0;
+        let v = |s| Vec3 { x: s, y: s, z: s };
+        let m = mass_properties(
+            sphere,
+            v(-1.5),
+            v(1.5),
+            0.02,
+            fidget::render::CancelToken::new(),
+        )
+        .unwrap();
+
+        let pi = std::f64::consts::PI;
+        let volume = 4.0 / 3.0 * pi;
+        assert!((m.volume - volume).abs() / volume < 0.01, "{}", m.volume);
+        assert!((m.area - 4.0 * pi).abs() / (4.0 * pi) < 0.02, "{}", m.area);
+        assert!(m.centroid.x.abs() < 1e-3);
+        assert!(m.centroid.y.abs() < 1e-3);
+        assert!(m.centroid.z.abs() < 1e-3);
+
+        // A solid sphere has inertia 2/5 m r^2 about every axis
+        let i = 0.4 * volume;
+        for (a, row) in m.inertia.iter().enumerate() {
+            for (b, v) in row.iter().enumerate() {
+                if a == b {
+                    assert!((v - i).abs() / i < 0.02, "{v}");
+                } else {
+                    assert!(v.abs() < 1e-3, "{v}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn clipped_sphere() {
+        let (x, y, z) = (Tree::x(), Tree::y(), Tree::z());
+        let sphere = (x.square() + y.square() + z.square()).sqrt() - 1.0;
+        let r = mass_properties(
+            sphere,
+            Vec3 {
+                x: 0.0,
+                y: -1.5,
+                z: -1.5,
+            },
+            Vec3 {
+                x: 1.5,
+                y: 1.5,
+                z: 1.5,
+            },
+            0.02,
+            fidget::render::CancelToken::new(),
+        );
+        assert!(matches!(r, Err(MassError::TouchesBounds)), "{r:?}");
+    }
+}
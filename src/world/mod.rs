@@ -6,20 +6,34 @@ use std::{
 
 use fidget::context::Tree;
 
+use crate::formats::MeshFormat;
 pub use crate::state::BlockIndex;
-use crate::state::{BlockState, ScriptState, ValueState, WorldState};
+use crate::state::{
+    BlockState, NoteState, ScriptState, ValueState, WorldState,
+};
 use facet::Facet;
 use heck::ToSnakeCase;
 
+mod debug;
+mod deps;
+mod lint;
+mod mass;
+mod query;
 mod scene;
 mod shapes;
+pub use debug::{Breakpoints, DebugCommand, DebugStatus, DebugTask, Local};
+pub use deps::Reference;
+pub use lint::Warning;
+pub use mass::{MassError, MassProperties, mass_properties};
+pub use query::{bounds, scene_bounds};
 pub use scene::{Color, Drawable, Scene};
-pub use shapes::{ShapeKind, ShapeLibrary};
+pub use shapes::{ShapeConstructor, ShapeKind, ShapeLibrary};
 
 #[allow(clippy::large_enum_variant)]
 pub enum Block {
     Script(ScriptBlock),
     Value(ValueBlock),
+    Note(NoteBlock),
 }
 
 impl Block {
@@ -27,6 +41,7 @@ impl Block {
         match self {
             Block::Script(s) => s.name.as_str(),
             Block::Value(s) => s.name.as_str(),
+            Block::Note(s) => s.name.as_str(),
         }
     }
 
@@ -34,6 +49,15 @@ impl Block {
         self.get_view().is_some()
     }
 
+    /// Checks whether the block is disabled (skipped during evaluation)
+    pub fn is_disabled(&self) -> bool {
+        match self {
+            Block::Script(s) => s.disabled,
+            Block::Value(s) => s.disabled,
+            Block::Note(..) => false,
+        }
+    }
+
     /// Checks whether the block is error-free
     ///
     /// A block with no state is _invalid_, i.e. returns `false`
@@ -45,6 +69,64 @@ impl Block {
             Block::Value(s) => {
                 s.data.as_ref().is_some_and(|s| s.output.is_ok())
             }
+            Block::Note(..) => true,
+        }
+    }
+
+    /// Returns the block's error, if present
+    pub fn error(&self) -> Option<&BlockError> {
+        match self {
+            Block::Script(s) => s.data.as_ref().and_then(|s| s.error.as_ref()),
+            Block::Value(s) => {
+                s.data.as_ref().and_then(|s| s.output.as_ref().err())
+            }
+            Block::Note(..) => None,
+        }
+    }
+
+    /// Checks whether the block failed and its last good value was used
+    pub fn is_stale(&self) -> bool {
+        match self {
+            Block::Script(s) => s.data.as_ref().is_some_and(|s| s.stale),
+            Block::Value(s) => s.data.as_ref().is_some_and(|s| s.stale),
+            Block::Note(..) => false,
+        }
+    }
+
+    fn set_stale(&mut self) {
+        match self {
+            Block::Script(s) => {
+                if let Some(data) = s.data.as_mut() {
+                    data.stale = true;
+                }
+            }
+            Block::Value(s) => {
+                if let Some(data) = s.data.as_mut() {
+                    data.stale = true;
+                }
+            }
+            Block::Note(..) => (),
+        }
+    }
+
+    /// Applies a function to the block's error, if present
+    fn map_error(&mut self, f: impl FnOnce(BlockError) -> BlockError) {
+        match self {
+            Block::Script(s) => {
+                if let Some(data) = s.data.as_mut() {
+                    data.error = data.error.take().map(f);
+                }
+            }
+            Block::Value(s) => {
+                if let Some(data) = s.data.as_mut()
+                    && data.output.is_err()
+                {
+                    let out =
+                        std::mem::replace(&mut data.output, Ok(().into()));
+                    data.output = out.map_err(f);
+                }
+            }
+            Block::Note(..) => (),
         }
     }
 
@@ -53,6 +135,35 @@ impl Block {
         match self {
             Block::Script(s) => s.data.as_ref().and_then(|s| s.view.as_ref()),
             Block::Value(s) => s.data.as_ref().and_then(|s| s.view.as_ref()),
+            Block::Note(..) => None,
+        }
+    }
+
+    /// Returns the block's outputs which are trees, as `(name, tree)` tuples
+    ///
+    /// A value block's output is named after the block.
+    pub fn tree_outputs(&self) -> Vec<(String, Tree)> {
+        match self {
+            Block::Script(s) => s
+                .data
+                .iter()
+                .flat_map(|d| &d.io_values)
+                .filter_map(|(name, v)| match v {
+                    IoValue::Output { value, .. } => value
+                        .clone()
+                        .try_cast::<Tree>()
+                        .map(|t| (name.clone(), t)),
+                    IoValue::Input { .. } => None,
+                })
+                .collect(),
+            Block::Value(v) => v
+                .data
+                .iter()
+                .filter_map(|d| d.output.as_ref().ok())
+                .filter_map(|v| v.clone().try_cast::<Tree>())
+                .map(|t| (v.name.clone(), t))
+                .collect(),
+            Block::Note(..) => vec![],
         }
     }
 }
@@ -68,12 +179,29 @@ pub struct ScriptBlock {
     /// This does not live in the [`ScriptData`] because it must be persistent;
     /// the resulting values _are_ stored in the block state.
     pub inputs: HashMap<String, String>,
+
+    /// Disabled blocks are skipped during evaluation
+    pub disabled: bool,
+
+    /// Input which is forwarded as the block's value when it is disabled
+    pub passthrough: Option<String>,
 }
 
 pub struct ValueBlock {
     pub name: String,
     pub input: String,
     pub data: Option<ValueData>,
+
+    /// Disabled blocks are skipped during evaluation
+    pub disabled: bool,
+}
+
+/// Block containing Markdown-formatted text
+///
+/// Notes take no part in evaluation.
+pub struct NoteBlock {
+    pub name: String,
+    pub text: String,
 }
 
 impl From<BlockState> for Block {
@@ -83,13 +211,20 @@ impl From<BlockState> for Block {
                 name: b.name,
                 script: b.script,
                 inputs: b.inputs,
+                disabled: b.disabled,
+                passthrough: b.passthrough,
                 data: None,
             }),
             BlockState::Value(b) => Self::Value(ValueBlock {
                 name: b.name,
                 input: b.input,
+                disabled: b.disabled,
                 data: None,
             }),
+            BlockState::Note(b) => Self::Note(NoteBlock {
+                name: b.name,
+                text: b.text,
+            }),
         }
     }
 }
@@ -101,10 +236,17 @@ impl From<&Block> for BlockState {
                 name: b.name.clone(),
                 script: b.script.clone(),
                 inputs: b.inputs.clone(),
+                disabled: b.disabled,
+                passthrough: b.passthrough.clone(),
             }),
             Block::Value(b) => BlockState::Value(ValueState {
                 name: b.name.clone(),
                 input: b.input.clone(),
+                disabled: b.disabled,
+            }),
+            Block::Note(b) => BlockState::Note(NoteState {
+                name: b.name.clone(),
+                text: b.text.clone(),
             }),
         }
     }
@@ -115,6 +257,52 @@ pub struct World {
     next_index: u64,
     pub order: Vec<BlockIndex>,
     pub blocks: HashMap<BlockIndex, Block>,
+
+    /// When a block fails, use its last good value for downstream blocks
+    pub keep_stale: bool,
+
+    /// Most recent successful value of each block
+    last_good: HashMap<BlockIndex, rhai::Dynamic>,
+
+    /// References between blocks, found by parsing each block
+    pub references: Vec<Reference>,
+
+    /// Bounds to find for the most recently inserted block
+    bounds_request: Option<BoundsRequest>,
+}
+
+/// Request to fill a new block's `lower` and `upper` inputs from the bounds of
+/// the shape which is wired into it
+///
+/// Finding bounds can be slow, so [`BoundsRequest::run`] should be called on a
+/// worker thread, then its result passed to [`World::fill_bounds`].
+pub struct BoundsRequest {
+    block: BlockIndex,
+    value: rhai::Dynamic,
+    dims: usize,
+    /// Template text for the bounds, which are only replaced if unchanged
+    template: (String, String),
+}
+
+impl BoundsRequest {
+    /// Finds the bounds, returning `None` if the shape is empty or unbounded
+    pub fn run(self) -> Option<BoundsFill> {
+        let (lower, upper) = shapes::bounds_literals(&self.value, self.dims)?;
+        Some(BoundsFill {
+            block: self.block,
+            lower,
+            upper,
+            template: self.template,
+        })
+    }
+}
+
+/// Bounds found by [`BoundsRequest::run`]
+pub struct BoundsFill {
+    block: BlockIndex,
+    lower: String,
+    upper: String,
+    template: (String, String),
 }
 
 impl std::ops::Index<BlockIndex> for World {
@@ -138,6 +326,25 @@ pub enum BlockError {
     Parse(#[from] rhai::ParseError),
     #[error(transparent)]
     Eval(#[from] Box<rhai::EvalAltResult>),
+    /// The block failed because it refers to another block which failed
+    ///
+    /// `name` is the referenced block; `root` is the block where the chain of
+    /// failures began (which may be the same block).  `source` is the block's
+    /// own evaluation error, which may or may not be caused by the upstream
+    /// failure.
+    #[error("upstream block `{name}` failed")]
+    Upstream {
+        name: String,
+        root: BlockIndex,
+        #[source]
+        source: Box<rhai::EvalAltResult>,
+    },
+    /// A call to `assert(cond, msg)` in the script failed
+    #[error("assertion failed: {message}")]
+    Assertion {
+        message: String,
+        pos: rhai::Position,
+    },
 }
 
 impl BlockError {
@@ -151,6 +358,19 @@ impl BlockError {
         }
         chain
     }
+
+    /// Returns the position of the error within the block's script, if known
+    pub fn position(&self) -> Option<rhai::Position> {
+        let pos = match self {
+            BlockError::Name(..) => return None,
+            BlockError::Parse(e) => e.position(),
+            BlockError::Eval(e) | BlockError::Upstream { source: e, .. } => {
+                e.position()
+            }
+            BlockError::Assertion { pos, .. } => *pos,
+        };
+        (!pos.is_none()).then_some(pos)
+    }
 }
 
 #[derive(Copy, Clone, Debug, thiserror::Error)]
@@ -186,6 +406,54 @@ pub struct BlockView {
     pub scene: scene::Scene,
 }
 
+impl BlockView {
+    /// Builds a view from a view-compatible value (tree, drawable, or scene)
+    fn from_value(value: &rhai::Dynamic) -> Option<Self> {
+        if let Some(tree) = value.clone().try_cast::<Tree>() {
+            Some(BlockView { scene: tree.into() })
+        } else if let Some(d) = value.clone().try_cast::<Drawable>() {
+            Some(BlockView { scene: d.into() })
+        } else {
+            value
+                .clone()
+                .try_cast::<Scene>()
+                .map(|scene| BlockView { scene })
+        }
+    }
+}
+
+/// Summary of a math tree, for display
+pub struct TreeSummary {
+    /// Number of unique nodes
+    pub nodes: usize,
+    /// Name of the root operation
+    pub root: String,
+}
+
+impl TreeSummary {
+    pub fn new(tree: &Tree) -> Self {
+        let mut ctx = fidget::Context::new();
+        let node = ctx.import(tree);
+        let root = match ctx.get_op(node) {
+            Some(fidget::context::Op::Binary(op, ..)) => format!("{op:?}"),
+            Some(fidget::context::Op::Unary(op, ..)) => format!("{op:?}"),
+            Some(op) => format!("{op:?}"),
+            None => "?".to_owned(),
+        };
+        Self {
+            nodes: ctx.len(),
+            root: root.to_lowercase(),
+        }
+    }
+}
+
+impl std::fmt::Display for TreeSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = if self.nodes == 1 { "" } else { "s" };
+        write!(f, "tree ({} node{s}, root: {})", self.nodes, self.root)
+    }
+}
+
 /// Transient script data (e.g. evaluation results)
 ///
 /// This data is _not_ saved or serialized; it can be recalculated on-demand
@@ -203,6 +471,10 @@ pub struct ScriptData {
     pub view: Option<BlockView>,
     /// Export request from the script
     pub export: Option<ExportRequest>,
+    /// The script failed, and its last good value was used instead
+    pub stale: bool,
+    /// Non-fatal problems found by static analysis of the script
+    pub warnings: Vec<Warning>,
 }
 
 /// Transient value data (e.g. evaluation results)
@@ -214,6 +486,8 @@ pub struct ValueData {
     pub output: Result<rhai::Dynamic, BlockError>,
     /// Value exported to a view
     pub view: Option<BlockView>,
+    /// The value failed, and its last good value was used instead
+    pub stale: bool,
 }
 
 impl From<&World> for WorldState {
@@ -225,30 +499,43 @@ impl From<&World> for WorldState {
             next_index: w.next_index,
             order: w.order.clone(),
             blocks: w.blocks.iter().map(|(k, v)| (*k, v.into())).collect(),
+            keep_stale: w.keep_stale,
         }
     }
 }
 
+/// Returns signatures for every function available to block scripts
+///
+/// Signatures are formatted as `name(arg: Type, ..) -> Type`.
+pub fn function_signatures() -> Vec<String> {
+    let mut engine = eval_engine(fidget::render::CancelToken::new());
+    let eval_data = Arc::new(RwLock::new(BlockEvalData::default()));
+    BlockEvalData::bind(&eval_data, &mut engine);
+    engine.gen_fn_signatures(true)
+}
+
+/// Builds an engine for evaluating blocks
+///
+/// Slow functions (e.g. `mass_properties`) give up early if `cancel` is
+/// cancelled.
+fn eval_engine(cancel: fidget::render::CancelToken) -> rhai::Engine {
+    let mut engine = fidget::rhai::engine();
+    scene::register_types(&mut engine); // add scene and drawable types
+    mass::register(&mut engine, cancel);
+    engine
+}
+
 impl From<WorldState> for World {
     /// Rebuilds the entire world, populating data for each block
     fn from(state: WorldState) -> Self {
-        let mut world = World {
-            next_index: state.next_index,
-            order: state.order,
-            blocks: state
-                .blocks
-                .into_iter()
-                .map(|(k, v)| (k, v.into()))
-                .collect(),
-        };
-        world.rebuild();
-        world
+        Self::build(state, HashMap::new(), fidget::render::CancelToken::new())
     }
 }
 
 impl PartialEq<WorldState> for World {
     fn eq(&self, other: &WorldState) -> bool {
         self.next_index == other.next_index
+            && self.keep_stale == other.keep_stale
             && self.order == other.order
             && self.blocks.len() == other.blocks.len()
             && self.blocks.iter().all(|(i, b)| {
@@ -260,9 +547,16 @@ impl PartialEq<WorldState> for World {
                         b.name == other.name
                             && b.script == other.script
                             && b.inputs == other.inputs
+                            && b.disabled == other.disabled
+                            && b.passthrough == other.passthrough
                     }
                     (Block::Value(b), BlockState::Value(other)) => {
-                        b.name == other.name && b.input == other.input
+                        b.name == other.name
+                            && b.input == other.input
+                            && b.disabled == other.disabled
+                    }
+                    (Block::Note(b), BlockState::Note(other)) => {
+                        b.name == other.name && b.text == other.text
                     }
                     _ => false,
                 }
@@ -276,6 +570,115 @@ impl World {
         Self::default()
     }
 
+    /// Builds a world from the given state, populating data for each block
+    ///
+    /// `last_good` contains the most recent successful value of each block,
+    /// which is used in place of failed blocks if `keep_stale` is set.
+    ///
+    /// If `cancel` is cancelled, then slow functions called by blocks return
+    /// errors instead of finishing.
+    pub fn build(
+        state: WorldState,
+        last_good: HashMap<BlockIndex, rhai::Dynamic>,
+        cancel: fidget::render::CancelToken,
+    ) -> Self {
+        let mut world = World {
+            next_index: state.next_index,
+            order: state.order,
+            blocks: state
+                .blocks
+                .into_iter()
+                .map(|(k, v)| (k, v.into()))
+                .collect(),
+            keep_stale: state.keep_stale,
+            last_good,
+            references: vec![],
+            bounds_request: None,
+        };
+        world.rebuild(&cancel);
+        world
+    }
+
+    /// Checks a new block order for blocks placed above their dependencies
+    ///
+    /// Returns the names of blocks which would be broken by the new order
+    /// (ignoring blocks which are already out of order), along with an
+    /// alternate order which moves dependencies as well; see
+    /// [`deps::sort_dependencies`] for the meaning of `up`.
+    pub fn check_order(
+        &self,
+        order: &[BlockIndex],
+        up: bool,
+    ) -> (Vec<String>, Vec<BlockIndex>) {
+        let deps = deps::block_dependencies(self);
+        let prev = deps::misordered(&self.order, &deps);
+        let broken = deps::misordered(order, &deps);
+        let names = order
+            .iter()
+            .filter(|i| broken.contains(i) && !prev.contains(i))
+            .map(|i| self.blocks[i].name().to_owned())
+            .collect();
+        (names, deps::sort_dependencies(order, &deps, up))
+    }
+
+    /// Renames a block, rewriting references in downstream blocks
+    ///
+    /// References are found by parsing downstream scripts and input
+    /// expressions.  Blocks which define a local variable with the old name
+    /// are left unchanged, since their references are ambiguous; their names
+    /// are returned.
+    pub fn rename_block(
+        &mut self,
+        index: BlockIndex,
+        name: &str,
+    ) -> Vec<String> {
+        let block = self.blocks.get_mut(&index).unwrap();
+        let old = match block {
+            Block::Script(b) => std::mem::replace(&mut b.name, name.to_owned()),
+            Block::Value(b) => std::mem::replace(&mut b.name, name.to_owned()),
+            Block::Note(b) => {
+                // Notes can't be referenced by other blocks
+                b.name = name.to_owned();
+                return vec![];
+            }
+        };
+
+        let engine = deps::parse_engine();
+        let mut skipped = vec![];
+        let pos = self.order.iter().position(|i| *i == index).unwrap();
+        for i in &self.order[pos + 1..] {
+            let block = self.blocks.get_mut(i).unwrap();
+            let mut shadowed = false;
+            let mut rewrite =
+                |text: &mut String, expr: bool| match deps::rename_references(
+                    &engine, text, expr, &old, name,
+                ) {
+                    deps::Rewrite::Unchanged => (),
+                    deps::Rewrite::Changed(t) => *text = t,
+                    deps::Rewrite::Shadowed => shadowed = true,
+                };
+            match block {
+                Block::Script(b) => {
+                    rewrite(&mut b.script, false);
+                    for txt in b.inputs.values_mut() {
+                        rewrite(txt, true);
+                    }
+                }
+                Block::Value(b) => rewrite(&mut b.input, false),
+                Block::Note(..) => (),
+            }
+            if shadowed {
+                skipped.push(block.name().to_owned());
+            }
+        }
+        skipped
+    }
+
+    /// Returns the most recent successful value of each block
+    pub fn last_good(&self) -> &HashMap<BlockIndex, rhai::Dynamic> {
+        &self.last_good
+    }
+
     /// Filters blocks based on a function
     ///
     /// Returns `true` if anything changed, or `false` otherwise
@@ -304,16 +707,65 @@ impl World {
             .unwrap()
     }
 
+    /// Takes the request to find bounds for a newly inserted block, if any
+    pub fn take_bounds_request(&mut self) -> Option<BoundsRequest> {
+        self.bounds_request.take()
+    }
+
+    /// Fills a block's `lower` and `upper` inputs with bounds found by
+    /// [`BoundsRequest::run`]
+    ///
+    /// Returns `true` if the block was changed.  Bounds which have been edited
+    /// since the block was inserted are left alone.
+    #[must_use]
+    pub fn fill_bounds(&mut self, fill: BoundsFill) -> bool {
+        let Some(Block::Script(s)) = self.blocks.get_mut(&fill.block) else {
+            return false;
+        };
+        if s.inputs.get("lower") != Some(&fill.template.0)
+            || s.inputs.get("upper") != Some(&fill.template.1)
+        {
+            return false;
+        }
+        s.inputs.insert("lower".to_owned(), fill.lower);
+        s.inputs.insert("upper".to_owned(), fill.upper);
+        true
+    }
+
     #[must_use]
     pub fn new_block_from(&mut self, s: &shapes::ShapeDefinition) -> bool {
+        self.new_block_after(s, None)
+    }
+
+    /// Inserts a new block after the given block (or at the end, if `None`)
+    ///
+    /// If the shape has a single tree input, then it is wired to the previous
+    /// block's tree output; when inserting into the middle of a chain, inputs
+    /// of the following block which referred to the previous block are then
+    /// rewired to the new block.
+    #[must_use]
+    pub fn new_block_after(
+        &mut self,
+        s: &shapes::ShapeDefinition,
+        after: Option<BlockIndex>,
+    ) -> bool {
+        let pos = match after {
+            Some(a) => match self.order.iter().position(|i| *i == a) {
+                Some(p) => p + 1,
+                None => return false,
+            },
+            None => self.order.len(),
+        };
         let index = BlockIndex::new(self.next_index);
         self.next_index += 1;
         let name = self.next_name_with_prefix(&s.name.to_snake_case());
+        let mut wired_from = None;
 
         let b = match &s.kind {
             ShapeKind::Script { inputs, script } => {
-                // Special casing: if the shape has a single tree input and our
-                // last block has a single tree output or input, then we
+                let template = inputs;
+                // Special casing: if the shape has a single tree input and the
+                // previous block has a single tree output or input, then we
                 // pre-populate the input.
                 let mut iter = inputs.iter().filter(|(_name, i)| {
                     i.ty.is_some_and(|ty| {
@@ -322,7 +774,7 @@ impl World {
                 });
                 let tree_input = iter.next().filter(|_| iter.next().is_none());
                 let mut last_tree = None;
-                if let Some(i) = self.order.last()
+                if let Some(i) = pos.checked_sub(1).map(|p| &self.order[p])
                     && let Block::Script(ScriptBlock {
                         name,
                         data: Some(data),
@@ -352,7 +804,7 @@ impl World {
                         }
                     }
                     if has_tree && ((output_count == 1) ^ (input_count == 1)) {
-                        last_tree = Some(name);
+                        last_tree = Some((name, *i));
                     }
                 }
                 let mut inputs = inputs
@@ -360,32 +812,74 @@ impl World {
                     .map(|(name, v)| (name.clone(), v.text.clone()))
                     .collect::<HashMap<_, _>>();
                 if let Some(tree_input) = tree_input
-                    && let Some(last_tree) = last_tree
+                    && let Some((last_tree, last_index)) = last_tree
                 {
                     *inputs.get_mut(tree_input.0).unwrap() =
                         last_tree.to_owned();
+                    wired_from = Some(last_tree.to_owned());
+
+                    // Templates with bounds (e.g. exports) start out with the
+                    // bounds of the wired shape, which the user can adjust.
+                    // These are found off-thread; see `take_bounds_request`.
+                    if let Some(value) = self.last_good.get(&last_index)
+                        && let Some(dims) = shapes::bounds_dims(template)
+                    {
+                        self.bounds_request = Some(BoundsRequest {
+                            block: index,
+                            value: value.clone(),
+                            dims,
+                            template: (
+                                inputs["lower"].clone(),
+                                inputs["upper"].clone(),
+                            ),
+                        });
+                    }
                 }
 
                 Block::Script(ScriptBlock {
                     name,
                     script: script.clone(),
                     inputs,
+                    disabled: false,
+                    passthrough: None,
                     data: None,
                 })
             }
             ShapeKind::Value { input } => Block::Value(ValueBlock {
                 name,
                 input: input.clone(),
+                disabled: false,
                 data: None,
             }),
+            ShapeKind::Note { text } => Block::Note(NoteBlock {
+                name,
+                text: text.clone(),
+            }),
         };
 
+        // Rewire the next block so that it uses the new block instead
+        if let Some(prev) = wired_from
+            && let Some(next) = self.order.get(pos)
+        {
+            let name = b.name();
+            let rewire = |txt: &mut String| {
+                if txt.trim() == prev {
+                    *txt = name.to_owned();
+                }
+            };
+            match self.blocks.get_mut(next).unwrap() {
+                Block::Script(s) => s.inputs.values_mut().for_each(rewire),
+                Block::Value(v) => rewire(&mut v.input),
+                Block::Note(..) => (),
+            }
+        }
+
         self.blocks.insert(index, b);
-        self.order.push(index);
+        self.order.insert(pos, index);
         true
     }
 
-    fn rebuild(&mut self) {
+    fn rebuild(&mut self, cancel: &fidget::render::CancelToken) {
         // Steal order so that we can mutate self; we'll swap it back later
         let order = std::mem::take(&mut self.order);
 
@@ -395,34 +889,141 @@ impl World {
 
         // We maintain a separate map of block names to detect duplicates
         let mut name_map = HashMap::new();
+
+        // Failed blocks are recorded (in evaluation order) along with the
+        // root cause of their failure.
+        let mut failed = vec![];
+        self.last_good.retain(|i, _| self.blocks.contains_key(i));
+
+        // Every block is parsed once, then the same ASTs are used for
+        // evaluation and static analysis.
+        let engine = deps::parse_engine();
+        let parsed = order
+            .iter()
+            .map(|i| (*i, deps::parse_block(&engine, &self.blocks[i])))
+            .collect::<HashMap<_, _>>();
         for i in &order {
-            input_scope = self.rebuild_block(*i, input_scope, &mut name_map);
+            input_scope = self.rebuild_block(
+                *i,
+                &parsed[i],
+                input_scope,
+                &mut name_map,
+                &mut failed,
+                cancel,
+            );
         }
         self.order = order;
+        self.references = deps::world_references(self, &parsed);
+        for (i, warnings) in lint::lint_world(self, &parsed) {
+            if let Block::Script(ScriptBlock { data: Some(d), .. }) =
+                self.blocks.get_mut(&i).unwrap()
+            {
+                d.warnings.extend(warnings);
+                d.warnings.sort_by_key(|w| w.pos);
+            }
+        }
     }
 
     fn rebuild_block(
         &mut self,
         i: BlockIndex,
+        parsed: &deps::ParsedBlock,
         input_scope: rhai::Scope<'static>,
         name_map: &mut HashMap<String, BlockIndex>,
+        failed: &mut Vec<(String, BlockIndex)>,
+        cancel: &fidget::render::CancelToken,
     ) -> rhai::Scope<'static> {
         let block = self.blocks.get_mut(&i).unwrap();
-        match block {
-            Block::Script(s) => {
-                Self::rebuild_script_block(i, s, input_scope, name_map)
+        let mut input_scope = match block {
+            Block::Script(s) => Self::rebuild_script_block(
+                i,
+                s,
+                parsed,
+                input_scope,
+                name_map,
+                cancel,
+            ),
+            Block::Value(s) => Self::rebuild_value_block(
+                i,
+                s,
+                parsed,
+                input_scope,
+                name_map,
+                cancel,
+            ),
+            // Notes take no part in evaluation
+            Block::Note(..) => input_scope,
+        };
+
+        // If the block failed to evaluate and refers to an upstream block which
+        // also failed, then report the upstream failure as well, since it's
+        // probably the more useful message.
+        let root = match block.error() {
+            None | Some(BlockError::Name(..)) => None,
+            Some(
+                BlockError::Parse(..)
+                | BlockError::Assertion { .. }
+                | BlockError::Upstream { .. },
+            ) => Some(i),
+            Some(BlockError::Eval(..)) if failed.is_empty() => Some(i),
+            Some(BlockError::Eval(..)) => {
+                let refs = deps::block_references(parsed);
+                if let Some((name, root)) =
+                    failed.iter().find(|(name, _)| refs.contains(name))
+                {
+                    block.map_error(|e| match e {
+                        BlockError::Eval(source) => BlockError::Upstream {
+                            name: name.clone(),
+                            root: *root,
+                            source,
+                        },
+                        e => e,
+                    });
+                    Some(*root)
+                } else {
+                    Some(i)
+                }
             }
-            Block::Value(s) => {
-                Self::rebuild_value_block(i, s, input_scope, name_map)
+        };
+
+        // Only enabled blocks which evaluated and bound their own name have a
+        // value worth keeping; anything else in the scope under this name
+        // belongs to an earlier block.
+        let owns_name = name_map.get(block.name()) == Some(&i);
+        if block.error().is_none() {
+            let v = (owns_name && !block.is_disabled())
+                .then(|| input_scope.get_value::<rhai::Dynamic>(block.name()))
+                .flatten();
+            match v {
+                Some(v) => self.last_good.insert(i, v),
+                None => self.last_good.remove(&i),
+            };
+        } else if let Some(root) = root {
+            // Blocks which failed during evaluation have already bound their
+            // name, so we only bind it here if it's missing.
+            if self.keep_stale
+                && let Some(v) = self.last_good.get(&i)
+                && (owns_name
+                    || Self::bind_name(i, block.name(), name_map).is_ok())
+            {
+                // Use the last good value, so that downstream blocks still see
+                // something meaningful while this block is broken.
+                input_scope.push(block.name().to_owned(), v.clone());
+                block.set_stale();
+            } else {
+                failed.push((block.name().to_owned(), root));
             }
         }
+        input_scope
     }
 
     fn rebuild_script_block(
         i: BlockIndex,
         block: &mut ScriptBlock,
+        parsed: &deps::ParsedBlock,
         input_scope: rhai::Scope<'static>,
         name_map: &mut HashMap<String, BlockIndex>,
+        cancel: &fidget::render::CancelToken,
     ) -> rhai::Scope<'static> {
         block.data = Some(ScriptData {
             stdout: String::new(),
@@ -431,18 +1032,28 @@ impl World {
             io_values: vec![],
             view: None,
             export: None,
+            stale: false,
+            warnings: vec![],
         });
+        if block.disabled {
+            return Self::rebuild_disabled_script_block(
+                i,
+                block,
+                input_scope,
+                name_map,
+                cancel,
+            );
+        }
         let data = block.data.as_mut().unwrap();
 
-        let mut engine = fidget::rhai::engine();
-        scene::register_types(&mut engine); // add scene and drawable types
-        let ast = match engine.compile(&block.script) {
+        let ast = match parsed.script.as_ref().unwrap() {
             Ok(ast) => ast,
             Err(e) => {
-                data.error = Some(BlockError::Parse(e));
+                data.error = Some(BlockError::Parse(e.clone()));
                 return input_scope;
             }
         };
+        let mut engine = eval_engine(cancel.clone());
 
         // Build the data used during block evaluation
         let eval_data = Arc::new(RwLock::new(BlockEvalData::new(
@@ -451,7 +1062,7 @@ impl World {
         )));
         BlockEvalData::bind(&eval_data, &mut engine);
 
-        let r = engine.eval_ast::<rhai::Dynamic>(&ast);
+        let r = engine.eval_ast::<rhai::Dynamic>(ast);
 
         // Update block state based on actions taken by the script.  We manually
         // unpack `data` here to produce a compiler error if it changes.
@@ -463,8 +1074,11 @@ impl World {
             view,
             error,
             export,
+            stale: _,
+            warnings,
         } = data;
         *stdout = eval_data.stdout.join("\n");
+        *warnings = eval_data.warnings;
         *debug = eval_data.debug;
         *io_values = eval_data.values;
         *view = eval_data.view.map(|scene| BlockView { scene });
@@ -474,7 +1088,14 @@ impl World {
         block.inputs = eval_data.inputs;
 
         if let Err(e) = r {
-            *error = Some(BlockError::Eval(e));
+            // The script may have caught a failed assertion, so we only report
+            // it if it's the error which stopped evaluation.
+            *error = Some(match eval_data.assertion {
+                Some((message, pos)) if is_assertion(&e, &message, pos) => {
+                    BlockError::Assertion { message, pos }
+                }
+                _ => BlockError::Eval(e),
+            });
         } else {
             // If the script evaluated successfully, filter out any input
             // fields which haven't been used in the script.
@@ -485,28 +1106,13 @@ impl World {
         // first check that the name is valid.  We prioritize script errors over
         // name errors, so will not replace an existing value in `data.error`
         let mut input_scope = eval_data.scope;
-        if !rhai::is_valid_identifier(&block.name) {
+        if let Err(e) = Self::bind_name(i, &block.name, name_map) {
             if data.error.is_none() {
-                data.error =
-                    Some(BlockError::Name(NameError::InvalidIdentifier));
+                data.error = Some(BlockError::Name(e));
             }
             return input_scope;
         }
 
-        // Next, bind from the name to a block index (if available)
-        match name_map.entry(block.name.clone()) {
-            std::collections::hash_map::Entry::Occupied(..) => {
-                if data.error.is_none() {
-                    data.error =
-                        Some(BlockError::Name(NameError::DuplicateName));
-                }
-                return input_scope;
-            }
-            std::collections::hash_map::Entry::Vacant(v) => {
-                v.insert(i);
-            }
-        }
-
         // Write IO values into the shared input scope.  The value which is
         // written depends on a few heuristics:
         // - If there is a single output or input, then write it with the object
@@ -546,37 +1152,124 @@ impl World {
             // If there's no view but there's a single view-compatible output,
             // then treat it as the view.
             if data.view.is_none() {
-                if let Some(tree) = value.clone().try_cast::<Tree>() {
-                    data.view = Some(BlockView { scene: tree.into() })
-                } else if let Some(d) = value.clone().try_cast::<Drawable>() {
-                    data.view = Some(BlockView { scene: d.into() })
-                } else if let Some(scene) = value.try_cast::<Scene>() {
-                    data.view = Some(BlockView { scene })
-                }
+                data.view = BlockView::from_value(&value);
             }
         }
 
         input_scope
     }
 
+    /// Rebuilds a disabled script block
+    ///
+    /// The script is not evaluated.  If the block has a pass-through input,
+    /// then that input's expression is evaluated and bound to the block's name.
+    fn rebuild_disabled_script_block(
+        i: BlockIndex,
+        block: &mut ScriptBlock,
+        mut input_scope: rhai::Scope<'static>,
+        name_map: &mut HashMap<String, BlockIndex>,
+        cancel: &fidget::render::CancelToken,
+    ) -> rhai::Scope<'static> {
+        let data = block.data.as_mut().unwrap();
+        let value = match block
+            .passthrough
+            .as_ref()
+            .and_then(|name| block.inputs.get(name).map(|txt| (name, txt)))
+        {
+            Some((name, txt)) => {
+                let mut engine = eval_engine(cancel.clone());
+                let v = engine.eval_expression_with_scope::<rhai::Dynamic>(
+                    &mut input_scope,
+                    txt,
+                );
+                data.io_values.push((
+                    name.clone(),
+                    IoValue::Input {
+                        pos: rhai::Position::NONE,
+                        value: v.as_ref().map_err(|e| e.to_string()).cloned(),
+                    },
+                ));
+                match v {
+                    Ok(v) => Some(v),
+                    Err(mut e) => {
+                        // The error's position is within the input expression,
+                        // not the script, so we drop it.
+                        e.clear_position();
+                        data.error = Some(BlockError::Eval(e));
+                        None
+                    }
+                }
+            }
+            None => None,
+        };
+
+        if let Err(e) = Self::bind_name(i, &block.name, name_map) {
+            if data.error.is_none() {
+                data.error = Some(BlockError::Name(e));
+            }
+            return input_scope;
+        }
+        if let Some(value) = value {
+            input_scope.push(&block.name, value.clone());
+            data.view = BlockView::from_value(&value);
+        }
+        input_scope
+    }
+
+    /// Checks that a block name is valid and unique, recording it if so
+    fn bind_name(
+        i: BlockIndex,
+        name: &str,
+        name_map: &mut HashMap<String, BlockIndex>,
+    ) -> Result<(), NameError> {
+        if !rhai::is_valid_identifier(name) {
+            return Err(NameError::InvalidIdentifier);
+        }
+        match name_map.entry(name.to_owned()) {
+            std::collections::hash_map::Entry::Occupied(..) => {
+                Err(NameError::DuplicateName)
+            }
+            std::collections::hash_map::Entry::Vacant(v) => {
+                v.insert(i);
+                Ok(())
+            }
+        }
+    }
+
     fn rebuild_value_block(
         i: BlockIndex,
         block: &mut ValueBlock,
+        parsed: &deps::ParsedBlock,
         input_scope: rhai::Scope<'static>,
         name_map: &mut HashMap<String, BlockIndex>,
+        cancel: &fidget::render::CancelToken,
     ) -> rhai::Scope<'static> {
-        let mut engine = fidget::rhai::engine();
-        scene::register_types(&mut engine); // add scene and drawable types
-        let ast = match engine.compile(&block.input) {
+        if block.disabled {
+            let mut data = ValueData {
+                output: Ok(rhai::Dynamic::UNIT),
+                view: None,
+                stale: false,
+            };
+            // Disabled blocks still reserve their name
+            if let Err(e) = Self::bind_name(i, &block.name, name_map) {
+                data.output = Err(BlockError::Name(e));
+            }
+            block.data = Some(data);
+            return input_scope;
+        }
+
+        let ast = match parsed.script.as_ref().unwrap() {
             Ok(ast) => ast,
             Err(e) => {
                 block.data = Some(ValueData {
-                    output: Err(BlockError::Parse(e)),
+                    output: Err(BlockError::Parse(e.clone())),
                     view: None,
+                    stale: false,
                 });
                 return input_scope;
             }
         };
+        let mut engine = eval_engine(cancel.clone());
 
         // Build the data used during block evaluation
         let eval_data = Arc::new(RwLock::new(BlockEvalData::new(
@@ -585,15 +1278,17 @@ impl World {
         )));
         // Note that we don't call `BlockEvalData::bind` here, because we're
         // only evaluating a single expression.
+        BlockEvalData::reject_script_only(&mut engine);
 
         // TODO check for single expression?
-        let r = engine.eval_ast::<rhai::Dynamic>(&ast);
+        let r = engine.eval_ast::<rhai::Dynamic>(ast);
 
         // Update block state based on actions taken by the script
         let eval_data = std::mem::take(&mut *eval_data.write().unwrap());
         block.data = Some(ValueData {
             output: r.map_err(BlockError::Eval),
             view: eval_data.view.map(|scene| BlockView { scene }),
+            stale: false,
         });
 
         // Then, check whether we can bind outputs to the block name.  We'll
@@ -601,40 +1296,19 @@ impl World {
         // name errors, so will not replace an existing value in `data.error`
         let mut input_scope = eval_data.scope;
         let data = block.data.as_mut().unwrap();
-        if !rhai::is_valid_identifier(&block.name) {
+        if let Err(e) = Self::bind_name(i, &block.name, name_map) {
             if data.output.is_ok() {
-                data.output =
-                    Err(BlockError::Name(NameError::InvalidIdentifier));
+                data.output = Err(BlockError::Name(e));
             }
             return input_scope;
         }
 
-        // Next, bind from the name to a block index (if available)
-        match name_map.entry(block.name.clone()) {
-            std::collections::hash_map::Entry::Occupied(..) => {
-                if data.output.is_ok() {
-                    data.output =
-                        Err(BlockError::Name(NameError::DuplicateName));
-                }
-                return input_scope;
-            }
-            std::collections::hash_map::Entry::Vacant(v) => {
-                v.insert(i);
-            }
-        }
-
         if let Ok(value) = &data.output {
             input_scope.push(&block.name, value.clone());
             // If there's a single view-compatible output, then treat it as the
             // view.
             if data.view.is_none() {
-                if let Some(tree) = value.clone().try_cast::<Tree>() {
-                    data.view = Some(BlockView { scene: tree.into() })
-                } else if let Some(d) = value.clone().try_cast::<Drawable>() {
-                    data.view = Some(BlockView { scene: d.into() })
-                } else if let Some(scene) = value.clone().try_cast::<Scene>() {
-                    data.view = Some(BlockView { scene })
-                }
+                data.view = BlockView::from_value(value);
             }
         }
 
@@ -674,8 +1348,12 @@ impl World {
                             view,
                             io_values,
                             export,
+                            stale,
+                            warnings,
                         } = prev_data;
                         *stdout = new_data.stdout;
+                        *stale = new_data.stale;
+                        *warnings = new_data.warnings;
                         *debug = new_data.debug;
                         *error = new_data.error;
                         *view = new_data.view;
@@ -745,19 +1423,23 @@ impl World {
                 (Block::Value(b), Block::Value(ob)) => {
                     b.data = ob.data;
                 }
+                (Block::Note(..), Block::Note(..)) => (),
                 _ => warn!("cannot import data from different block types"),
             }
         }
+        self.last_good = other.last_good;
+        self.references = other.references;
     }
 }
 
 #[derive(Clone)]
 pub enum ExportRequest {
     Mesh {
-        tree: fidget::context::Tree,
+        scene: Scene,
         min: fidget::shapes::types::Vec3,
         max: fidget::shapes::types::Vec3,
         feature_size: f64,
+        format: MeshFormat,
     },
     Image {
         scene: Scene,
@@ -767,6 +1449,22 @@ pub enum ExportRequest {
     },
 }
 
+/// Checks whether an evaluation error was raised by a failed `assert` call
+///
+/// The error may be wrapped by calls to script-defined functions.
+fn is_assertion(
+    e: &rhai::EvalAltResult,
+    message: &str,
+    pos: rhai::Position,
+) -> bool {
+    match e.unwrap_inner() {
+        rhai::EvalAltResult::ErrorRuntime(v, p) => {
+            *p == pos && v.clone().into_string().is_ok_and(|v| v == message)
+        }
+        _ => false,
+    }
+}
+
 /// Handle to intermediate block data during evaluation
 #[derive(Default)]
 struct BlockEvalData {
@@ -777,6 +1475,9 @@ struct BlockEvalData {
 
     stdout: Vec<String>,
     debug: HashMap<usize, Vec<String>>,
+    warnings: Vec<Warning>,
+    /// Message and position of a failed `assert` call
+    assertion: Option<(String, rhai::Position)>,
     inputs: HashMap<String, String>,
     new_inputs: HashSet<String>,
     scope: rhai::Scope<'static>,
@@ -794,6 +1495,8 @@ impl BlockEvalData {
             export: None,
             stdout: vec![],
             debug: HashMap::new(),
+            warnings: vec![],
+            assertion: None,
             inputs,
             new_inputs: HashSet::new(),
             scope,
@@ -875,6 +1578,57 @@ impl BlockEvalData {
         v.map_err(|_| "error in input expression".into())
     }
 
+    fn export_mesh<T: Into<Scene>>(
+        &mut self,
+        ctx: rhai::NativeCallContext,
+        t: T,
+        min: fidget::shapes::types::Vec3,
+        max: fidget::shapes::types::Vec3,
+        feature_size: f64,
+        format: Option<&str>,
+    ) -> Result<(), Box<rhai::EvalAltResult>> {
+        if self.export.is_some() {
+            return Err(rhai::EvalAltResult::ErrorRuntime(
+                "cannot have multiple exports in a single block".into(),
+                ctx.call_position(),
+            )
+            .into());
+        }
+        if ![min.x, min.y, min.z, max.x, max.y, max.z]
+            .iter()
+            .all(|v| v.is_finite())
+        {
+            return Err(rhai::EvalAltResult::ErrorRuntime(
+                "export bounds must be finite".into(),
+                ctx.call_position(),
+            )
+            .into());
+        }
+        let format = match format {
+            None => MeshFormat::default(),
+            Some(f) => MeshFormat::from_script_name(f).ok_or_else(|| {
+                let names = MeshFormat::ALL
+                    .iter()
+                    .map(|f| format!("\"{}\"", f.script_name()))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                rhai::EvalAltResult::ErrorRuntime(
+                    format!("unknown mesh format \"{f}\"; expected {names}")
+                        .into(),
+                    ctx.call_position(),
+                )
+            })?,
+        };
+        self.export = Some(ExportRequest::Mesh {
+            scene: t.into(),
+            min,
+            max,
+            feature_size,
+            format,
+        });
+        Ok(())
+    }
+
     fn view<T: Into<Scene>>(
         &mut self,
         ctx: rhai::NativeCallContext,
@@ -891,6 +1645,40 @@ impl BlockEvalData {
         Ok(())
     }
 
+    /// Registers functions which are only available in script blocks
+    ///
+    /// Calling them is an error, rather than silently doing nothing.
+    fn reject_script_only(engine: &mut rhai::Engine) {
+        fn err(ctx: rhai::NativeCallContext) -> Box<rhai::EvalAltResult> {
+            rhai::EvalAltResult::ErrorRuntime(
+                format!(
+                    "`{}` is only available in script blocks",
+                    ctx.fn_name()
+                )
+                .into(),
+                ctx.call_position(),
+            )
+            .into()
+        }
+        engine.register_fn(
+            "warn",
+            |ctx: rhai::NativeCallContext,
+             _msg: &str|
+             -> Result<(), Box<rhai::EvalAltResult>> {
+                Err(err(ctx))
+            },
+        );
+        engine.register_fn(
+            "assert",
+            |ctx: rhai::NativeCallContext,
+             _cond: bool,
+             _msg: &str|
+             -> Result<(), Box<rhai::EvalAltResult>> {
+                Err(err(ctx))
+            },
+        );
+    }
+
     /// Binds `input`, `output`, `view`, `print`, and `debug`
     fn bind(eval_data: &Arc<RwLock<Self>>, engine: &mut rhai::Engine) {
         let eval_data_ = eval_data.clone();
@@ -908,6 +1696,37 @@ impl BlockEvalData {
             eval_data_.write().unwrap().stdout.push(s.to_owned())
         });
 
+        let eval_data_ = eval_data.clone();
+        engine.register_fn(
+            "warn",
+            move |ctx: rhai::NativeCallContext, msg: &str| {
+                eval_data_
+                    .write()
+                    .unwrap()
+                    .warnings
+                    .push(Warning::new(msg.to_owned(), ctx.call_position()))
+            },
+        );
+        let eval_data_ = eval_data.clone();
+        engine.register_fn(
+            "assert",
+            move |ctx: rhai::NativeCallContext,
+                  cond: bool,
+                  msg: &str|
+                  -> Result<(), Box<rhai::EvalAltResult>> {
+                if cond {
+                    return Ok(());
+                }
+                // Record the failure, then stop evaluation; if this error isn't
+                // caught, the block's error is replaced with a
+                // `BlockError::Assertion` afterwards.
+                let pos = ctx.call_position();
+                eval_data_.write().unwrap().assertion =
+                    Some((msg.to_owned(), pos));
+                Err(rhai::EvalAltResult::ErrorRuntime(msg.into(), pos).into())
+            },
+        );
+
         let eval_data_ = eval_data.clone();
         engine.register_fn(
             "input",
@@ -959,6 +1778,7 @@ impl BlockEvalData {
             },
         );
 
+        // `export_mesh` accepts a tree or scene, with an optional format name
         let eval_data_ = eval_data.clone();
         engine.register_fn(
             "export_mesh",
@@ -969,20 +1789,57 @@ impl BlockEvalData {
                   feature_size: f64|
                   -> Result<(), Box<rhai::EvalAltResult>> {
                 let mut eval_data = eval_data_.write().unwrap();
-                if eval_data.export.is_some() {
-                    return Err(rhai::EvalAltResult::ErrorRuntime(
-                        "cannot have multiple exports in a single block".into(),
-                        ctx.call_position(),
-                    )
-                    .into());
-                }
-                eval_data.export = Some(ExportRequest::Mesh {
-                    tree,
+                eval_data.export_mesh(ctx, tree, min, max, feature_size, None)
+            },
+        );
+        let eval_data_ = eval_data.clone();
+        engine.register_fn(
+            "export_mesh",
+            move |ctx: rhai::NativeCallContext,
+                  scene: Scene,
+                  min: fidget::shapes::types::Vec3,
+                  max: fidget::shapes::types::Vec3,
+                  feature_size: f64|
+                  -> Result<(), Box<rhai::EvalAltResult>> {
+                let mut eval_data = eval_data_.write().unwrap();
+                eval_data.export_mesh(ctx, scene, min, max, feature_size, None)
+            },
+        );
+        let eval_data_ = eval_data.clone();
+        engine.register_fn(
+            "export_mesh",
+            move |ctx: rhai::NativeCallContext,
+                  tree: fidget::context::Tree,
+                  min: fidget::shapes::types::Vec3,
+                  max: fidget::shapes::types::Vec3,
+                  feature_size: f64,
+                  format: &str|
+                  -> Result<(), Box<rhai::EvalAltResult>> {
+                let mut eval_data = eval_data_.write().unwrap();
+                let format = Some(format);
+                eval_data.export_mesh(ctx, tree, min, max, feature_size, format)
+            },
+        );
+        let eval_data_ = eval_data.clone();
+        engine.register_fn(
+            "export_mesh",
+            move |ctx: rhai::NativeCallContext,
+                  scene: Scene,
+                  min: fidget::shapes::types::Vec3,
+                  max: fidget::shapes::types::Vec3,
+                  feature_size: f64,
+                  format: &str|
+                  -> Result<(), Box<rhai::EvalAltResult>> {
+                let mut eval_data = eval_data_.write().unwrap();
+                let format = Some(format);
+                eval_data.export_mesh(
+                    ctx,
+                    scene,
                     min,
                     max,
                     feature_size,
-                });
-                Ok(())
+                    format,
+                )
             },
         );
         let eval_data_ = eval_data.clone();
@@ -1002,6 +1859,13 @@ impl BlockEvalData {
                     )
                     .into());
                 }
+                if ![min.x, min.y, max.x, max.y].iter().all(|v| v.is_finite()) {
+                    return Err(rhai::EvalAltResult::ErrorRuntime(
+                        "export bounds must be finite".into(),
+                        ctx.call_position(),
+                    )
+                    .into());
+                }
                 eval_data.export = Some(ExportRequest::Image {
                     scene,
                     min,
@@ -1013,3 +1877,314 @@ impl BlockEvalData {
         );
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use fidget::render::CancelToken;
+
+    fn script(name: &str, script: &str, inputs: &[(&str, &str)]) -> BlockState {
+        BlockState::Script(ScriptState {
+            name: name.to_owned(),
+            script: script.to_owned(),
+            inputs: inputs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            disabled: false,
+            passthrough: None,
+        })
+    }
+
+    fn value(name: &str, input: &str) -> BlockState {
+        BlockState::Value(ValueState {
+            name: name.to_owned(),
+            input: input.to_owned(),
+            disabled: false,
+        })
+    }
+
+    fn note(name: &str, text: &str) -> BlockState {
+        BlockState::Note(NoteState {
+            name: name.to_owned(),
+            text: text.to_owned(),
+        })
+    }
+
+    /// Builds a world state with blocks in the given order
+    fn state(blocks: Vec<BlockState>) -> WorldState {
+        let order = (0..blocks.len() as u64)
+            .map(BlockIndex::new)
+            .collect::<Vec<_>>();
+        WorldState {
+            next_index: blocks.len() as u64,
+            blocks: order.iter().cloned().zip(blocks).collect(),
+            order,
+            keep_stale: false,
+        }
+    }
+
+    /// Returns the integer output of a value block
+    fn int_value(world: &World, i: u64) -> i64 {
+        let Block::Value(v) = &world.blocks[&BlockIndex::new(i)] else {
+            panic!("not a value block");
+        };
+        let out = v.data.as_ref().unwrap().output.as_ref();
+        out.unwrap_or_else(|e| panic!("{}", e.print_chain()))
+            .as_int()
+            .unwrap()
+    }
+
+    #[test]
+    fn disabled_passthrough() {
+        let mut s = state(vec![
+            value("a", "2"),
+            script("b", "output(\"y\", input(\"x\") * 10);", &[("x", "a")]),
+            value("c", "b + 1"),
+        ]);
+        let world = World::from(s.clone());
+        assert_eq!(int_value(&world, 2), 21);
+
+        // A disabled block forwards its pass-through input unchanged
+        let BlockState::Script(b) =
+            s.blocks.get_mut(&BlockIndex::new(1)).unwrap()
+        else {
+            unreachable!()
+        };
+        b.disabled = true;
+        b.passthrough = Some("x".to_owned());
+        let world = World::from(s.clone());
+        assert!(world.blocks[&BlockIndex::new(1)].is_valid());
+        assert_eq!(int_value(&world, 2), 3);
+
+        // Without a pass-through input, the block has no value
+        let BlockState::Script(b) =
+            s.blocks.get_mut(&BlockIndex::new(1)).unwrap()
+        else {
+            unreachable!()
+        };
+        b.passthrough = None;
+        let world = World::from(s);
+        assert!(world.blocks[&BlockIndex::new(1)].is_valid());
+        assert!(!world.blocks[&BlockIndex::new(2)].is_valid());
+    }
+
+    #[test]
+    fn notes_are_skipped() {
+        // Notes don't reserve their names
+        let s = state(vec![
+            note("a", "# Design notes"),
+            value("a", "1"),
+            value("c", "a + 1"),
+        ]);
+        let world = World::from(s.clone());
+        assert!(world.blocks[&BlockIndex::new(0)].is_valid());
+        assert_eq!(int_value(&world, 2), 2);
+        assert!(world == s);
+        assert_eq!(WorldState::from(&world), s);
+    }
+
+    #[test]
+    fn upstream_errors() {
+        let s = state(vec![
+            value("a", "1 +"),
+            value("b", "a + 1"),
+            value("c", "b * 2"),
+            value("d", "undefined_name"),
+            script("e", "let a = 2; output(\"v\", a + missing);", &[]),
+        ]);
+        let world = World::from(s);
+        let a = BlockIndex::new(0);
+        assert!(matches!(
+            world.blocks[&a].error(),
+            Some(BlockError::Parse(..))
+        ));
+
+        // Blocks which refer to a failed block report the root cause
+        let Some(BlockError::Upstream { name, root, .. }) =
+            world.blocks[&BlockIndex::new(1)].error()
+        else {
+            panic!("expected upstream error");
+        };
+        assert_eq!((name.as_str(), *root), ("a", a));
+        let Some(BlockError::Upstream { name, root, .. }) =
+            world.blocks[&BlockIndex::new(2)].error()
+        else {
+            panic!("expected upstream error");
+        };
+        assert_eq!((name.as_str(), *root), ("b", a));
+
+        // Unrelated failures keep their own error
+        assert!(matches!(
+            world.blocks[&BlockIndex::new(3)].error(),
+            Some(BlockError::Eval(..))
+        ));
+
+        // Local variables shadow the failed block's name, so this block's
+        // failure is its own
+        assert!(matches!(
+            world.blocks[&BlockIndex::new(4)].error(),
+            Some(BlockError::Eval(..))
+        ));
+    }
+
+    #[test]
+    fn keep_stale() {
+        let mut s = state(vec![value("a", "2"), value("b", "a * 3")]);
+        s.keep_stale = true;
+        let world = World::build(s.clone(), HashMap::new(), CancelToken::new());
+        assert_eq!(int_value(&world, 1), 6);
+        let last_good = world.last_good().clone();
+
+        // Break the upstream block; its last good value is still used
+        let BlockState::Value(a) =
+            s.blocks.get_mut(&BlockIndex::new(0)).unwrap()
+        else {
+            unreachable!()
+        };
+        a.input = "2 +".to_owned();
+        let world =
+            World::build(s.clone(), last_good.clone(), CancelToken::new());
+        let a = &world.blocks[&BlockIndex::new(0)];
+        assert!(a.error().is_some());
+        assert!(a.is_stale());
+        assert!(!world.blocks[&BlockIndex::new(1)].is_stale());
+        assert_eq!(int_value(&world, 1), 6);
+
+        // Without the option, downstream blocks fail
+        s.keep_stale = false;
+        let world = World::build(s, last_good, CancelToken::new());
+        assert!(!world.blocks[&BlockIndex::new(0)].is_stale());
+        assert!(matches!(
+            world.blocks[&BlockIndex::new(1)].error(),
+            Some(BlockError::Upstream { .. })
+        ));
+    }
+
+    #[test]
+    fn insert_rewires() {
+        let lib = ShapeLibrary::build();
+        let shape = |name: &str| lib.shapes.iter().find(|s| s.name == name);
+        let inputs = |world: &World, i: u64| {
+            let Block::Script(s) = &world.blocks[&BlockIndex::new(i)] else {
+                panic!("not a script block");
+            };
+            s.inputs.clone()
+        };
+
+        let mut world = World::new();
+        assert!(world.new_block_from(shape("Sphere").unwrap()));
+        world.rebuild(&CancelToken::new());
+        assert!(world.new_block_from(shape("Scale").unwrap()));
+        world.rebuild(&CancelToken::new());
+        assert_eq!(inputs(&world, 1)["shape"], "sphere");
+
+        // Inserting into the middle of the chain rewires the next block
+        let sphere = BlockIndex::new(0);
+        assert!(world.new_block_after(shape("Scale").unwrap(), Some(sphere)));
+        world.rebuild(&CancelToken::new());
+        assert_eq!(
+            world.order,
+            [0, 2, 1].map(BlockIndex::new).to_vec(),
+            "new block should be placed after the selected block"
+        );
+        assert_eq!(inputs(&world, 2)["shape"], "sphere");
+        assert_eq!(inputs(&world, 1)["shape"], "scale_000");
+        assert!(world.blocks.values().all(|b| b.error().is_none()));
+
+        // Inserting after a missing block fails
+        assert!(!world.new_block_after(
+            shape("Scale").unwrap(),
+            Some(BlockIndex::new(100))
+        ));
+    }
+
+    #[test]
+    fn keep_stale_eval_errors() {
+        let mut s = state(vec![
+            value("x", "2"),
+            value("a", "6 / x"),
+            script("b", "output(\"v\", 6 / input(\"n\"));", &[("n", "x")]),
+            value("c", "a + b"),
+            note("x", "Not a value"),
+        ]);
+        s.keep_stale = true;
+        let world = World::build(s.clone(), HashMap::new(), CancelToken::new());
+        assert_eq!(int_value(&world, 3), 6);
+        let last_good = world.last_good().clone();
+        assert!(!last_good.contains_key(&BlockIndex::new(4)));
+
+        // Dividing by zero fails during evaluation, after both blocks have
+        // bound their names; their last good values are still passed on.
+        let BlockState::Value(x) =
+            s.blocks.get_mut(&BlockIndex::new(0)).unwrap()
+        else {
+            unreachable!()
+        };
+        x.input = "0".to_owned();
+        let world = World::build(s, last_good, CancelToken::new());
+        for i in [1, 2] {
+            let b = &world.blocks[&BlockIndex::new(i)];
+            assert!(matches!(b.error(), Some(BlockError::Eval(..))));
+            assert!(b.is_stale());
+        }
+        assert!(world.blocks[&BlockIndex::new(3)].error().is_none());
+        assert_eq!(int_value(&world, 3), 6);
+    }
+
+    #[test]
+    fn insert_fills_bounds() {
+        let lib = ShapeLibrary::build();
+        let shape = |name: &str| lib.shapes.iter().find(|s| s.name == name);
+
+        let mut world = World::new();
+        assert!(world.new_block_from(shape("Sphere").unwrap()));
+        world.rebuild(&CancelToken::new());
+        assert!(world.take_bounds_request().is_none());
+
+        // Bounds are found separately, then filled into the new block
+        assert!(world.new_block_from(shape("Export (mesh)").unwrap()));
+        let fill = world.take_bounds_request().unwrap().run().unwrap();
+        let export = world.order[1];
+        let Block::Script(s) = &world.blocks[&export] else {
+            panic!("not a script block");
+        };
+        let lower = s.inputs["lower"].clone();
+        assert!(world.fill_bounds(fill));
+        let Block::Script(s) = &world.blocks[&export] else {
+            panic!("not a script block");
+        };
+        assert_ne!(s.inputs["lower"], lower);
+
+        // Bounds which were edited in the meantime are left alone
+        let sphere = world.order[0];
+        let export = shape("Export (mesh)").unwrap();
+        assert!(world.new_block_after(export, Some(sphere)));
+        let fill = world.take_bounds_request().unwrap().run();
+        let Block::Script(s) = world.blocks.get_mut(&world.order[1]).unwrap()
+        else {
+            panic!("not a script block");
+        };
+        s.inputs.insert("lower".to_owned(), "[0, 0, 0]".to_owned());
+        assert!(!world.fill_bounds(fill.unwrap()));
+    }
+
+    #[test]
+    fn cancelled_rebuild() {
+        let s = state(vec![value(
+            "m",
+            "mass_properties(sphere([0, 0, 0], 1), [-2, -2, -2], [2, 2, 2], 0.05)",
+        )]);
+        let world = World::build(s.clone(), HashMap::new(), CancelToken::new());
+        assert!(world.blocks[&BlockIndex::new(0)].error().is_none());
+
+        // Meshing is abandoned if the rebuild is cancelled
+        let cancel = CancelToken::new();
+        cancel.cancel();
+        let world = World::build(s, HashMap::new(), cancel);
+        assert!(matches!(
+            world.blocks[&BlockIndex::new(0)].error(),
+            Some(BlockError::Eval(..))
+        ));
+    }
+}
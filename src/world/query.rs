@@ -0,0 +1,540 @@
+//! Point, gradient, ray, and bounds queries on math trees
+//!
+//! These are available to scripts, so that features can be placed relative to
+//! a shape's surface (e.g. dropping a boss onto a curved shell with `raycast`)
+//! instead of at hard-coded coordinates.
+use std::cell::RefCell;
+
+use fidget::{
+    context::Tree,
+    rhai::FromDynamic,
+    shape::EzShape,
+    shapes::types::Vec3,
+    types::{Grad, Interval},
+    vm::VmShape,
+};
+
+use super::Scene;
+
+/// Number of times a ray is halved (using interval arithmetic to skip empty
+/// segments) before checking individual segments for a sign change
+const MAX_RAY_DEPTH: usize = 16;
+
+/// Number of bisection steps used to refine a ray hit
+const REFINE_STEPS: usize = 32;
+
+/// Half-size of the region searched by [`bounds`]
+///
+/// Shapes which extend past this region are treated as unbounded.
+const SEARCH_SIZE: f64 = 1e6;
+
+/// Subdivision depth when shrinking the search region in [`bounds`]
+const SHRINK_DEPTH: usize = 3;
+
+/// Subdivision depth when refining each side of the box in [`bounds`]
+///
+/// Sides are accurate to `1 / 2^10` of the (shrunk) search region's size.
+const REFINE_DEPTH: usize = 10;
+
+thread_local! {
+    /// Most recently built shape, along with the tree it was built from
+    ///
+    /// Scripts often query the same tree many times (e.g. in a loop), and
+    /// building a shape is much more expensive than evaluating it.  Holding a
+    /// clone of the tree keeps its allocation alive, so comparing tree
+    /// pointers is enough to check whether the cache is still valid.
+    static SHAPE_CACHE: RefCell<Option<(Tree, VmShape)>> =
+        const { RefCell::new(None) };
+}
+
+/// Returns a shape for the given tree, reusing the cached shape if possible
+fn shape(tree: &Tree) -> VmShape {
+    SHAPE_CACHE.with_borrow_mut(|cache| {
+        if let Some((t, s)) = cache
+            && std::ptr::eq(&**t, &**tree)
+        {
+            return s.clone();
+        }
+        let s = VmShape::from(tree.clone());
+        *cache = Some((tree.clone(), s.clone()));
+        s
+    })
+}
+
+/// Evaluates a tree at a single point
+pub fn eval_at(tree: &Tree, p: Vec3) -> Result<f64, fidget::Error> {
+    let shape = shape(tree);
+    let tape = shape.ez_point_tape();
+    let mut eval = VmShape::new_point_eval();
+    let (v, _trace) = eval.eval(&tape, p.x as f32, p.y as f32, p.z as f32)?;
+    Ok(v.into())
+}
+
+/// Evaluates a tree's gradient at a single point
+pub fn gradient_at(tree: &Tree, p: Vec3) -> Result<Vec3, fidget::Error> {
+    let shape = shape(tree);
+    let tape = shape.ez_grad_slice_tape();
+    let mut eval = VmShape::new_grad_slice_eval();
+    let out = eval.eval(
+        &tape,
+        &[Grad::new(p.x as f32, 1.0, 0.0, 0.0)],
+        &[Grad::new(p.y as f32, 0.0, 1.0, 0.0)],
+        &[Grad::new(p.z as f32, 0.0, 0.0, 1.0)],
+    )?;
+    let g = out[0];
+    Ok(Vec3 {
+        x: g.dx.into(),
+        y: g.dy.into(),
+        z: g.dz.into(),
+    })
+}
+
+/// Finds the first point along a ray where the tree is `<= 0`
+///
+/// Returns the ray's origin if it starts inside the shape, or `None` if the ray
+/// doesn't hit the shape within `max_dist`.  Points where the tree evaluates to
+/// NaN are treated as outside the shape.
+///
+/// The ray is subdivided, using interval arithmetic to skip segments which
+/// can't contain the surface; then each remaining segment is checked for a
+/// sign change and refined by bisection.  Features which are thinner than
+/// `max_dist / 2^16` may be missed.
+pub fn raycast(
+    tree: &Tree,
+    origin: Vec3,
+    dir: Vec3,
+    max_dist: f64,
+) -> Result<Option<Vec3>, fidget::Error> {
+    let norm = (dir.x * dir.x + dir.y * dir.y + dir.z * dir.z).sqrt();
+    let at = |t: f64| Vec3 {
+        x: origin.x + dir.x / norm * t,
+        y: origin.y + dir.y / norm * t,
+        z: origin.z + dir.z / norm * t,
+    };
+
+    let shape = shape(tree);
+    let point_tape = shape.ez_point_tape();
+    let mut point_eval = VmShape::new_point_eval();
+    let mut point = |t: f64| -> Result<f32, fidget::Error> {
+        let p = at(t);
+        let (v, _trace) =
+            point_eval.eval(&point_tape, p.x as f32, p.y as f32, p.z as f32)?;
+        Ok(v)
+    };
+    let interval_tape = shape.ez_interval_tape();
+    let mut interval_eval = VmShape::new_interval_eval();
+    let mut interval = |t0: f64, t1: f64| -> Result<Interval, fidget::Error> {
+        let (a, b) = (at(t0), at(t1));
+        let range =
+            |a: f64, b: f64| Interval::new(a.min(b) as f32, a.max(b) as f32);
+        let (v, _trace) = interval_eval.eval(
+            &interval_tape,
+            range(a.x, b.x),
+            range(a.y, b.y),
+            range(a.z, b.z),
+        )?;
+        Ok(v)
+    };
+
+    // Segments are checked in order along the ray, so the first hit is the
+    // closest one.
+    let mut todo = vec![(0.0, max_dist, 0)];
+    while let Some((t0, t1, depth)) = todo.pop() {
+        let i = interval(t0, t1)?;
+        if i.lower() > 0.0 {
+            continue;
+        } else if i.upper() <= 0.0 {
+            return Ok(Some(at(t0)));
+        } else if depth < MAX_RAY_DEPTH {
+            let mid = (t0 + t1) / 2.0;
+            todo.push((mid, t1, depth + 1));
+            todo.push((t0, mid, depth + 1));
+            continue;
+        }
+        // Comparisons are written so that NaN values count as outside, and
+        // `hi` only ever moves to points which are known to be inside.
+        if point(t0)? <= 0.0 {
+            return Ok(Some(at(t0)));
+        }
+        let v1 = point(t1)?;
+        if v1 > 0.0 || v1.is_nan() {
+            continue;
+        }
+        let (mut lo, mut hi) = (t0, t1);
+        for _ in 0..REFINE_STEPS {
+            let mid = (lo + hi) / 2.0;
+            if point(mid)? <= 0.0 {
+                hi = mid;
+            } else {
+                lo = mid;
+            }
+        }
+        return Ok(Some(at(hi)));
+    }
+    Ok(None)
+}
+
+/// Axis-aligned box, as `(lower, upper)` corners
+type Region = ([f64; 3], [f64; 3]);
+
+/// Computes an axis-aligned bounding box for a tree
+///
+/// Returns `(lower, upper)` corners, or `None` if the shape is empty.  Sides
+/// on which the shape extends past `±1e6` (e.g. the Z axis of a 2D shape) are
+/// infinite.
+///
+/// Bounds are found with interval arithmetic, so they're conservative: the
+/// shape is always inside the box, but the box may be slightly larger than the
+/// shape.
+pub fn bounds(tree: &Tree) -> Result<Option<(Vec3, Vec3)>, fidget::Error> {
+    let shape = shape(tree);
+    let tape = shape.ez_interval_tape();
+    let mut eval = VmShape::new_interval_eval();
+    let mut empty = |(lo, hi): Region| -> Result<bool, fidget::Error> {
+        let i = |a: usize| Interval::new(lo[a] as f32, hi[a] as f32);
+        let (v, _trace) = eval.eval(&tape, i(0), i(1), i(2))?;
+        Ok(v.lower() > 0.0)
+    };
+
+    // Shrink the search region to the union of cells which may contain the
+    // shape, until it stops shrinking
+    let mut region = ([-SEARCH_SIZE; 3], [SEARCH_SIZE; 3]);
+    loop {
+        let mut next: Option<Region> = None;
+        let mut todo = vec![(region, 0)];
+        while let Some((cell, depth)) = todo.pop() {
+            // Cells within the union can't expand it
+            if next.is_some_and(|n| contains(n, cell)) || empty(cell)? {
+                continue;
+            }
+            if depth < SHRINK_DEPTH {
+                todo.extend(octants(cell).map(|c| (c, depth + 1)));
+                continue;
+            }
+            next = Some(match next {
+                Some((lo, hi)) => (
+                    std::array::from_fn(|a| lo[a].min(cell.0[a])),
+                    std::array::from_fn(|a| hi[a].max(cell.1[a])),
+                ),
+                None => cell,
+            });
+        }
+        let Some(next) = next else {
+            return Ok(None);
+        };
+        if next == region {
+            break;
+        }
+        region = next;
+    }
+
+    // Then, find the extreme cells on each side of the region
+    let mut lower = [f64::NEG_INFINITY; 3];
+    let mut upper = [f64::INFINITY; 3];
+    for a in 0..3 {
+        if region.0[a] > -SEARCH_SIZE {
+            let Some(v) = extreme(&mut empty, region, |(lo, _)| lo[a])? else {
+                return Ok(None);
+            };
+            lower[a] = v;
+        }
+        if region.1[a] < SEARCH_SIZE {
+            let Some(v) = extreme(&mut empty, region, |(_, hi)| -hi[a])? else {
+                return Ok(None);
+            };
+            upper[a] = -v;
+        }
+    }
+    let v = |p: [f64; 3]| Vec3 {
+        x: p[0],
+        y: p[1],
+        z: p[2],
+    };
+    Ok(Some((v(lower), v(upper))))
+}
+
+/// Computes an axis-aligned bounding box for every shape in a scene
+///
+/// See [`bounds`] for details.
+pub fn scene_bounds(
+    scene: &Scene,
+) -> Result<Option<(Vec3, Vec3)>, fidget::Error> {
+    let mut out: Option<(Vec3, Vec3)> = None;
+    for d in &scene.shapes {
+        let Some((lo, hi)) = bounds(&d.tree)? else {
+            continue;
+        };
+        out = Some(match out {
+            Some((a, b)) => (
+                Vec3 {
+                    x: a.x.min(lo.x),
+                    y: a.y.min(lo.y),
+                    z: a.z.min(lo.z),
+                },
+                Vec3 {
+                    x: b.x.max(hi.x),
+                    y: b.y.max(hi.y),
+                    z: b.z.max(hi.z),
+                },
+            ),
+            None => (lo, hi),
+        });
+    }
+    Ok(out)
+}
+
+/// Computes the bounds of a tree, drawable, or scene value
+///
+/// Returns `None` for other types, for empty shapes, or if evaluation fails.
+pub(crate) fn value_bounds(value: &rhai::Dynamic) -> Option<(Vec3, Vec3)> {
+    let scene = if let Some(tree) = value.clone().try_cast::<Tree>() {
+        Scene::from(tree)
+    } else if let Some(d) = value.clone().try_cast::<super::Drawable>() {
+        Scene::from(d)
+    } else {
+        value.clone().try_cast::<Scene>()?
+    };
+    scene_bounds(&scene).ok().flatten()
+}
+
+/// Finds the smallest value of `key` for cells which may contain the shape
+///
+/// This is a depth-first search which visits the most promising cells first,
+/// and skips cells which can't improve on the best leaf found so far.
+fn extreme(
+    empty: &mut impl FnMut(Region) -> Result<bool, fidget::Error>,
+    region: Region,
+    key: impl Fn(Region) -> f64,
+) -> Result<Option<f64>, fidget::Error> {
+    let mut best: Option<f64> = None;
+    let mut todo = vec![(region, 0)];
+    while let Some((cell, depth)) = todo.pop() {
+        if best.is_some_and(|b| key(cell) >= b) || empty(cell)? {
+            continue;
+        }
+        if depth == REFINE_DEPTH {
+            best = Some(key(cell));
+            continue;
+        }
+        // Push the best children last, so that they're checked first
+        let mut children = octants(cell).collect::<Vec<_>>();
+        children.sort_by(|a, b| key(*b).total_cmp(&key(*a)));
+        todo.extend(children.into_iter().map(|c| (c, depth + 1)));
+    }
+    Ok(best)
+}
+
+/// Checks whether `outer` contains `inner`
+fn contains(outer: Region, inner: Region) -> bool {
+    (0..3).all(|a| outer.0[a] <= inner.0[a] && inner.1[a] <= outer.1[a])
+}
+
+/// Splits a cell into eight octants
+fn octants((lo, hi): Region) -> impl Iterator<Item = Region> {
+    let mid: [f64; 3] = std::array::from_fn(|a| (lo[a] + hi[a]) / 2.0);
+    (0..8).map(move |i| {
+        let (mut lo, mut hi) = (lo, hi);
+        for a in 0..3 {
+            if i & (1 << a) == 0 {
+                hi[a] = mid[a];
+            } else {
+                lo[a] = mid[a];
+            }
+        }
+        (lo, hi)
+    })
+}
+
+/// Converts bounds into a script value
+///
+/// Bounds are returned as an object map `#{ lower, upper }`, or `()` if the
+/// shape is empty.
+fn bounds_value(b: Option<(Vec3, Vec3)>) -> rhai::Dynamic {
+    let Some((lower, upper)) = b else {
+        return rhai::Dynamic::UNIT;
+    };
+    let mut map = rhai::Map::new();
+    map.insert("lower".into(), rhai::Dynamic::from(lower));
+    map.insert("upper".into(), rhai::Dynamic::from(upper));
+    map.into()
+}
+
+/// Converts a `fidget` error into a script error
+fn script_error(
+    ctx: &rhai::NativeCallContext,
+    e: fidget::Error,
+) -> Box<rhai::EvalAltResult> {
+    rhai::EvalAltResult::ErrorRuntime(e.to_string().into(), ctx.call_position())
+        .into()
+}
+
+/// Converts a script argument into a point
+fn point_arg(
+    ctx: &rhai::NativeCallContext,
+    x: rhai::Dynamic,
+    y: rhai::Dynamic,
+    z: rhai::Dynamic,
+) -> Result<Vec3, Box<rhai::EvalAltResult>> {
+    Ok(Vec3 {
+        x: f64::from_dynamic(ctx, x, None)?,
+        y: f64::from_dynamic(ctx, y, None)?,
+        z: f64::from_dynamic(ctx, z, None)?,
+    })
+}
+
+/// Registers query functions with the engine
+pub fn register(engine: &mut rhai::Engine) {
+    engine.register_fn(
+        "eval_at",
+        |ctx: rhai::NativeCallContext,
+         tree: Tree,
+         x: rhai::Dynamic,
+         y: rhai::Dynamic,
+         z: rhai::Dynamic|
+         -> Result<f64, Box<rhai::EvalAltResult>> {
+            let p = point_arg(&ctx, x, y, z)?;
+            eval_at(&tree, p).map_err(|e| script_error(&ctx, e))
+        },
+    );
+    engine.register_fn(
+        "eval_at",
+        |ctx: rhai::NativeCallContext,
+         tree: Tree,
+         p: rhai::Dynamic|
+         -> Result<f64, Box<rhai::EvalAltResult>> {
+            let p = Vec3::from_dynamic(&ctx, p, None)?;
+            eval_at(&tree, p).map_err(|e| script_error(&ctx, e))
+        },
+    );
+    engine.register_fn(
+        "gradient_at",
+        |ctx: rhai::NativeCallContext,
+         tree: Tree,
+         x: rhai::Dynamic,
+         y: rhai::Dynamic,
+         z: rhai::Dynamic|
+         -> Result<Vec3, Box<rhai::EvalAltResult>> {
+            let p = point_arg(&ctx, x, y, z)?;
+            gradient_at(&tree, p).map_err(|e| script_error(&ctx, e))
+        },
+    );
+    engine.register_fn(
+        "gradient_at",
+        |ctx: rhai::NativeCallContext,
+         tree: Tree,
+         p: rhai::Dynamic|
+         -> Result<Vec3, Box<rhai::EvalAltResult>> {
+            let p = Vec3::from_dynamic(&ctx, p, None)?;
+            gradient_at(&tree, p).map_err(|e| script_error(&ctx, e))
+        },
+    );
+    engine.register_fn(
+        "raycast",
+        |ctx: rhai::NativeCallContext,
+         tree: Tree,
+         origin: rhai::Dynamic,
+         dir: rhai::Dynamic,
+         max_dist: rhai::Dynamic|
+         -> Result<rhai::Dynamic, Box<rhai::EvalAltResult>> {
+            let origin = Vec3::from_dynamic(&ctx, origin, None)?;
+            let dir = Vec3::from_dynamic(&ctx, dir, None)?;
+            let max_dist = f64::from_dynamic(&ctx, max_dist, None)?;
+            if dir.x == 0.0 && dir.y == 0.0 && dir.z == 0.0 {
+                return Err(rhai::EvalAltResult::ErrorRuntime(
+                    "ray direction must be non-zero".into(),
+                    ctx.call_position(),
+                )
+                .into());
+            } else if !max_dist.is_finite() || max_dist <= 0.0 {
+                return Err(rhai::EvalAltResult::ErrorRuntime(
+                    "maximum ray distance must be positive and finite".into(),
+                    ctx.call_position(),
+                )
+                .into());
+            }
+            let hit = raycast(&tree, origin, dir, max_dist)
+                .map_err(|e| script_error(&ctx, e))?;
+            Ok(hit.map(rhai::Dynamic::from).unwrap_or(rhai::Dynamic::UNIT))
+        },
+    );
+    engine.register_fn(
+        "bounds",
+        |ctx: rhai::NativeCallContext,
+         tree: Tree|
+         -> Result<rhai::Dynamic, Box<rhai::EvalAltResult>> {
+            let b = bounds(&tree).map_err(|e| script_error(&ctx, e))?;
+            Ok(bounds_value(b))
+        },
+    );
+    engine.register_fn(
+        "bounds",
+        |ctx: rhai::NativeCallContext,
+         scene: Scene|
+         -> Result<rhai::Dynamic, Box<rhai::EvalAltResult>> {
+            let b = scene_bounds(&scene).map_err(|e| script_error(&ctx, e))?;
+            Ok(bounds_value(b))
+        },
+    );
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn sphere() -> Tree {
+        let (x, y, z) = (Tree::x(), Tree::y(), Tree::z());
+        (x.square() + y.square() + z.square()).sqrt() - 1.0
+    }
+
+    #[test]
+    fn sphere_queries() {
+        let s = sphere();
+        let v = |x, y, z| Vec3 { x, y, z };
+        let d = eval_at(&s, v(0.0, 0.0, 0.0)).unwrap();
+        assert!((d + 1.0).abs() < 1e-6);
+
+        let g = gradient_at(&s, v(2.0, 0.0, 0.0)).unwrap();
+        assert!((g.x - 1.0).abs() < 1e-6);
+        assert!(g.y.abs() < 1e-6 && g.z.abs() < 1e-6);
+
+        let hit = raycast(&s, v(0.0, 0.0, 5.0), v(0.0, 0.0, -2.0), 10.0)
+            .unwrap()
+            .unwrap();
+        assert!((hit.z - 1.0).abs() < 1e-4);
+        assert!(hit.x.abs() < 1e-6 && hit.y.abs() < 1e-6);
+
+        let miss = raycast(&s, v(0.0, 0.0, 5.0), v(0.0, 0.0, 1.0), 10.0);
+        assert!(miss.unwrap().is_none());
+
+        let inside = raycast(&s, v(0.0, 0.0, 0.0), v(1.0, 0.0, 0.0), 10.0)
+            .unwrap()
+            .unwrap();
+        assert_eq!(inside.x, 0.0);
+
+        // A tree which is NaN along the whole ray is never hit
+        let nan = Tree::x().sqrt();
+        let miss = raycast(&nan, v(-1.0, 0.0, 0.0), v(-1.0, 0.0, 0.0), 10.0);
+        assert!(miss.unwrap().is_none());
+    }
+
+    #[test]
+    fn sphere_bounds() {
+        let (lo, hi) = bounds(&sphere()).unwrap().unwrap();
+        for (lo, hi) in [(lo.x, hi.x), (lo.y, hi.y), (lo.z, hi.z)] {
+            assert!(lo <= -1.0 && lo > -1.01, "bad lower bound {lo}");
+            assert!(hi >= 1.0 && hi < 1.01, "bad upper bound {hi}");
+        }
+
+        // A circle is unbounded on the Z axis
+        let (x, y) = (Tree::x(), Tree::y());
+        let circle = (x.square() + y.square()).sqrt() - 2.0;
+        let (lo, hi) = bounds(&circle).unwrap().unwrap();
+        assert!(lo.x <= -2.0 && lo.x > -2.02);
+        assert!(hi.y >= 2.0 && hi.y < 2.02);
+        assert_eq!(lo.z, f64::NEG_INFINITY);
+        assert_eq!(hi.z, f64::INFINITY);
+
+        let empty = Tree::x().square() + 1.0;
+        assert!(bounds(&empty).unwrap().is_none());
+    }
+}
@@ -17,13 +17,42 @@ struct Visitor {
 
 pub struct ShapeLibrary {
     pub shapes: Vec<ShapeDefinition>,
+
+    /// Constructor functions for fidget shapes, available in scripts
+    pub constructors: Vec<ShapeConstructor>,
+}
+
+/// A shape constructor function, e.g. `sphere(#{ center, radius })`
+pub struct ShapeConstructor {
+    /// Name of the function
+    pub name: String,
+    /// Documentation for the shape
+    pub doc: String,
+    /// Fields of the object passed to the constructor, with documentation
+    pub fields: Vec<(String, String)>,
+}
+
+impl ShapeConstructor {
+    /// Returns a signature suitable for display
+    pub fn signature(&self) -> String {
+        let fields = self
+            .fields
+            .iter()
+            .map(|(f, _)| f.as_str())
+            .collect::<Vec<_>>()
+            .join(", ");
+        format!("{}(#{{ {fields} }}) -> Tree", self.name)
+    }
 }
 
 impl ShapeLibrary {
     pub fn build() -> Self {
         let mut v = Visitor {
             names: HashSet::new(),
-            lib: ShapeLibrary { shapes: vec![] },
+            lib: ShapeLibrary {
+                shapes: vec![],
+                constructors: vec![],
+            },
         };
         v.names.insert("Script".to_owned());
         v.lib.shapes.push(ShapeDefinition {
@@ -41,6 +70,13 @@ impl ShapeLibrary {
             },
             category: ShapeCategory::Halfspace,
         });
+        v.lib.shapes.push(ShapeDefinition {
+            name: "Note".to_owned(),
+            kind: ShapeKind::Note {
+                text: "".to_owned(),
+            },
+            category: ShapeCategory::Halfspace,
+        });
         v.lib.shapes.push(ShapeDefinition {
             name: "Export (mesh)".to_owned(),
             kind: ShapeKind::Script {
@@ -74,6 +110,13 @@ impl ShapeLibrary {
                             text: "0.1".to_owned(),
                         },
                     ),
+                    (
+                        "format".to_string(),
+                        ShapeInput {
+                            ty: Some(String::SHAPE.id),
+                            text: "\"stl\"".to_owned(),
+                        },
+                    ),
                 ]
                 .into_iter()
                 .collect(),
@@ -127,19 +170,87 @@ impl ShapeLibrary {
 }
 
 const EXPORT_MESH_SCRIPT: &str = r#"// Script to export a mesh
+//
+// `lower` and `upper` are filled in from the shape's bounds when this block is
+// inserted after a shape; use `bounds(shape)` to recompute them.
+//
+// `format` is the default file format, which may also be picked next to the
+// export button.  It's one of "stl", "stl_ascii", "obj", "ply", "3mf", or
+// "glb"; the last three also store colors from drawables in a scene.
 let shape = input("shape");
 let lower = input("lower");
 let upper = input("upper");
 let min_feature = input("min_feature");
-export_mesh(shape, vec3(lower), vec3(upper), min_feature.to_float());"#;
+let format = input("format");
+export_mesh(shape, vec3(lower), vec3(upper), min_feature.to_float(), format);"#;
 
-const EXPORT_IMAGE_SCRIPT: &str = r#"// Script to export a mesh
+const EXPORT_IMAGE_SCRIPT: &str = r#"// Script to export an image
+//
+// `lower` and `upper` are filled in from the scene's bounds when this block is
+// inserted after a shape; use `bounds(scene)` to recompute them.
 let scene = input("scene");
 let lower = input("lower");
 let upper = input("upper");
 let resolution = input("resolution");
 export_image(scene(scene), vec2(lower), vec2(upper), resolution.to_float());"#;
 
+/// Fraction of the largest dimension used to pad bounds in [`bounds_literals`]
+const BOUNDS_PADDING: f64 = 0.05;
+
+/// Returns the number of dimensions of a template's `lower` and `upper` inputs
+///
+/// Returns `None` if the template doesn't have matching vector bounds.
+pub(super) fn bounds_dims(
+    template: &HashMap<String, ShapeInput>,
+) -> Option<usize> {
+    let dims = |name: &str| {
+        let ty = template.get(name)?.ty?;
+        if ty == Vec3::SHAPE.id {
+            Some(3)
+        } else if ty == Vec2::SHAPE.id {
+            Some(2)
+        } else {
+            None
+        }
+    };
+    let n = dims("lower")?;
+    (dims("upper")? == n).then_some(n)
+}
+
+/// Formats a value's bounds as literals for `lower` and `upper` inputs
+///
+/// The value may be a tree, drawable, or scene.  Bounds are padded so that the
+/// surface isn't clipped, then rounded outwards.  Returns `None` if the value
+/// is empty or unbounded along any of the first `n` axes.
+///
+/// Finding bounds can be slow, so this should be called on a worker thread.
+pub(super) fn bounds_literals(
+    value: &rhai::Dynamic,
+    n: usize,
+) -> Option<(String, String)> {
+    let (lower, upper) = super::query::value_bounds(value)?;
+    let lower = [lower.x, lower.y, lower.z];
+    let upper = [upper.x, upper.y, upper.z];
+    if !lower[..n].iter().chain(&upper[..n]).all(|v| v.is_finite()) {
+        return None;
+    }
+    let size = (0..n).map(|i| upper[i] - lower[i]).fold(0.0, f64::max);
+    let pad = (size * BOUNDS_PADDING).max(1e-3);
+
+    // Round to a precision which is relative to the padding, so that the
+    // literals stay readable
+    let digits = -pad.log10().floor();
+    let step = 10f64.powf(-digits);
+    let prec = digits.max(0.0) as usize;
+    let literal = |v: Vec<f64>| {
+        let v = v.iter().map(|v| format!("{v:.prec$}")).collect::<Vec<_>>();
+        format!("[{}]", v.join(", "))
+    };
+    let lo = (0..n).map(|i| ((lower[i] - pad) / step).floor() * step);
+    let hi = (0..n).map(|i| ((upper[i] + pad) / step).ceil() * step);
+    Some((literal(lo.collect()), literal(hi.collect())))
+}
+
 #[derive(Copy, Clone, PartialEq)]
 pub enum ShapeCategory {
     Halfspace,
@@ -176,6 +287,10 @@ pub enum ShapeKind {
         /// Input to populate when building this shape as a block
         input: String,
     },
+    Note {
+        /// Markdown text to populate when building this shape as a block
+        text: String,
+    },
 }
 
 impl ShapeVisitor for Visitor {
@@ -201,6 +316,7 @@ impl ShapeVisitor for Visitor {
             "// auto-generated script for fidget::shapes::{shape_name}\n"
         );
         let mut inputs: HashMap<String, _> = HashMap::new();
+        let mut fields = vec![];
         for f in s.fields {
             let field_name = f.name;
             let std::collections::hash_map::Entry::Vacant(i) =
@@ -210,6 +326,8 @@ impl ShapeVisitor for Visitor {
             };
 
             i.insert(get_input_field(f));
+            let doc = f.doc.iter().map(|d| d.trim()).collect::<Vec<_>>();
+            fields.push((field_name.to_owned(), doc.join(" ")));
             script += "\n";
             for line in f.doc {
                 script += &format!("// {line}\n");
@@ -246,6 +364,16 @@ impl ShapeVisitor for Visitor {
             kind: ShapeKind::Script { script, inputs },
             category: ShapeCategory::Fidget,
         });
+        self.lib.constructors.push(ShapeConstructor {
+            name: shape_name.to_snake_case(),
+            doc: T::SHAPE
+                .doc
+                .iter()
+                .map(|d| d.trim())
+                .collect::<Vec<_>>()
+                .join(" "),
+            fields,
+        });
     }
 }
 
@@ -372,4 +500,41 @@ mod test {
         assert_eq!(scale.ty, Some(Vec3::SHAPE.id));
         assert_eq!(scale.text, "[1, 1, 1]");
     }
+
+    #[test]
+    fn export_bounds() {
+        use fidget::context::Tree;
+        let s = ShapeLibrary::build();
+        let export = s.shapes.iter().find(|s| s.name == "Export (mesh)");
+        let ShapeKind::Script {
+            inputs: template, ..
+        } = &export.unwrap().kind
+        else {
+            panic!()
+        };
+        let n = bounds_dims(template).unwrap();
+        assert_eq!(n, 3);
+
+        // Empty shapes have no bounds
+        let empty = Tree::x().square() + 1.0;
+        assert!(bounds_literals(&rhai::Dynamic::from(empty), n).is_none());
+
+        let (x, y, z) = (Tree::x(), Tree::y(), Tree::z());
+        let sphere = (x.square() + y.square() + z.square()).sqrt() - 2.0;
+        let (lower, upper) =
+            bounds_literals(&rhai::Dynamic::from(sphere), n).unwrap();
+        let parse = |s: &str| {
+            s.trim_matches(['[', ']'])
+                .split(", ")
+                .map(|v| v.parse::<f64>().unwrap())
+                .collect::<Vec<_>>()
+        };
+        let lower = parse(&lower);
+        let upper = parse(&upper);
+        assert_eq!(lower.len(), 3);
+        for (lo, hi) in lower.iter().zip(&upper) {
+            assert!(*lo <= -2.2 && *lo > -2.5, "{lo}");
+            assert!(*hi >= 2.2 && *hi < 2.5, "{hi}");
+        }
+    }
 }